@@ -1,12 +1,16 @@
 //! PBot Library
 //!
 //! It includes the PBot modules, PBot Telegram clients encapsulation,
-//! and the utils such as [`getenv`].
+//! the storage, and the utils such as [`getenv`].
 
 #![warn(missing_docs)]
 pub mod modules;
+pub mod storage;
 pub mod telegram;
 pub mod utils;
 
 /// The path to store the Telegram session.
 pub const SESSION_PATH: &str = "./.telegram.session.dat";
+
+/// The path to store the states of PBot.
+pub const STORAGE_PATH: &str = "./.pbot.storage.json";
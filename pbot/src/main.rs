@@ -1,50 +1,317 @@
 use actix::prelude::*;
 
 use dotenv::dotenv;
-use log::info;
+use log::{info, warn};
 use pbot::telegram::client::commands::SaveSessionToFileCommand;
 use simple_logger::SimpleLogger;
 
 use pbot::getenv;
-use pbot::SESSION_PATH;
+use pbot::{SESSION_PATH, STORAGE_PATH};
 
 use std::sync::Arc;
 
+use pbot::modules::base::response::{ResponseOptions, SelfDestructConfig};
+use pbot::storage::StorageActor;
 use pbot::telegram::{
+    cleanup::CleanupActor,
     client::{
-        commands::{LoginCommand, NextUpdatesCommand},
+        commands::{ConnectReplayCommand, LoginCommand, NextUpdatesCommand},
         ClientActor,
     },
+    recorder::{
+        commands::RecordUpdateCommand, read_recording, RecordedUpdate, RecorderActor, Redaction,
+    },
+    replay,
     update::{ClientModuleExecutor, ClientModuleMessage},
     user::LoginConfig,
 };
 
-#[cfg(feature = "fwdmod")]
-async fn activate_fwd_mod(client: &Addr<ClientActor>) -> pbot::modules::base::ActivatedModuleInfo {
-    use pbot::modules::{base::ModuleActivator, fwd::FwdModuleActor};
+/// How often AfkModule auto-replies in the same chat by default.
+#[cfg(feature = "afkmod")]
+const DEFAULT_AFK_REPLY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// How long a filter of FiltersModule cools down by default.
+#[cfg(feature = "filtersmod")]
+const DEFAULT_FILTERS_COOLDOWN: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// The flag to log the mutating requests instead of sending them.
+const DRY_RUN_FLAG: &str = "--dry-run";
+
+/// Replay the recorded updates through the executor.
+///
+/// See [`pbot::telegram::replay`] for the details.
+async fn replay_updates(
+    client: &Addr<ClientActor>,
+    executor: &Addr<ClientModuleExecutor>,
+    recording: Vec<RecordedUpdate>,
+) {
+    use pbot::telegram::client::commands::GetMessagesCommand;
+
+    let summary = replay::replay(
+        recording,
+        |chat, message_id| async move {
+            let mut messages = client
+                .send(GetMessagesCommand {
+                    chat,
+                    message_ids: vec![message_id],
+                })
+                .await??;
+
+            Ok(messages.pop().flatten())
+        },
+        // The executor resolves once the modules have handled the update.
+        |message| async move {
+            let update = grammers_client::Update::NewMessage(message);
+            executor.send(ClientModuleMessage { update }).await?
+        },
+    )
+    .await;
+    info!(
+        "Replayed {} updates, and skipped {}.",
+        summary.replayed,
+        summary.skipped.len()
+    );
+}
+
+/// Resolve the chat by its ID.
+#[cfg(any(feature = "fwdmod", feature = "autofwdmod"))]
+async fn resolve_chat(client: &Addr<ClientActor>, id: i32) -> grammers_client::types::Chat {
     use pbot::telegram::client::commands::{ResolveChatCommand, UnpackChatCommand};
 
-    // Resolve the chat ID from the environment variable `TG_FWD_TO`.
-    //
     // For details, see the implementation of Handler<ResolveChatCommand> in ClientActor.
     let pack_chat = client
-        .send(ResolveChatCommand(getenv!("TG_FWD_TO", i32)))
+        .send(ResolveChatCommand(id))
         .await
         .unwrap()
-        .expect("failed to get the chat forward to");
+        .unwrap_or_else(|e| panic!("failed to get the chat {}: {:?}", id, e));
 
     // Unpack the Chat object from the PackedChat.
     //
     // For details, see the implementation of Handler<UnpackChatCommand> in ClientActor.
-    let fwd_chat = client
+    client
         .send(UnpackChatCommand(pack_chat))
         .await
         .unwrap()
-        .expect("failed to unpack the chat");
+        .expect("failed to unpack the chat")
+}
+
+#[cfg(feature = "fwdmod")]
+async fn activate_fwd_mod(
+    client: &Addr<ClientActor>,
+    storage: &Addr<StorageActor>,
+) -> pbot::modules::base::ActivatedModuleInfo {
+    use pbot::modules::{
+        base::ModuleActivator,
+        fwd::{
+            records::ForwardRecords, FwdModuleActor, FwdTargets, DEFAULT_ATTRIBUTION,
+            RESERVED_NAMES,
+        },
+    };
+
+    // Resolve the default chat from the environment variable `TG_FWD_TO`.
+    let default = Arc::new(resolve_chat(client, getenv!("TG_FWD_TO", i32)).await);
+
+    // Resolve the named chats from the environment variable `TG_FWD_TARGETS`.
+    let mut named = std::collections::BTreeMap::new();
+    for (name, id) in pbot::utils::getenv_pairs("TG_FWD_TARGETS") {
+        assert!(
+            !RESERVED_NAMES.contains(&name.as_str()),
+            "`{}` is reserved and can't be a name in TG_FWD_TARGETS",
+            name
+        );
+        let id = id
+            .parse()
+            .expect("TG_FWD_TARGETS should have chat IDs in i32");
+        named.insert(name, Arc::new(resolve_chat(client, id).await));
+    }
 
-    // We initiate the FwdModule with the Chat object.
+    // Load the records of the forwarded messages.
+    let records = ForwardRecords::load(storage.clone())
+        .await
+        .expect("failed to load the forward records");
+
+    // We initiate the FwdModule with the Chat objects.
     FwdModuleActor {
-        target: Arc::new(fwd_chat),
+        targets: Arc::new(FwdTargets { default, named }),
+        records: Arc::new(records),
+        // The attribution footer is disabled if `TG_FWD_ATTRIBUTION` is empty,
+        // and `\n` in it stands for a line break.
+        attribution: Some(
+            pbot::getenv_opt!("TG_FWD_ATTRIBUTION")
+                .map(|attribution| attribution.replace("\\n", "\n"))
+                .unwrap_or_else(|| DEFAULT_ATTRIBUTION.to_string()),
+        )
+        .filter(|attribution| !attribution.is_empty()),
+        dedup_content: pbot::getenv_opt!("TG_FWD_DEDUP_CONTENT", bool).unwrap_or(false),
+    }
+    .activate_module()
+}
+
+#[cfg(feature = "autofwdmod")]
+async fn activate_autofwd_mod(
+    client: &Addr<ClientActor>,
+) -> Option<pbot::modules::base::ActivatedModuleInfo> {
+    use pbot::modules::{
+        autofwd::{
+            rules::{load_rules, Rule},
+            AutoFwdModuleActor,
+        },
+        base::ModuleActivator,
+    };
+
+    // Load the rules from the file specified in `TG_AUTOFWD_RULES`,
+    // and resolve the targets of them.
+    let path = match pbot::getenv_opt!("TG_AUTOFWD_RULES") {
+        Some(path) => path,
+        None => {
+            warn!("TG_AUTOFWD_RULES is not specified; AutoFwdModule is disabled.");
+            return None;
+        }
+    };
+    let mut rules = Vec::new();
+    for config in load_rules(path).expect("failed to load the rules") {
+        let target = resolve_chat(client, config.target).await;
+        let name = config.name.clone();
+
+        rules.push(
+            Rule::compile(config, target)
+                .unwrap_or_else(|e| panic!("failed to compile the rule `{}`: {:?}", name, e)),
+        );
+    }
+
+    Some(
+        AutoFwdModuleActor {
+            rules: Arc::new(rules),
+            dry_run: pbot::getenv_opt!("TG_AUTOFWD_DRY_RUN", bool).unwrap_or(false),
+        }
+        .activate_module(),
+    )
+}
+
+#[cfg(feature = "addrankmod")]
+async fn activate_addrank_mod(
+    client: &Addr<ClientActor>,
+    storage: &Addr<StorageActor>,
+) -> pbot::modules::base::ActivatedModuleInfo {
+    use pbot::modules::{
+        addrank::{
+            expiry::RankExpiryActor, grants::RankGrants, presets::RankPresets,
+            snapshots::RankSnapshots, AddRankModuleActor,
+        },
+        base::ModuleActivator,
+    };
+
+    // Load the administrator status granted by AddRankModule.
+    let grants = RankGrants::load(storage.clone())
+        .await
+        .expect("failed to load the admin grants");
+    let grants = Arc::new(grants);
+    let snapshots = RankSnapshots::load(storage.clone())
+        .await
+        .expect("failed to load the rank snapshots");
+
+    // Start the actor restoring the temporary ranks.
+    let expiry = RankExpiryActor::new(client.clone(), storage.clone(), grants.clone()).start();
+
+    AddRankModuleActor {
+        grants,
+        snapshots: Arc::new(snapshots),
+        expiry,
+        // Read the presets for `--preset` from `TG_ADDRANK_PRESETS`.
+        presets: Arc::new(RankPresets::from_env()),
+    }
+    .activate_module()
+}
+
+#[cfg(feature = "afkmod")]
+async fn activate_afk_mod(
+    storage: &Addr<StorageActor>,
+) -> pbot::modules::base::ActivatedModuleInfo {
+    use pbot::modules::{
+        afk::{status::AfkState, AfkModuleActor},
+        base::ModuleActivator,
+    };
+
+    // Load the AFK status, so being away survives restarts.
+    let state = AfkState::load(storage.clone())
+        .await
+        .expect("failed to load the AFK status");
+
+    AfkModuleActor {
+        state: Arc::new(state),
+        interval: pbot::getenv_opt!("TG_AFK_REPLY_INTERVAL")
+            .map(|interval| {
+                pbot::utils::parse_duration(&interval)
+                    .expect("TG_AFK_REPLY_INTERVAL should be a duration, such as 1h")
+            })
+            .unwrap_or(DEFAULT_AFK_REPLY_INTERVAL),
+    }
+    .activate_module()
+}
+
+#[cfg(feature = "notesmod")]
+async fn activate_notes_mod(
+    storage: &Addr<StorageActor>,
+) -> pbot::modules::base::ActivatedModuleInfo {
+    use pbot::modules::{
+        base::ModuleActivator,
+        notes::{store::NoteStore, NotesModuleActor},
+    };
+
+    // Load the saved notes.
+    let notes = NoteStore::load(storage.clone())
+        .await
+        .expect("failed to load the notes");
+
+    NotesModuleActor {
+        notes: Arc::new(notes),
+    }
+    .activate_module()
+}
+
+#[cfg(feature = "filtersmod")]
+async fn activate_filters_mod(
+    storage: &Addr<StorageActor>,
+) -> pbot::modules::base::ActivatedModuleInfo {
+    use pbot::modules::{
+        base::ModuleActivator,
+        filters::{store::FilterStore, FiltersModuleActor},
+    };
+
+    // Load the filters and the chats where they are enabled.
+    let filters = FilterStore::load(storage.clone())
+        .await
+        .expect("failed to load the filters");
+
+    FiltersModuleActor {
+        filters: Arc::new(filters),
+        cooldown: pbot::getenv_opt!("TG_FILTERS_COOLDOWN")
+            .map(|cooldown| {
+                pbot::utils::parse_duration(&cooldown)
+                    .expect("TG_FILTERS_COOLDOWN should be a duration, such as 1m")
+            })
+            .unwrap_or(DEFAULT_FILTERS_COOLDOWN),
+    }
+    .activate_module()
+}
+
+#[cfg(feature = "remindersmod")]
+fn activate_reminders_mod(
+    client: &Addr<ClientActor>,
+    storage: &Addr<StorageActor>,
+) -> pbot::modules::base::ActivatedModuleInfo {
+    use pbot::modules::{
+        base::ModuleActivator,
+        reminders::{scheduler::ReminderActor, RemindersModuleActor},
+    };
+
+    // Start the actor delivering the reminders.
+    let reminders = ReminderActor::new(client.clone(), storage.clone()).start();
+
+    RemindersModuleActor {
+        reminders,
+        // Read the time zone of the owner from `TG_TIMEZONE`.
+        timezone: pbot::getenv_opt!("TG_TIMEZONE", chrono_tz::Tz).unwrap_or(chrono_tz::UTC),
     }
     .activate_module()
 }
@@ -60,18 +327,52 @@ async fn main() {
         .expect("failed to configure logger");
     dotenv().expect("a .env file should be existed in the current working directory");
 
+    // `--dry-run` logs the mutating requests instead of sending them to Telegram.
+    // Replaying always runs in the dry-run mode, so it never acts on the real chats.
+    let (flags, args): (Vec<_>, Vec<_>) = std::env::args().partition(|arg| arg == DRY_RUN_FLAG);
+    let replaying = args.get(1).map(String::as_str) == Some("replay");
+    let dry_run = !flags.is_empty() || replaying;
+
     /* Phase II: Start Telegram Client */
     info!("Starting Telegram client...");
-    let client = ClientActor::default().start();
-    client
-        .send(LoginCommand(LoginConfig {
-            api_id: getenv!("TG_ID", usize),
-            api_hash: getenv!("TG_HASH"),
-            mobile_number: getenv!("TG_MOBILE_NUMBER"),
-            session_path: SESSION_PATH,
-        }))
-        .await
-        .expect("failed to login");
+    if dry_run {
+        warn!("Dry-run mode: the mutating requests are only logged instead of being sent to Telegram.");
+    }
+    let client = ClientActor::new(dry_run).start();
+    // `pbot replay <file>` connects to a server answering from the recording
+    // instead of logging in, so replaying never talks to Telegram.
+    let recording = if replaying {
+        let path = args.get(2).expect("usage: pbot replay <file>");
+        let recording = read_recording(path).expect("failed to read the recording");
+        let addr = replay::server::serve(&recording)
+            .await
+            .expect("failed to start the replay server");
+        client
+            .send(ConnectReplayCommand(addr))
+            .await
+            .expect("failed to connect to the replay server");
+
+        info!("Replaying {} updates from {}...", recording.len(), path);
+        Some(recording)
+    } else {
+        client
+            .send(LoginCommand(LoginConfig {
+                api_id: getenv!("TG_ID", usize),
+                api_hash: getenv!("TG_HASH"),
+                mobile_number: getenv!("TG_MOBILE_NUMBER"),
+                session_path: SESSION_PATH,
+            }))
+            .await
+            .expect("failed to login");
+        None
+    };
+
+    /* Phase II-1: Start the storage and the cleanup actor */
+    info!("Starting storage...");
+    let storage = StorageActor::open(STORAGE_PATH)
+        .expect("failed to open the storage")
+        .start();
+    let cleanup = CleanupActor::new(client.clone(), storage.clone()).start();
 
     /* Phase III: Initiate Modules */
     info!("Initiating modules...");
@@ -81,7 +382,7 @@ async fn main() {
     #[cfg(feature = "fwdmod")]
     {
         info!("  → Enabled: FwdModule");
-        modules.push(activate_fwd_mod(&client).await);
+        modules.push(activate_fwd_mod(&client, &storage).await);
     }
     // Initiate GetInfoModule
     #[cfg(feature = "getinfomod")]
@@ -93,8 +394,38 @@ async fn main() {
     #[cfg(feature = "addrankmod")]
     {
         info!("  → Enabled: AddRankModule");
+        modules.push(activate_addrank_mod(&client, &storage).await);
+    }
+    #[cfg(feature = "autofwdmod")]
+    {
+        info!("  → Enabled: AutoFwdModule");
+        modules.extend(activate_autofwd_mod(&client).await);
+    }
+    #[cfg(feature = "afkmod")]
+    {
+        info!("  → Enabled: AfkModule");
+        modules.push(activate_afk_mod(&storage).await);
+    }
+    #[cfg(feature = "notesmod")]
+    {
+        info!("  → Enabled: NotesModule");
+        modules.push(activate_notes_mod(&storage).await);
+    }
+    #[cfg(feature = "filtersmod")]
+    {
+        info!("  → Enabled: FiltersModule");
+        modules.push(activate_filters_mod(&storage).await);
+    }
+    #[cfg(feature = "purgemod")]
+    {
+        info!("  → Enabled: PurgeModule");
         use pbot::modules::base::ModuleActivator;
-        modules.push(pbot::modules::addrank::AddRankModuleActor.activate_module());
+        modules.push(pbot::modules::purge::PurgeModuleActor.activate_module());
+    }
+    #[cfg(feature = "remindersmod")]
+    {
+        info!("  → Enabled: RemindersModule");
+        modules.push(activate_reminders_mod(&client, &storage));
     }
 
     /* Phase IV: Initiate ClientModuleExecutor */
@@ -102,25 +433,64 @@ async fn main() {
     let executor = ClientModuleExecutor {
         client: client.clone(),
         modules: Arc::new(modules),
+        response_options: Arc::new(ResponseOptions {
+            cleanup: Some(cleanup),
+            self_destruct: SelfDestructConfig::from_env(),
+            errors_to_saved_messages: pbot::getenv_opt!("TG_ERRORS_TO_SAVED_MESSAGES", bool)
+                .unwrap_or(false),
+            error_details: pbot::getenv_opt!("TG_ERROR_DETAILS", bool).unwrap_or(false),
+        }),
     }
     .start();
 
-    /* Phase V: Polling updates */
-    info!("Polling updates...");
-    while let Some(updates) = tokio::select! {
-        _ = tokio::signal::ctrl_c() => Ok(Ok(None)),
-        result = client.send(NextUpdatesCommand) => result,
-    }
-    .unwrap()
-    .expect("failed to retrieve updates")
-    {
-        for update in updates {
-            // Send request to ClientModuleExecutor, let it distribute Update to modules.
-            tokio::spawn(executor.send(ClientModuleMessage { update }));
+    /* Phase V: Polling or replaying updates */
+    // `pbot replay <file>` replays the recorded updates instead of polling.
+    match (recording, args.get(1).map(String::as_str)) {
+        (Some(recording), _) => {
+            replay_updates(&client, &executor, recording).await;
+        }
+        (None, Some(command)) => panic!(
+            "unknown command `{}`; usage: pbot [--dry-run] [replay <file>]",
+            command
+        ),
+        (None, None) => {
+            // Record the updates into `TG_RECORD_UPDATES` if specified.
+            let recorder = pbot::getenv_opt!("TG_RECORD_UPDATES").map(|path| {
+                info!("Recording updates into {}...", path);
+                RecorderActor::open(path)
+                    .expect("failed to open the recording")
+                    .start()
+            });
+            let redaction = Redaction::from_env();
+
+            info!("Polling updates...");
+            while let Some(updates) = tokio::select! {
+                _ = tokio::signal::ctrl_c() => Ok(Ok(None)),
+                result = client.send(NextUpdatesCommand) => result,
+            }
+            .unwrap()
+            .expect("failed to retrieve updates")
+            {
+                for update in updates {
+                    if let Some(recorder) = &recorder {
+                        if let Some(record) = RecordedUpdate::new(&update, redaction) {
+                            recorder.do_send(RecordUpdateCommand(record));
+                        }
+                    }
+
+                    // Send request to ClientModuleExecutor, let it distribute Update to modules.
+                    tokio::spawn(executor.send(ClientModuleMessage { update }));
+                }
+            }
         }
     }
 
     /* Phase VI: Save session to file */
+    // The session of the replay server must not replace the real one.
+    if replaying {
+        info!("Exiting...");
+        return;
+    }
     info!("Saving session file and exiting...");
     client
         .send(SaveSessionToFileCommand(SESSION_PATH))
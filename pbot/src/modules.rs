@@ -2,8 +2,20 @@
 
 #[cfg(feature = "addrankmod")]
 pub mod addrank;
+#[cfg(feature = "afkmod")]
+pub mod afk;
+#[cfg(feature = "autofwdmod")]
+pub mod autofwd;
 pub mod base;
+#[cfg(feature = "filtersmod")]
+pub mod filters;
 #[cfg(feature = "fwdmod")]
 pub mod fwd;
 #[cfg(feature = "getinfomod")]
 pub mod getinfo;
+#[cfg(feature = "notesmod")]
+pub mod notes;
+#[cfg(feature = "purgemod")]
+pub mod purge;
+#[cfg(feature = "remindersmod")]
+pub mod reminders;
@@ -2,140 +2,1048 @@
 //!
 //! You can add rank for every member you administrated
 //! without giving the actual permission.
+//!
+//! Reply to the member with `!addrank <rank>`, or specify the members
+//! with their usernames, IDs or mentions, such as
+//! `!addrank @user1 @user2 "Senior Dev"`. The IDs are only taken
+//! when not replying, so `!addrank 2024` sets the rank `2024` on
+//! the member replied to.
+//!
+//! The administrator status granted for the rank is recorded,
+//! so `!rmrank` revokes the whole status if PBot granted it,
+//! and only clears the title otherwise.
+//!
+//! `!ranks` lists the administrators in the chat with their titles
+//! and rights, `!ranks csv` exports them, and `!ranks diff` shows
+//! the changes since the last listing.
+//!
+//! With `!addrank --for 24h <rank>`, the rank is temporary: when it
+//! expires, the previous title is restored, or the administrator
+//! status granted by PBot is revoked. `!ranks pending` lists the
+//! pending expiries.
+//!
+//! With `!addrank --preset <name> <rank>`, the rights of the preset
+//! are applied along with the title. See [`presets`] for the presets.
+
+pub mod expiry;
+pub mod grants;
+pub mod presets;
+pub mod snapshots;
+
+use std::sync::Arc;
+use std::time::Duration;
 
 use actix::prelude::*;
-use grammers_client::InputMessage;
-use log::{debug, info};
+use chrono::{TimeZone, Utc};
+use grammers_client::types::{Chat, Message, User};
+use grammers_tl_types as tl;
+use log::{info, warn};
 use pbot_modules_derive::{ModuleActivator, ModuleActor, ModuleMeta};
 
-use crate::telegram::{client::commands::GetAdminRightsBuilderCommand, user::is_root_user};
+use crate::telegram::{
+    client::{
+        admins::{AdminInfo, AdminRights, MemberRef},
+        commands::{GetAdminsCommand, ResolveMemberCommand, SetAdminRightsCommand},
+    },
+    format::FormattedText,
+    user::is_root_user,
+};
+use crate::utils::{format_duration, parse_duration};
 
-use super::base::ModuleMessage;
+use self::expiry::{
+    commands::{CancelExpiryCommand, ListExpiriesCommand, ScheduleExpiryCommand},
+    RankExpiry, RankExpiryActor,
+};
+use self::grants::{RankGrant, RankGrants};
+use self::presets::RankPresets;
+use self::snapshots::{diff, RankChange, RankSnapshots};
+
+use super::base::{
+    error::{ModuleError, ModuleResult},
+    ModuleMessage,
+};
 
 const CMD_PREFIX: &str = "!addrank";
 
+/// The usage of `!addrank`.
+const USAGE: &str = "!addrank [@使用者|ID…] [--for <期限>] [--preset <角色>] <頭銜>";
+
+/// The flag to set the rank temporarily, such as `!addrank --for 24h`.
+const FOR_FLAG: &str = "--for";
+
+/// The flag to apply the rights of a preset, such as `!addrank --preset mod`.
+const PRESET_FLAG: &str = "--preset";
+
+/// The maximum length of the rank, in characters.
+const MAX_RANK_LENGTH: usize = 16;
+
+/// The command to remove the rank, such as `!rmrank`.
+const RMRANK_CMD: &str = "!rmrank";
+
+/// The command to list the ranks, such as `!ranks diff`.
+const RANKS_CMD: &str = "!ranks";
+
+/// The subcommand to export the ranks as CSV.
+const CSV_SUBCOMMAND: &str = "csv";
+
+/// The subcommand to show the changes since the last snapshot.
+const DIFF_SUBCOMMAND: &str = "diff";
+
+/// The subcommand to list the pending expiries of the temporary ranks.
+const PENDING_SUBCOMMAND: &str = "pending";
+
 /// The AddRank actor.
-#[derive(Clone, Default, ModuleActor, ModuleActivator, ModuleMeta)]
+#[derive(Clone, ModuleActor, ModuleActivator, ModuleMeta)]
 #[name = "AddRankModule"]
-pub struct AddRankModuleActor;
+pub struct AddRankModuleActor {
+    /// The administrator status granted by this module.
+    pub grants: Arc<RankGrants>,
+    /// The latest snapshots of the administrators.
+    pub snapshots: Arc<RankSnapshots>,
+    /// The actor restoring the temporary ranks.
+    pub expiry: Addr<RankExpiryActor>,
+    /// The rights sets for `--preset`.
+    pub presets: Arc<RankPresets>,
+}
 
 impl Handler<ModuleMessage> for AddRankModuleActor {
-    type Result = ResponseActFuture<Self, anyhow::Result<()>>;
+    type Result = ResponseActFuture<Self, ModuleResult>;
 
     fn handle(&mut self, msg: ModuleMessage, _: &mut Self::Context) -> Self::Result {
-        // https://github.com/actix/actix/issues/308
-        // DEVEDIT: You can clone the variables from self here to workaround this error.
+        // Clone the fields of self to move into the following block.
+        let grants = self.grants.clone();
+        let snapshots = self.snapshots.clone();
+        let expiry = self.expiry.clone();
+        let presets = self.presets.clone();
 
         async move {
-            // Destruct msg and get `handle` and `message`.
-            let ModuleMessage { handle, message } = msg;
-
-            // Extract the rank to set from the command message.
-            let rank = match extract_rank(&*message.read().await) {
-                // Return rank if the rank extracted successfully.
-                Some(rank) => rank,
-                // Otherwise, we return early.
+            // Take a snapshot of the message, so we don't need to lock it again.
+            let mut message = msg.snapshot().await;
+
+            if extract_args(&message, CMD_PREFIX).is_some() {
+                handle_addrank(&msg, &mut message, &grants, &expiry, &presets).await
+            } else if extract_args(&message, RMRANK_CMD).is_some() {
+                handle_rmrank(&msg, &mut message, &grants, &expiry).await
+            } else if let Some(args) = extract_args(&message, RANKS_CMD) {
+                handle_ranks(&msg, &message, &args, &grants, &snapshots, &expiry).await
+            } else {
+                Ok(())
+            }
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Set the rank of the members with `!addrank [@user|ID…] [--for <duration>] <rank>`.
+///
+/// Without any member specified, the user replied to is the target.
+/// With `--for`, the rank expires after the duration.
+/// With `--preset`, the rights of the preset are applied.
+async fn handle_addrank(
+    msg: &ModuleMessage,
+    message: &mut Message,
+    grants: &RankGrants,
+    expiry: &Addr<RankExpiryActor>,
+    presets: &RankPresets,
+) -> ModuleResult {
+    let AddRankArgs {
+        members,
+        rank,
+        duration,
+        preset,
+    } = parse_args(message)?;
+
+    // Check the rank, the expiry and the preset before asking Telegram.
+    validate_rank(&rank)?;
+    let due = duration
+        .map(|duration| expiry_due(Utc::now().timestamp(), duration))
+        .transpose()?;
+    let rights = preset
+        .as_deref()
+        .map(|name| presets.get(name))
+        .transpose()?;
+
+    let chat = message.chat();
+
+    // Check if this message is replying to a message,
+    // and the message has a sender.
+    if members.is_empty() {
+        let user_replied_to = match get_user_replied_to(message).await? {
+            Some(user_replied_to) => user_replied_to,
+            None => return Err(ModuleError::usage_with("請回覆訊息或指定成員。", USAGE)),
+        };
+
+        // Get the full name of user repiled to.
+        //
+        // We get it before `set_rank` since it will own `user_replied_to`.
+        let repiled_user_name = user_replied_to.full_name();
+        let repiled_user_id = user_replied_to.id();
+
+        let outcome = set_rank(msg, &chat, user_replied_to, &rank, rights, grants).await?;
+        let changes = rights_changes(&outcome);
+        track_expiry(
+            expiry,
+            &chat,
+            repiled_user_id,
+            &repiled_user_name,
+            &rank,
+            due,
+            outcome,
+        );
+
+        // Notify user that the operation is succeed.
+        let text = FormattedText::new()
+            .push("✅ 成功將 ")
+            .mention(&repiled_user_name, repiled_user_id)
+            .push(" 的頭銜設定為 ")
+            .bold(&rank)
+            .push(expiry_note(duration))
+            .push("。");
+        msg.edit_or_reply(if changes.is_empty() {
+            text
+        } else {
+            text.push("\n權限變更：").append(changes)
+        })
+        .await?;
+
+        // It worked with no fault errors! 👌
+        return Ok(());
+    }
+
+    // Set the rank of every member, and summarize the results.
+    let mut summary = FormattedText::new()
+        .push("🏷️ 設定頭銜 ")
+        .bold(&rank)
+        .push(expiry_note(duration))
+        .push(" 的結果：");
+    for member in members {
+        let user = msg
+            .handle
+            .send(ResolveMemberCommand {
+                chat: chat.clone(),
+                member: member.clone(),
+            })
+            .await?
+            .map_err(|e| ModuleError::rpc("failed to resolve the member", e));
+
+        let (name, result) = match user {
+            Ok(Some(user)) => {
+                let (user_id, user_name) = (user.id(), user.full_name());
+                let result =
+                    set_rank(msg, &chat, user, &rank, rights, grants)
+                        .await
+                        .map(|outcome| {
+                            let changes = rights_changes(&outcome);
+                            track_expiry(expiry, &chat, user_id, &user_name, &rank, due, outcome);
+                            changes
+                        });
+
+                (FormattedText::new().mention(user_name, user_id), result)
+            }
+            Ok(None) => (
+                FormattedText::plain(member.to_string()),
+                Err(ModuleError::usage("找不到這位成員。")),
+            ),
+            Err(e) => (FormattedText::plain(member.to_string()), Err(e)),
+        };
+
+        summary = match result {
+            Ok(changes) => summary.push("\n✅ ").append(name).push(" ").append(changes),
+            Err(e) => {
+                warn!("Failed to set the rank of {}: {}", member, e);
+                summary
+                    .push("\n❌ ")
+                    .append(name)
+                    .push("：")
+                    .append(e.render(msg.options.error_details))
+            }
+        };
+    }
+
+    msg.edit_or_reply(summary).await?;
+
+    Ok(())
+}
+
+/// What [`set_rank`] has changed.
+struct SetRankOutcome {
+    /// The title before, if any.
+    previous_rank: Option<String>,
+    /// Whether the administrator status was granted.
+    granted: bool,
+    /// The rights before.
+    previous_rights: AdminRights,
+    /// The rights now.
+    rights: AdminRights,
+}
+
+/// Set the rank of the user, and record the grant if
+/// the user was not an administrator.
+///
+/// The rights are replaced if specified, otherwise
+/// `manage_call` is granted on top of the current rights.
+async fn set_rank(
+    msg: &ModuleMessage,
+    chat: &Chat,
+    user: User,
+    rank: &str,
+    rights: Option<AdminRights>,
+    grants: &RankGrants,
+) -> Result<SetRankOutcome, ModuleError> {
+    let user_id = user.id();
+
+    // Check if the user is an administrator already,
+    // so we know if we are granting the status.
+    let previous = find_admin(msg, chat, user_id).await?;
+    let was_admin = previous.is_some();
+    let previous_rights = previous
+        .as_ref()
+        .map(|admin| admin.rights)
+        .unwrap_or_default();
+    let rights = rights.unwrap_or(AdminRights {
+        manage_call: true,
+        ..previous_rights
+    });
+
+    // Set the rank and send the request to Telegram.
+    // The "Rank" is one of the administrator privileges.
+    msg.handle
+        .send(SetAdminRightsCommand {
+            channel: chat.clone(),
+            user,
+            rights: Some(rights),
+            rank: rank.to_string(),
+        })
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to set the rank", e))?;
+
+    // Record the grant, so `!rmrank` can revoke the status later.
+    if !was_admin {
+        grants
+            .insert(RankGrant {
+                chat_id: chat.id(),
+                user_id,
+            })
+            .await?;
+    }
+
+    Ok(SetRankOutcome {
+        previous_rank: previous.and_then(|admin| admin.rank),
+        granted: !was_admin,
+        previous_rights,
+        rights,
+    })
+}
+
+/// Render the changed rights, such as `+ban_users -pin_messages`.
+fn rights_changes(outcome: &SetRankOutcome) -> FormattedText {
+    let (granted, revoked) = outcome.rights.changes_from(&outcome.previous_rights);
+    let mut text = FormattedText::new();
+
+    for name in granted {
+        text = text.push(" +").code(name);
+    }
+    for name in revoked {
+        text = text.push(" -").code(name);
+    }
+    text
+}
+
+/// Schedule the expiry of the rank if it is temporary,
+/// otherwise cancel the pending expiry of the member.
+///
+/// `due` is when the rank expires, in UNIX timestamp.
+fn track_expiry(
+    expiry: &Addr<RankExpiryActor>,
+    chat: &Chat,
+    user_id: i32,
+    user_name: &str,
+    rank: &str,
+    due: Option<i64>,
+    outcome: SetRankOutcome,
+) {
+    match due {
+        Some(due) => expiry.do_send(ScheduleExpiryCommand(RankExpiry {
+            chat: chat.pack().to_bytes(),
+            chat_id: chat.id(),
+            user_id,
+            user_name: user_name.to_string(),
+            rank: rank.to_string(),
+            previous_rank: outcome.previous_rank,
+            previous_rights: Some(outcome.previous_rights),
+            revoke: outcome.granted,
+            due,
+        })),
+        None => expiry.do_send(CancelExpiryCommand {
+            chat_id: chat.id(),
+            user_id,
+        }),
+    }
+}
+
+/// Get when the rank lasting for the duration from `now` expires,
+/// both in UNIX timestamp.
+fn expiry_due(now: i64, duration: Duration) -> Result<i64, ModuleError> {
+    i64::try_from(duration.as_secs())
+        .ok()
+        .and_then(|secs| now.checked_add(secs))
+        .ok_or_else(|| ModuleError::usage_with("期限太長了。", USAGE))
+}
+
+/// Describe when the rank expires, such as `（24 小時後到期）`.
+fn expiry_note(duration: Option<Duration>) -> String {
+    match duration {
+        Some(duration) => format!("（{} 後到期）", format_duration(duration)),
+        None => String::new(),
+    }
+}
+
+/// Remove the rank of the user replied to with `!rmrank`.
+///
+/// If the administrator status was granted by this module,
+/// the whole status is revoked. Otherwise, only the title is cleared.
+async fn handle_rmrank(
+    msg: &ModuleMessage,
+    message: &mut Message,
+    grants: &RankGrants,
+    expiry: &Addr<RankExpiryActor>,
+) -> ModuleResult {
+    let user_replied_to = match get_user_replied_to(message).await? {
+        Some(user_replied_to) => user_replied_to,
+        None => {
+            return Err(ModuleError::usage_with(
+                "請回覆訊息。",
+                format!("回覆要移除頭銜的成員並輸入 {}", RMRANK_CMD),
+            ))
+        }
+    };
+
+    let repiled_user_name = user_replied_to.full_name();
+    let repiled_user_id = user_replied_to.id();
+    let chat = message.chat();
+    let grant = grants.find(chat.id(), repiled_user_id).await;
+
+    // The rank is removed now, so there is nothing to restore later.
+    expiry.do_send(CancelExpiryCommand {
+        chat_id: chat.id(),
+        user_id: repiled_user_id,
+    });
+
+    // Revoke every right if PBot granted the admin status, or keep them otherwise.
+    msg.handle
+        .send(SetAdminRightsCommand {
+            channel: chat,
+            user: user_replied_to,
+            rights: grant.as_ref().map(|_| AdminRights::default()),
+            rank: String::new(),
+        })
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to remove the rank", e))?;
+
+    let result = match &grant {
+        Some(grant) => {
+            grants.remove(grant).await?;
+            " 的頭銜，並撤銷由 PBot 授予的管理員身分。"
+        }
+        None => " 的頭銜。",
+    };
+
+    msg.edit_or_reply(
+        FormattedText::new()
+            .push("✅ 成功移除 ")
+            .mention(&repiled_user_name, repiled_user_id)
+            .push(result),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// List the administrators with `!ranks [csv|diff|pending]`.
+///
+/// Every listing of the administrators replaces the snapshot of the chat.
+async fn handle_ranks(
+    msg: &ModuleMessage,
+    message: &Message,
+    args: &[String],
+    grants: &RankGrants,
+    snapshots: &RankSnapshots,
+    expiry: &Addr<RankExpiryActor>,
+) -> ModuleResult {
+    let subcommand = args.first().map(String::as_str);
+    let chat = message.chat();
+
+    match subcommand {
+        None | Some(CSV_SUBCOMMAND) | Some(DIFF_SUBCOMMAND) => {}
+        Some(PENDING_SUBCOMMAND) => {
+            let expiries = expiry
+                .send(ListExpiriesCommand { chat_id: chat.id() })
+                .await?;
+            if expiries.is_empty() {
+                msg.edit_or_reply("⏳ 沒有待到期的頭銜。").await?;
+                return Ok(());
+            }
+
+            let mut text =
+                FormattedText::plain(format!("⏳ 有 {} 個待到期的頭銜：", expiries.len()));
+            for expiry in expiries {
+                let due = Utc
+                    .timestamp_opt(expiry.due, 0)
+                    .single()
+                    .map(|due| due.format("%Y-%m-%d %H:%M UTC").to_string())
+                    .unwrap_or_default();
+                let then = match (&expiry.previous_rank, expiry.revoke) {
+                    (_, true) => "撤銷管理員身分".to_string(),
+                    (Some(previous_rank), _) => format!("恢復為「{}」", previous_rank),
+                    (None, _) => "移除頭銜".to_string(),
+                };
+
+                text = text
+                    .push("\n• ")
+                    .mention(&expiry.user_name, expiry.user_id)
+                    .push("｜")
+                    .bold(&expiry.rank)
+                    .push(format!("｜{} 到期後{}", due, then));
+            }
+
+            msg.edit_or_reply(text).await?;
+            return Ok(());
+        }
+        Some(_) => {
+            return Err(ModuleError::usage_with(
+                "未知的子命令。",
+                format!(
+                    "{} [{}|{}|{}]",
+                    RANKS_CMD, CSV_SUBCOMMAND, DIFF_SUBCOMMAND, PENDING_SUBCOMMAND
+                ),
+            ));
+        }
+    }
+
+    let admins = msg
+        .handle
+        .send(GetAdminsCommand { chat: chat.clone() })
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to get the administrators", e))?;
+
+    // Check which administrators are granted by PBot.
+    let mut granted = Vec::new();
+    for admin in admins.iter() {
+        granted.push(grants.find(chat.id(), admin.user_id).await.is_some());
+    }
+
+    let previous = snapshots.replace(chat.id(), admins.clone()).await?;
+
+    match subcommand {
+        Some(CSV_SUBCOMMAND) => {
+            let mut content = String::from("user_id,name,is_creator,rank,rights,granted_by_pbot\n");
+            for (admin, granted) in admins.iter().zip(granted) {
+                content.push_str(&format!(
+                    "{},{},{},{},{},{}\n",
+                    admin.user_id,
+                    csv_field(&admin.name),
+                    admin.is_creator,
+                    csv_field(admin.rank.as_deref().unwrap_or_default()),
+                    admin.rights.granted().join(";"),
+                    granted
+                ));
+            }
+
+            msg.reply_file(
+                format!("pbot-ranks-{}.csv", chat.id()),
+                content.into_bytes(),
+                format!("📤 已匯出 {} 位管理員的頭銜。", admins.len()),
+            )
+            .await?;
+        }
+        Some(DIFF_SUBCOMMAND) => {
+            let previous = match previous {
+                Some(previous) => previous,
                 None => {
-                    debug!("Failed to extract rank.");
+                    msg.edit_or_reply("📸 這是第一份快照，下次即可比較差異。")
+                        .await?;
                     return Ok(());
                 }
             };
 
-            // Check if this message is replying to a message,
-            // and the message has a sender.
-            //
-            // FOR DEVS: don't merge the following two lines,
-            // otherwise it will deadlock the thread.
-            let user_replied_to = get_user_replied_to(&mut *message.write().await).await?;
-            let user_replied_to = match user_replied_to {
-                Some(user_replied_to) => user_replied_to,
-                None => {
-                    message
-                        .write()
-                        .await
-                        .edit(InputMessage::text("[PBOT] ⚠️ 請回覆訊息。"))
-                        .await?;
+            let changes = diff(&previous, &admins);
+            if changes.is_empty() {
+                msg.edit_or_reply("✅ 自上次快照以來沒有變更。").await?;
+                return Ok(());
+            }
 
-                    return Ok(());
+            let mut text =
+                FormattedText::plain(format!("🔍 自上次快照以來有 {} 項變更：", changes.len()));
+            for change in changes {
+                text = match change {
+                    RankChange::Added(admin) => text
+                        .push("\n➕ ")
+                        .mention(&admin.name, admin.user_id)
+                        .push(format!("｜{}", rank_of(&admin))),
+                    RankChange::Removed(admin) => text
+                        .push("\n➖ ")
+                        .mention(&admin.name, admin.user_id)
+                        .push(format!("｜{}", rank_of(&admin))),
+                    RankChange::RankChanged { admin, old_rank } => text
+                        .push("\n✏️ ")
+                        .mention(&admin.name, admin.user_id)
+                        .push(format!(
+                            "｜{} → {}",
+                            old_rank.as_deref().unwrap_or("（無）"),
+                            rank_of(&admin)
+                        )),
+                    RankChange::RightsChanged {
+                        admin,
+                        granted,
+                        revoked,
+                    } => {
+                        let mut text = text.push("\n🔧 ").mention(&admin.name, admin.user_id);
+                        for name in granted {
+                            text = text.push(" +").code(name);
+                        }
+                        for name in revoked {
+                            text = text.push(" -").code(name);
+                        }
+                        text
+                    }
+                };
+            }
+
+            msg.edit_or_reply(text).await?;
+        }
+        _ => {
+            let mut text =
+                FormattedText::plain(format!("🏷️ 這個聊天室有 {} 位管理員：", admins.len()));
+            for (admin, granted) in admins.iter().zip(granted) {
+                text = text
+                    .push(if admin.is_creator {
+                        "\n👑 "
+                    } else {
+                        "\n• "
+                    })
+                    .mention(&admin.name, admin.user_id)
+                    .push("｜")
+                    .bold(rank_of(admin));
+                if granted {
+                    text = text.push("｜🤖 PBot 授予");
+                }
+
+                let rights = admin.rights.granted();
+                if !rights.is_empty() {
+                    text = text.push("\n    ").code(rights.join(", "));
                 }
+            }
+
+            msg.edit_or_reply(text).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the title of the administrator to show.
+fn rank_of(admin: &AdminInfo) -> &str {
+    admin.rank.as_deref().unwrap_or("（無頭銜）")
+}
+
+/// Quote the CSV field if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Find the user in the administrators of the chat.
+async fn find_admin(
+    msg: &ModuleMessage,
+    chat: &Chat,
+    user_id: i32,
+) -> Result<Option<AdminInfo>, ModuleError> {
+    let admins = msg
+        .handle
+        .send(GetAdminsCommand { chat: chat.clone() })
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to get the administrators", e))?;
+
+    Ok(admins.into_iter().find(|admin| admin.user_id == user_id))
+}
+
+/// The arguments of `!addrank`.
+struct AddRankArgs {
+    /// The members to set the rank of.
+    members: Vec<MemberRef>,
+    /// The rank to set.
+    rank: String,
+    /// How long the rank lasts, if it is temporary.
+    duration: Option<Duration>,
+    /// The name of the preset to apply, if any.
+    preset: Option<String>,
+}
+
+/// Parse the arguments of `!addrank`.
+///
+/// The leading `@username`, numeric IDs and mentions are the members,
+/// and the rest is the rank, which can be quoted, such as
+/// `!addrank @user1 @user2 "Senior Dev"`. `--for <duration>` can be
+/// put among the members, such as `!addrank --for 24h "Birthday Star"`,
+/// and so can `--preset <name>`, such as `!addrank --preset mod "Moderator"`.
+///
+/// When replying, the numeric words are the rank rather than the IDs,
+/// so the ranks such as `2024` still work for the member replied to.
+fn parse_args(message: &Message) -> Result<AddRankArgs, ModuleError> {
+    let mentions = message
+        .fmt_entities()
+        .into_iter()
+        .flatten()
+        .filter_map(|entity| match entity {
+            tl::enums::MessageEntity::MentionName(mention) => Some(mention.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    parse_text(
+        message.text(),
+        &mentions,
+        message.reply_to_message_id().is_none(),
+    )
+}
+
+/// Parse the arguments of `!addrank` from the text of the command.
+///
+/// See [`parse_args`] for the syntax. `accepts_ids` tells if the
+/// numeric words are the IDs of the members.
+fn parse_text(
+    command: &str,
+    mentions: &[tl::types::MessageEntityMentionName],
+    accepts_ids: bool,
+) -> Result<AddRankArgs, ModuleError> {
+    // Take the mentions of the users without usernames as the members,
+    // and blank them out of the text.
+    let mut members = mentions
+        .iter()
+        .map(|mention| MemberRef::Id(mention.user_id))
+        .collect::<Vec<_>>();
+
+    let mut text = String::new();
+    let mut offset = 0;
+    for c in command.chars() {
+        let is_mention = mentions
+            .iter()
+            .any(|m| (m.offset..m.offset + m.length).contains(&offset));
+        text.push(if is_mention { ' ' } else { c });
+        offset += c.len_utf16() as i32;
+    }
+
+    let text = text.trim_start();
+    let text = text.strip_prefix(CMD_PREFIX).unwrap_or(text);
+
+    // The leading unquoted usernames and IDs are the members.
+    let mut duration = None;
+    let mut preset = None;
+    let mut words = split_words(text).into_iter().peekable();
+    while let Some((word, false)) = words.peek() {
+        if word == FOR_FLAG {
+            words.next();
+            duration = match words.next().and_then(|(word, _)| parse_duration(&word)) {
+                Some(duration) if duration.as_secs() > 0 => Some(duration),
+                _ => {
+                    return Err(ModuleError::usage_with(
+                        "請指定有效的期限，例如 24h 或 1d12h。",
+                        USAGE,
+                    ))
+                }
+            };
+            continue;
+        }
+        if word == PRESET_FLAG {
+            words.next();
+            preset = match words.next() {
+                Some((name, _)) => Some(name),
+                None => return Err(ModuleError::usage_with("請指定預設角色。", USAGE)),
             };
+            continue;
+        }
 
-            // Get the full name of user repiled to.
-            //
-            // We get it before `admin_builder`
-            // since `GetAdminRightsBuilderCommand` will own
-            // `user_replied_to`.
-            let repiled_user_name = user_replied_to.full_name();
-
-            // Get the admin builder.
-            // The "Rank" is one of the administrator privileges.
-            let mut admin_builder = handle
-                .send(GetAdminRightsBuilderCommand {
-                    channel: message.read().await.chat(),
-                    user: user_replied_to,
-                })
-                .await?;
+        let member = match word.strip_prefix('@') {
+            Some(username) if !username.is_empty() => MemberRef::Username(username.to_string()),
+            _ => match word.parse() {
+                Ok(id) if accepts_ids => MemberRef::Id(id),
+                _ => break,
+            },
+        };
 
-            // Set the rank and send the request to Telegram.
-            admin_builder
-                .load_current()
-                .await?
-                .manage_call(true)
-                .rank(&rank)
-                .invoke()
-                .await?;
+        members.push(member);
+        words.next();
+    }
 
-            // Notify user that the operation is succeed.
-            message
-                .write()
-                .await
-                .edit(InputMessage::text(format!(
-                    "[PBOT] ✅ 成功將 {user} 的頭銜設定為 {rank}。",
-                    user = repiled_user_name
-                )))
-                .await?;
+    Ok(AddRankArgs {
+        members,
+        rank: words.map(|(word, _)| word).collect::<Vec<_>>().join(" "),
+        duration,
+        preset,
+    })
+}
+
+/// Split the text into words, keeping the quoted words together.
+///
+/// It returns the words along with whether they were quoted.
+fn split_words(text: &str) -> Vec<(String, bool)> {
+    let mut words = Vec::new();
+    let mut chars = text.chars().peekable();
 
-            // It worked with no fault errors! 👌
-            Ok(())
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
         }
-        .into_actor(self)
-        .boxed_local()
+
+        let closing = match c {
+            '"' => Some('"'),
+            '「' => Some('」'),
+            '“' => Some('”'),
+            _ => None,
+        };
+        let word = match closing {
+            Some(closing) => chars.by_ref().take_while(|&c| c != closing).collect(),
+            None => std::iter::once(c)
+                .chain(std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())))
+                .collect(),
+        };
+
+        words.push((word, closing.is_some()));
+    }
+
+    words
+}
+
+/// Check if Telegram accepts the rank.
+///
+/// Telegram limits the length of the rank, and rejects any emoji in it.
+fn validate_rank(rank: &str) -> ModuleResult {
+    if rank.is_empty() {
+        return Err(ModuleError::usage_with("請指定頭銜。", USAGE));
+    }
+    if rank.chars().count() > MAX_RANK_LENGTH {
+        return Err(ModuleError::usage(format!(
+            "頭銜最多只能有 {} 個字元。",
+            MAX_RANK_LENGTH
+        )));
     }
+    if contains_emoji(rank) {
+        return Err(ModuleError::usage("頭銜不能包含表情符號。"));
+    }
+
+    Ok(())
+}
+
+/// Check if the text contains any emoji.
+///
+/// Only the fully-qualified emoji count, so the symbols shown as text
+/// by default, such as `✓`, `↔` and the digits, are allowed unless
+/// followed by the emoji presentation selector.
+fn contains_emoji(text: &str) -> bool {
+    emojis::iter().any(|emoji| text.contains(emoji.as_str()))
 }
 
-/// Extract the rank argument from the command message.
-fn extract_rank(message: &grammers_client::types::Message) -> Option<String> {
-    // Get the message text.
-    let msg_text = message.text().to_string();
-    // Get the sender.
-    let msg_from_root_user = is_root_user(message);
+/// Extract the arguments of the command message.
+///
+/// It returns `None` if the message is not the command,
+/// or it is not from the account owner (root user).
+fn extract_args(message: &Message, command: &str) -> Option<Vec<String>> {
     // Split the message text by whitespace.
     // For example: `!addrank idiot` -> `Iterator(!addrank, idiot)`
-    let mut cmd_list = msg_text.split_whitespace();
+    let mut words = message.text().split_whitespace();
 
-    // Get the command for checking if the command is matched.
-    let cmd = cmd_list.next();
-
-    // Check if we extracted the command sucussfully,
-    // the command is CMD_PREFIX and the command message
-    // is from the account owner (root user).
-    if matches!(cmd, Some(s) if s == CMD_PREFIX) && msg_from_root_user {
-        cmd_list.next().map(|s| s.to_string())
+    if words.next() == Some(command) && is_root_user(message) {
+        Some(words.map(String::from).collect())
     } else {
         None
     }
 }
 
 /// Get the user of the message replied to.
-async fn get_user_replied_to(
-    message: &mut grammers_client::types::Message,
-) -> anyhow::Result<Option<grammers_client::types::User>> {
+async fn get_user_replied_to(message: &mut Message) -> anyhow::Result<Option<User>> {
     Ok(message
         .get_reply()
         .await?
         .and_then(|message_replied_to| message_replied_to.sender())
         .and_then(|sender| match sender {
-            grammers_client::types::Chat::User(user) => Some(user),
+            Chat::User(user) => Some(user),
             _ => None,
         }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the reason of the usage error, or `None` if it is not one.
+    fn usage_reason(error: ModuleError) -> Option<String> {
+        match error {
+            ModuleError::Usage { reason, .. } => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// Parse the command without any mention.
+    fn parse(command: &str, accepts_ids: bool) -> Result<AddRankArgs, ModuleError> {
+        parse_text(command, &[], accepts_ids)
+    }
+
+    #[test]
+    fn ranks_with_symbols_are_valid() {
+        for rank in [
+            "Senior Dev",
+            "資深開發者",
+            "✓ Reviewer",
+            "⌘ Admin",
+            "→ 2024",
+            "#1",
+        ] {
+            assert!(validate_rank(rank).is_ok(), "{} should be valid", rank);
+        }
+    }
+
+    #[test]
+    fn ranks_with_emoji_are_rejected() {
+        for rank in [
+            "⭐ Star",
+            "Dev 👍🏻",
+            "🇹🇼",
+            "↔\u{FE0F}",
+            "1\u{FE0F}\u{20E3}",
+            "👨‍💻",
+        ] {
+            let error = validate_rank(rank).expect_err(rank);
+            assert_eq!(
+                usage_reason(error).as_deref(),
+                Some("頭銜不能包含表情符號。")
+            );
+        }
+    }
+
+    #[test]
+    fn ranks_are_limited_in_characters() {
+        assert!(validate_rank(&"頭".repeat(MAX_RANK_LENGTH)).is_ok());
+        assert!(validate_rank(&"頭".repeat(MAX_RANK_LENGTH + 1)).is_err());
+        assert_eq!(
+            usage_reason(validate_rank("").unwrap_err()).as_deref(),
+            Some("請指定頭銜。")
+        );
+    }
+
+    #[test]
+    fn words_keep_the_quotes_together() {
+        assert_eq!(
+            split_words("  a \"b c\" 「d e」 “f” g  "),
+            [
+                ("a".to_string(), false),
+                ("b c".to_string(), true),
+                ("d e".to_string(), true),
+                ("f".to_string(), true),
+                ("g".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_quotes_take_the_rest() {
+        assert_eq!(
+            split_words("a \"b c"),
+            [("a".to_string(), false), ("b c".to_string(), true)]
+        );
+    }
+
+    #[test]
+    fn args_with_usernames_and_ids() {
+        let args = parse("!addrank @user1 123 \"Senior Dev\"", true).unwrap();
+
+        assert_eq!(
+            args.members,
+            [MemberRef::Username("user1".to_string()), MemberRef::Id(123)]
+        );
+        assert_eq!(args.rank, "Senior Dev");
+        assert_eq!(args.duration, None);
+        assert_eq!(args.preset, None);
+    }
+
+    #[test]
+    fn numeric_ranks_when_replying() {
+        let args = parse("!addrank 2024", false).unwrap();
+
+        assert!(args.members.is_empty());
+        assert_eq!(args.rank, "2024");
+    }
+
+    #[test]
+    fn quoted_ids_are_the_rank() {
+        let args = parse("!addrank \"2024\"", true).unwrap();
+
+        assert!(args.members.is_empty());
+        assert_eq!(args.rank, "2024");
+    }
+
+    #[test]
+    fn args_with_flags() {
+        let args = parse("!addrank --for 1d12h @user --preset mod Moderator", true).unwrap();
+
+        assert_eq!(args.members, [MemberRef::Username("user".to_string())]);
+        assert_eq!(args.rank, "Moderator");
+        assert_eq!(args.duration, Some(Duration::from_secs(36 * 3600)));
+        assert_eq!(args.preset.as_deref(), Some("mod"));
+    }
+
+    #[test]
+    fn expiry_is_after_the_duration() {
+        assert_eq!(
+            expiry_due(1_000, Duration::from_secs(86_400)).ok(),
+            Some(87_400)
+        );
+    }
+
+    #[test]
+    fn overlong_expiries_are_rejected() {
+        for duration in [
+            Duration::from_secs(u64::MAX),
+            Duration::from_secs(i64::MAX as u64),
+        ] {
+            let error = expiry_due(1_000, duration).unwrap_err();
+            assert_eq!(usage_reason(error).as_deref(), Some("期限太長了。"));
+        }
+        assert!(expiry_due(0, Duration::from_secs(i64::MAX as u64)).is_ok());
+    }
+
+    #[test]
+    fn malformed_flags_are_rejected() {
+        for command in [
+            "!addrank --for 0s Rank",
+            "!addrank --for soon Rank",
+            "!addrank --for",
+        ] {
+            let error = parse(command, true).err().expect(command);
+            assert_eq!(
+                usage_reason(error).as_deref(),
+                Some("請指定有效的期限，例如 24h 或 1d12h。")
+            );
+        }
+
+        let error = parse("!addrank --preset", true).err().unwrap();
+        assert_eq!(usage_reason(error).as_deref(), Some("請指定預設角色。"));
+    }
+
+    #[test]
+    fn mentions_are_members() {
+        // `Alice` is mentioned, counted in UTF-16 code units after `🙂`.
+        let mention = tl::types::MessageEntityMentionName {
+            offset: 12,
+            length: 5,
+            user_id: 42,
+        };
+        let args = parse_text("!addrank 🙂 Alice Boss", &[mention], true).unwrap();
+
+        assert_eq!(args.members, [MemberRef::Id(42)]);
+        assert_eq!(args.rank, "🙂 Boss");
+    }
+}
@@ -0,0 +1,283 @@
+//! PBot: Modules: AddRankModule: Rank Expiry Actor
+//!
+//! This restores the temporary ranks set with `!addrank --for`
+//! when they expire: the previous title comes back, or the
+//! administrator status granted by PBot is revoked.
+//!
+//! The pending expiries are persisted in the storage, and
+//! rescheduled when PBot starts, so they survive restarts.
+
+pub mod commands;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::prelude::*;
+use chrono::Utc;
+use grammers_client::types::chat::PackedChat;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{self, StorageActor};
+use crate::telegram::client::{
+    admins::{AdminRights, MemberRef},
+    commands::{ResolveMemberCommand, SetAdminRightsCommand},
+    ClientActor,
+};
+
+use self::commands::{CancelExpiryCommand, ListExpiriesCommand, ScheduleExpiryCommand};
+
+use super::grants::{RankGrant, RankGrants};
+
+/// The storage namespace of the pending expiries.
+const STORAGE_NAMESPACE: &str = "rank_expiries";
+
+/// A temporary rank which has not expired yet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RankExpiry {
+    /// The serialized [`PackedChat`] where the member is.
+    pub chat: Vec<u8>,
+    /// The ID of the chat where the member is.
+    pub chat_id: i32,
+    /// The ID of the member.
+    pub user_id: i32,
+    /// The full name of the member.
+    pub user_name: String,
+    /// The temporary rank.
+    pub rank: String,
+    /// The title to restore, if any.
+    pub previous_rank: Option<String>,
+    /// The rights to restore, if the rank came with other rights.
+    #[serde(default)]
+    pub previous_rights: Option<AdminRights>,
+    /// Whether to revoke the administrator status granted by PBot.
+    pub revoke: bool,
+    /// When the rank expires, in UNIX timestamp.
+    pub due: i64,
+}
+
+impl RankExpiry {
+    /// Whether this expiry is of the member in the chat.
+    fn is_of(&self, chat_id: i32, user_id: i32) -> bool {
+        self.chat_id == chat_id && self.user_id == user_id
+    }
+}
+
+/// The rank expiry actor.
+pub struct RankExpiryActor {
+    /// The client to restore the ranks with.
+    client: Addr<ClientActor>,
+    /// The storage to persist the pending expiries.
+    storage: Addr<StorageActor>,
+    /// The administrator status granted by AddRankModule.
+    grants: Arc<RankGrants>,
+    /// The expiries which have not been done yet.
+    pending: Vec<RankExpiry>,
+}
+
+impl RankExpiryActor {
+    /// Create a rank expiry actor.
+    pub fn new(
+        client: Addr<ClientActor>,
+        storage: Addr<StorageActor>,
+        grants: Arc<RankGrants>,
+    ) -> Self {
+        Self {
+            client,
+            storage,
+            grants,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Persist the pending expiries.
+    fn persist(&self, ctx: &mut Context<Self>) {
+        let storage = self.storage.clone();
+        let pending = self.pending.clone();
+
+        ctx.spawn(
+            async move {
+                if let Err(e) = storage::save(&storage, STORAGE_NAMESPACE, &pending).await {
+                    error!("Failed to persist the pending expiries: {:?}", e);
+                }
+            }
+            .into_actor(self),
+        );
+    }
+
+    /// Run the expiry when it is due.
+    fn schedule(&mut self, expiry: RankExpiry, ctx: &mut Context<Self>) {
+        let delay = Duration::from_secs((expiry.due - Utc::now().timestamp()).max(0) as u64);
+
+        ctx.run_later(delay, move |act, ctx| act.execute(expiry, ctx));
+    }
+
+    /// Restore the rank, and forget the expiry.
+    fn execute(&mut self, expiry: RankExpiry, ctx: &mut Context<Self>) {
+        // The expiry may have been cancelled or replaced.
+        if !self.pending.contains(&expiry) {
+            return;
+        }
+
+        let client = self.client.clone();
+        let grants = self.grants.clone();
+        let task = expiry.clone();
+
+        ctx.spawn(
+            async move { restore(&client, &grants, &task).await }
+                .into_actor(self)
+                .map(move |result, act, ctx| {
+                    match result {
+                        Ok(()) => info!(
+                            "⌛ The rank `{}` of {} in {} expired.",
+                            expiry.rank, expiry.user_id, expiry.chat_id
+                        ),
+                        Err(e) => error!("Failed to restore the expired rank: {:?}", e),
+                    }
+
+                    act.pending.retain(|pending| pending != &expiry);
+                    act.persist(ctx);
+                }),
+        );
+    }
+}
+
+/// Restore the rank of the member according to the expiry.
+async fn restore(
+    client: &Addr<ClientActor>,
+    grants: &RankGrants,
+    expiry: &RankExpiry,
+) -> anyhow::Result<()> {
+    let chat = PackedChat::from_bytes(&expiry.chat)
+        .map_err(|_| anyhow::anyhow!("malformed chat"))?
+        .unpack();
+
+    let user = match client
+        .send(ResolveMemberCommand {
+            chat: chat.clone(),
+            member: MemberRef::Id(expiry.user_id),
+        })
+        .await??
+    {
+        Some(user) => user,
+        None => {
+            warn!(
+                "The member {} has left; nothing to restore.",
+                expiry.user_id
+            );
+            return Ok(());
+        }
+    };
+
+    // Revoke every right if PBot granted the admin status.
+    let rights = if expiry.revoke {
+        Some(AdminRights::default())
+    } else {
+        expiry.previous_rights
+    };
+    client
+        .send(SetAdminRightsCommand {
+            channel: chat,
+            user,
+            rights,
+            rank: expiry.previous_rank.clone().unwrap_or_default(),
+        })
+        .await??;
+
+    if expiry.revoke {
+        grants
+            .remove(&RankGrant {
+                chat_id: expiry.chat_id,
+                user_id: expiry.user_id,
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+impl Actor for RankExpiryActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("🌟 RankExpiry started!");
+
+        // Reschedule the expiries pending before the restart.
+        let storage = self.storage.clone();
+        ctx.wait(
+            async move { storage::load::<Vec<RankExpiry>>(&storage, STORAGE_NAMESPACE).await }
+                .into_actor(self)
+                .map(|result, act, ctx| match result {
+                    Ok(pending) => {
+                        for expiry in pending.unwrap_or_default() {
+                            act.pending.push(expiry.clone());
+                            act.schedule(expiry, ctx);
+                        }
+                    }
+                    Err(e) => error!("Failed to load the pending expiries: {:?}", e),
+                }),
+        );
+    }
+
+    fn stopped(&mut self, _: &mut Self::Context) {
+        info!("👋 RankExpiry stopped!");
+    }
+}
+
+impl Handler<ScheduleExpiryCommand> for RankExpiryActor {
+    type Result = ();
+
+    /// Restore the rank when the expiry is due.
+    fn handle(&mut self, cmd: ScheduleExpiryCommand, ctx: &mut Context<Self>) -> Self::Result {
+        let mut expiry = cmd.0;
+
+        // Keep what the replaced expiry would restore.
+        if let Some(replaced) = self
+            .pending
+            .iter()
+            .find(|pending| pending.is_of(expiry.chat_id, expiry.user_id))
+        {
+            expiry.previous_rank = replaced.previous_rank.clone();
+            expiry.previous_rights = replaced.previous_rights;
+            expiry.revoke = replaced.revoke;
+        }
+        self.pending
+            .retain(|pending| !pending.is_of(expiry.chat_id, expiry.user_id));
+
+        self.pending.push(expiry.clone());
+        self.persist(ctx);
+        self.schedule(expiry, ctx);
+    }
+}
+
+impl Handler<CancelExpiryCommand> for RankExpiryActor {
+    type Result = ();
+
+    /// Cancel the pending expiry of the member.
+    fn handle(&mut self, cmd: CancelExpiryCommand, ctx: &mut Context<Self>) -> Self::Result {
+        let count = self.pending.len();
+        self.pending
+            .retain(|pending| !pending.is_of(cmd.chat_id, cmd.user_id));
+
+        if self.pending.len() != count {
+            self.persist(ctx);
+        }
+    }
+}
+
+impl Handler<ListExpiriesCommand> for RankExpiryActor {
+    type Result = MessageResult<ListExpiriesCommand>;
+
+    /// List the pending expiries in the chat.
+    fn handle(&mut self, cmd: ListExpiriesCommand, _: &mut Context<Self>) -> Self::Result {
+        let mut expiries = self
+            .pending
+            .iter()
+            .filter(|pending| pending.chat_id == cmd.chat_id)
+            .cloned()
+            .collect::<Vec<_>>();
+        expiries.sort_by_key(|expiry| expiry.due);
+
+        MessageResult(expiries)
+    }
+}
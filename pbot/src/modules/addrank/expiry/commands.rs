@@ -0,0 +1,34 @@
+//! Commands for the rank expiry actor.
+
+use actix::prelude::*;
+
+use super::RankExpiry;
+
+/// Restore the rank when the expiry is due.
+///
+/// It replaces the pending expiry of the same member, but keeps
+/// what to restore, so the title before all the temporary ranks
+/// comes back. The expiry is persisted, so it will still be done
+/// after restarting PBot.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ScheduleExpiryCommand(pub RankExpiry);
+
+/// Cancel the pending expiry of the member, such as when the
+/// rank is set permanently or removed.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CancelExpiryCommand {
+    /// The ID of the chat where the member is.
+    pub chat_id: i32,
+    /// The ID of the member.
+    pub user_id: i32,
+}
+
+/// List the pending expiries in the chat, from the earliest due.
+#[derive(Message)]
+#[rtype(result = "Vec<RankExpiry>")]
+pub struct ListExpiriesCommand {
+    /// The ID of the chat.
+    pub chat_id: i32,
+}
@@ -0,0 +1,79 @@
+//! PBot: Modules: AddRankModule: Admin Grants
+//!
+//! The records of the administrator status which AddRankModule granted,
+//! so `!rmrank` knows whether to revoke the whole status or only
+//! to clear the title.
+//!
+//! They are persisted in the storage, so the grants made before
+//! a restart can still be revoked.
+
+use crate::storage::{Persisted, StorageActor};
+use actix::Addr;
+use serde::{Deserialize, Serialize};
+
+/// The storage namespace of the admin grants.
+const STORAGE_NAMESPACE: &str = "addrank";
+
+/// The record of an administrator status granted by AddRankModule.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RankGrant {
+    /// The ID of the chat where the status was granted.
+    pub chat_id: i32,
+    /// The ID of the user who was granted.
+    pub user_id: i32,
+}
+
+/// The persisted admin grants.
+pub struct RankGrants {
+    /// The grants, from the oldest to the newest.
+    grants: Persisted<Vec<RankGrant>>,
+}
+
+impl RankGrants {
+    /// Load the admin grants from the storage.
+    pub async fn load(storage: Addr<StorageActor>) -> anyhow::Result<Self> {
+        Ok(Self {
+            grants: Persisted::load(storage, STORAGE_NAMESPACE).await?,
+        })
+    }
+
+    /// Add the grant and persist it.
+    pub async fn insert(&self, grant: RankGrant) -> anyhow::Result<()> {
+        self.grants
+            .update(|grants| {
+                if grants.contains(&grant) {
+                    return None;
+                }
+                grants.push(grant);
+                Some(())
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Find the grant of the user in the chat.
+    pub async fn find(&self, chat_id: i32, user_id: i32) -> Option<RankGrant> {
+        self.grants
+            .read(|grants| {
+                grants
+                    .iter()
+                    .find(|grant| grant.chat_id == chat_id && grant.user_id == user_id)
+                    .cloned()
+            })
+            .await
+    }
+
+    /// Remove the grant and persist the remaining grants.
+    pub async fn remove(&self, grant: &RankGrant) -> anyhow::Result<()> {
+        self.grants
+            .update(|grants| {
+                let count = grants.len();
+                grants.retain(|g| g != grant);
+                (grants.len() != count).then_some(())
+            })
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+//! PBot: Modules: AddRankModule: Role Presets
+//!
+//! The named rights sets which `!addrank --preset <name>` applies
+//! along with the title, such as `!addrank --preset mod "Moderator"`.
+//!
+//! The built-in presets are `mod` (delete + ban), `helper` (pin) and
+//! `vanity` (the minimal rights for the title). They can be overridden
+//! or extended in `TG_ADDRANK_PRESETS`, for example
+//! `mod:delete_messages+ban_users+pin_messages,janitor:delete_messages`.
+
+use std::collections::BTreeMap;
+
+use crate::modules::base::error::ModuleError;
+use crate::telegram::client::admins::AdminRights;
+use crate::utils::getenv_pairs;
+
+/// The rights sets of the presets, by their names.
+#[derive(Clone, Debug)]
+pub struct RankPresets(BTreeMap<String, AdminRights>);
+
+impl RankPresets {
+    /// Read the presets from the environment variable `TG_ADDRANK_PRESETS`,
+    /// on top of the built-in presets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any right in the presets is unknown.
+    pub fn from_env() -> Self {
+        let mut presets = BTreeMap::new();
+        let built_in = [
+            ("mod", "delete_messages+ban_users"),
+            ("helper", "pin_messages"),
+            ("vanity", ""),
+        ];
+        let configured = getenv_pairs("TG_ADDRANK_PRESETS");
+
+        for (name, rights) in built_in
+            .iter()
+            .map(|(name, rights)| (name.to_string(), rights.to_string()))
+            .chain(configured)
+        {
+            let mut preset = AdminRights::default();
+            for right in rights.split('+').map(str::trim).filter(|r| !r.is_empty()) {
+                assert!(
+                    preset.set(right, true),
+                    "unknown right `{}` in the preset `{}`, the rights are: {}",
+                    right,
+                    name,
+                    AdminRights::NAMES.join(", ")
+                );
+            }
+
+            // Telegram demotes the administrators without any right,
+            // so the title needs at least the least harmful right.
+            if preset.granted().is_empty() {
+                preset.manage_call = true;
+            }
+
+            presets.insert(name, preset);
+        }
+
+        Self(presets)
+    }
+
+    /// Get the rights of the preset by its name.
+    pub fn get(&self, name: &str) -> Result<AdminRights, ModuleError> {
+        self.0.get(name).copied().ok_or_else(|| {
+            ModuleError::usage(format!(
+                "沒有名為「{}」的預設角色。可用的預設角色：{}",
+                name,
+                self.0
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join("、")
+            ))
+        })
+    }
+}
@@ -0,0 +1,195 @@
+//! PBot: Modules: AddRankModule: Rank Snapshots
+//!
+//! The snapshots of the administrators in the chats, taken when
+//! `!ranks` lists them, so `!ranks diff` can show what has changed
+//! since the last time.
+
+use std::collections::BTreeMap;
+
+use crate::storage::{Persisted, StorageActor};
+use crate::telegram::client::admins::AdminInfo;
+use actix::Addr;
+
+/// The storage namespace of the rank snapshots.
+const STORAGE_NAMESPACE: &str = "ranks";
+
+/// A change of an administrator between two snapshots.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RankChange {
+    /// The user became an administrator.
+    Added(AdminInfo),
+    /// The user is no longer an administrator.
+    Removed(AdminInfo),
+    /// The administrator got another title.
+    RankChanged {
+        /// The administrator now.
+        admin: AdminInfo,
+        /// The title before.
+        old_rank: Option<String>,
+    },
+    /// The administrator got other rights.
+    RightsChanged {
+        /// The administrator now.
+        admin: AdminInfo,
+        /// The names of the newly granted rights.
+        granted: Vec<&'static str>,
+        /// The names of the revoked rights.
+        revoked: Vec<&'static str>,
+    },
+}
+
+/// Compare the administrators in the two snapshots.
+pub fn diff(old: &[AdminInfo], new: &[AdminInfo]) -> Vec<RankChange> {
+    let mut changes = Vec::new();
+
+    for admin in new {
+        let before = match old.iter().find(|a| a.user_id == admin.user_id) {
+            Some(before) => before,
+            None => {
+                changes.push(RankChange::Added(admin.clone()));
+                continue;
+            }
+        };
+
+        if before.rank != admin.rank {
+            changes.push(RankChange::RankChanged {
+                admin: admin.clone(),
+                old_rank: before.rank.clone(),
+            });
+        }
+        if before.rights != admin.rights {
+            let (granted, revoked) = admin.rights.changes_from(&before.rights);
+            changes.push(RankChange::RightsChanged {
+                admin: admin.clone(),
+                granted,
+                revoked,
+            });
+        }
+    }
+
+    for admin in old {
+        if !new.iter().any(|a| a.user_id == admin.user_id) {
+            changes.push(RankChange::Removed(admin.clone()));
+        }
+    }
+
+    changes
+}
+
+/// The persisted rank snapshots, by the IDs of the chats.
+pub struct RankSnapshots {
+    /// The latest snapshot of each chat.
+    snapshots: Persisted<BTreeMap<i32, Vec<AdminInfo>>>,
+}
+
+impl RankSnapshots {
+    /// Load the rank snapshots from the storage.
+    pub async fn load(storage: Addr<StorageActor>) -> anyhow::Result<Self> {
+        Ok(Self {
+            snapshots: Persisted::load(storage, STORAGE_NAMESPACE).await?,
+        })
+    }
+
+    /// Replace the snapshot of the chat and persist it.
+    ///
+    /// It returns the previous snapshot of the chat, if any.
+    pub async fn replace(
+        &self,
+        chat_id: i32,
+        admins: Vec<AdminInfo>,
+    ) -> anyhow::Result<Option<Vec<AdminInfo>>> {
+        let previous = self
+            .snapshots
+            .update(|snapshots| Some(snapshots.insert(chat_id, admins)))
+            .await?;
+
+        Ok(previous.flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::telegram::client::admins::AdminRights;
+
+    use super::*;
+
+    /// An administrator who can only pin the messages.
+    fn admin(user_id: i32, rank: Option<&str>) -> AdminInfo {
+        AdminInfo {
+            user_id,
+            name: format!("User {}", user_id),
+            is_creator: false,
+            rank: rank.map(String::from),
+            rights: AdminRights {
+                pin_messages: true,
+                ..Default::default()
+            },
+            promoted_by: None,
+        }
+    }
+
+    #[test]
+    fn no_changes() {
+        let admins = [admin(1, Some("Dev")), admin(2, None)];
+
+        assert_eq!(diff(&admins, &admins), []);
+    }
+
+    #[test]
+    fn added_and_removed_admins() {
+        let old = [admin(1, Some("Dev")), admin(2, None)];
+        let new = [admin(2, None), admin(3, Some("Ops"))];
+
+        assert_eq!(
+            diff(&old, &new),
+            [
+                RankChange::Added(admin(3, Some("Ops"))),
+                RankChange::Removed(admin(1, Some("Dev"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_ranks() {
+        let old = [admin(1, Some("Dev")), admin(2, None)];
+        let new = [admin(1, None), admin(2, Some("Ops"))];
+
+        assert_eq!(
+            diff(&old, &new),
+            [
+                RankChange::RankChanged {
+                    admin: admin(1, None),
+                    old_rank: Some("Dev".to_string()),
+                },
+                RankChange::RankChanged {
+                    admin: admin(2, Some("Ops")),
+                    old_rank: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_rights() {
+        let old = [admin(1, None)];
+        let mut promoted = admin(1, Some("Mod"));
+        promoted.rights.pin_messages = false;
+        promoted.rights.ban_users = true;
+        promoted.rights.delete_messages = true;
+
+        assert_eq!(
+            diff(&old, &[promoted.clone()]),
+            [
+                RankChange::RankChanged {
+                    admin: promoted.clone(),
+                    old_rank: None,
+                },
+                RankChange::RightsChanged {
+                    admin: promoted,
+                    granted: vec!["delete_messages", "ban_users"],
+                    revoked: vec!["pin_messages"],
+                },
+            ]
+        );
+    }
+}
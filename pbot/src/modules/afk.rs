@@ -0,0 +1,265 @@
+//! PBot: Modules: AfkModule
+//!
+//! Reply to the pings automatically while you are away.
+//!
+//! `!afk [reason]` marks you away. The private messages and the
+//! mentions in groups are then auto-replied with the reason and
+//! how long you have been away, once per chat per interval.
+//!
+//! Sending any message other than the commands marks you back,
+//! and the digest of who pinged you is sent to your Saved Messages.
+
+pub mod status;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::prelude::*;
+use grammers_client::types::{Chat, Message};
+use log::{error, info};
+use pbot_modules_derive::{ModuleActivator, ModuleActor, ModuleMeta};
+
+use crate::telegram::{
+    format::{message_link, FormattedText},
+    user::is_root_user,
+};
+use crate::utils::format_duration;
+
+use self::status::{AfkState, AfkStatus, Ping, MAX_PINGS};
+
+use super::base::{error::ModuleResult, response::RESPONSE_PREFIX, ModuleMessage};
+
+const CMD: &str = "!afk";
+
+/// The maximum length of the message previews in the digest, in characters.
+const PREVIEW_LENGTH: usize = 50;
+
+/// The AfkModule actor.
+#[derive(Clone, ModuleActor, ModuleActivator, ModuleMeta)]
+#[name = "AfkModule"]
+pub struct AfkModuleActor {
+    /// The persisted AFK status.
+    pub state: Arc<AfkState>,
+    /// How often to auto-reply in the same chat.
+    pub interval: Duration,
+}
+
+impl Handler<ModuleMessage> for AfkModuleActor {
+    type Result = ResponseActFuture<Self, ModuleResult>;
+
+    fn handle(&mut self, msg: ModuleMessage, _: &mut Self::Context) -> Self::Result {
+        // Clone the fields of self to move into the following block.
+        let state = self.state.clone();
+        let interval = self.interval;
+
+        async move {
+            // Take a snapshot of the message, so we don't need to lock it again.
+            let message = msg.snapshot().await;
+
+            if let Some(reason) = extract_reason(&message) {
+                handle_afk(&msg, &state, reason).await
+            } else if message.outgoing() {
+                handle_back(&msg, &message, &state).await
+            } else if is_ping(&message) {
+                handle_ping(&msg, &message, &state, interval).await
+            } else {
+                Ok(())
+            }
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Mark the owner away with `!afk [reason]`.
+async fn handle_afk(msg: &ModuleMessage, state: &AfkState, reason: Option<String>) -> ModuleResult {
+    state.set(reason.clone()).await?;
+    info!("💤 Away: {}", reason.as_deref().unwrap_or("(no reason)"));
+
+    let text = FormattedText::new().push("💤 已設定為離開狀態。");
+    let text = match &reason {
+        Some(reason) => text.push("\n原因：").push(reason),
+        None => text,
+    };
+    msg.edit_or_reply(text.push("\n傳送任何訊息即可解除。"))
+        .await?;
+
+    Ok(())
+}
+
+/// Mark the owner back on the outgoing message, and send the digest.
+async fn handle_back(msg: &ModuleMessage, message: &Message, state: &AfkState) -> ModuleResult {
+    // The commands and the responses of PBot don't mean coming back.
+    let text = message.text();
+    if text.starts_with('!') || text.starts_with(RESPONSE_PREFIX) {
+        return Ok(());
+    }
+
+    let status = match state.clear().await? {
+        Some(status) => status,
+        None => return Ok(()),
+    };
+    info!(
+        "👋 Back after {}s, with {} pings.",
+        status.elapsed().as_secs(),
+        status.ping_count()
+    );
+
+    msg.notify(digest(&status)).await?;
+    Ok(())
+}
+
+/// Record the ping, and auto-reply to it if the chat is due.
+async fn handle_ping(
+    msg: &ModuleMessage,
+    message: &Message,
+    state: &AfkState,
+    interval: Duration,
+) -> ModuleResult {
+    // Don't bother the pinging chats with our errors.
+    if let Err(e) = auto_reply(msg, message, state, interval).await {
+        error!(
+            "Failed to handle the ping in {}: {:?}",
+            message.chat().id(),
+            e
+        );
+    }
+
+    Ok(())
+}
+
+/// Record the ping, and reply the AFK status if the chat is due.
+async fn auto_reply(
+    msg: &ModuleMessage,
+    message: &Message,
+    state: &AfkState,
+    interval: Duration,
+) -> ModuleResult {
+    let chat = message.chat();
+    let sender = message.sender();
+    let ping = Ping {
+        chat_id: chat.id(),
+        chat_name: chat.name().to_string(),
+        sender_id: match &sender {
+            Some(Chat::User(user)) => Some(user.id()),
+            _ => None,
+        },
+        sender_name: match &sender {
+            Some(Chat::User(user)) => user.full_name(),
+            Some(sender) => sender.name().to_string(),
+            None => chat.name().to_string(),
+        },
+        message_id: message.id(),
+        link: message_link(&chat, message.id()),
+        preview: preview(message),
+    };
+
+    let status = match state.ping(ping, interval).await? {
+        Some(status) => status,
+        None => return Ok(()),
+    };
+
+    let text = FormattedText::new()
+        .push("💤 我目前不在，已經離開 ")
+        .bold(format_elapsed(&status))
+        .push("了。");
+    let text = match &status.reason {
+        Some(reason) => text.push("\n原因：").push(reason),
+        None => text,
+    };
+    msg.reply(text).await?;
+
+    Ok(())
+}
+
+/// Build the digest of the pings received while being away.
+fn digest(status: &AfkStatus) -> FormattedText {
+    let text = FormattedText::new()
+        .push("👋 歡迎回來！你離開了 ")
+        .bold(format_elapsed(status))
+        .push("。");
+    if status.ping_count() == 0 {
+        return text.push("\n期間沒有人找你。");
+    }
+
+    let mut text = text.push(format!("\n期間有 {} 則訊息找你：", status.ping_count()));
+    for ping in status.pings.iter().take(MAX_PINGS) {
+        text = text.push("\n• ");
+        text = match ping.sender_id {
+            Some(sender_id) => text.mention(&ping.sender_name, sender_id),
+            None => text.push(&ping.sender_name),
+        };
+
+        // The chat of a private message is the sender itself.
+        text = if ping.sender_id == Some(ping.chat_id) {
+            text.push("（私訊）：")
+        } else {
+            text.push(format!("（{}）：", ping.chat_name))
+        };
+        text = match &ping.link {
+            Some(link) => text.link(&ping.preview, link.clone()),
+            None => text.push(&ping.preview),
+        };
+    }
+
+    if status.ping_count() > MAX_PINGS {
+        let rest = status.ping_count() - MAX_PINGS;
+        text = text.push(format!("\n…以及其他 {} 則。", rest));
+    }
+
+    text
+}
+
+/// Format how long the owner has been away,
+/// dropping the seconds if it is over a minute.
+fn format_elapsed(status: &AfkStatus) -> String {
+    let secs = status.elapsed().as_secs();
+    let secs = if secs >= 60 { secs - secs % 60 } else { secs };
+
+    format_duration(Duration::from_secs(secs))
+}
+
+/// Get the preview of the message text for the digest.
+fn preview(message: &Message) -> String {
+    let text = message
+        .text()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.is_empty() {
+        return if message.media().is_some() {
+            "[媒體]".to_string()
+        } else {
+            "[訊息]".to_string()
+        };
+    }
+
+    match text.char_indices().nth(PREVIEW_LENGTH) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+/// Check if the message pings the owner: a private message or a mention.
+fn is_ping(message: &Message) -> bool {
+    match message.sender() {
+        // Don't reply to the bots, or we may talk to each other forever.
+        Some(Chat::User(user)) if user.is_bot() => false,
+        _ => matches!(message.chat(), Chat::User(_)) || message.mentioned(),
+    }
+}
+
+/// Get the reason if the message is `!afk [reason]` sent by us.
+///
+/// It returns `Some(None)` if no reason is specified.
+fn extract_reason(message: &Message) -> Option<Option<String>> {
+    let rest = message.text().trim().strip_prefix(CMD)?;
+
+    // `!afkxxx` is not our command.
+    if !(rest.is_empty() || rest.starts_with(char::is_whitespace)) || !is_root_user(message) {
+        return None;
+    }
+
+    let reason = rest.trim();
+    Some((!reason.is_empty()).then(|| reason.to_string()))
+}
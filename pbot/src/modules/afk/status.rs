@@ -0,0 +1,148 @@
+//! PBot: Modules: AfkModule: AFK Status
+//!
+//! The status of being away, with the pings received meanwhile
+//! and when each chat was auto-replied the last time.
+//!
+//! It is persisted in the storage, so the status and the pings
+//! survive restarts.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::storage::{Persisted, StorageActor};
+use actix::Addr;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// The storage namespace of the AFK status.
+const STORAGE_NAMESPACE: &str = "afk";
+
+/// The maximum number of pings kept, which are all listed in the digest.
+/// The pings beyond it are only counted.
+pub const MAX_PINGS: usize = 50;
+
+/// A message pinging the owner while being away.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ping {
+    /// The ID of the chat where the message is.
+    pub chat_id: i32,
+    /// The name of the chat where the message is.
+    pub chat_name: String,
+    /// The ID of the sender, if it is a user.
+    pub sender_id: Option<i32>,
+    /// The name of the sender.
+    pub sender_name: String,
+    /// The ID of the message.
+    pub message_id: i32,
+    /// The link to the message, if the chat has message links.
+    pub link: Option<String>,
+    /// The preview of the message text.
+    pub preview: String,
+}
+
+/// The status of being away.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AfkStatus {
+    /// Why the owner is away.
+    pub reason: Option<String>,
+    /// Since when the owner is away, in UNIX timestamp.
+    pub since: i64,
+    /// The pings received meanwhile, from the oldest to the newest.
+    #[serde(default)]
+    pub pings: Vec<Ping>,
+    /// The number of pings received beyond [`MAX_PINGS`].
+    #[serde(default)]
+    pub dropped: usize,
+    /// When each chat was auto-replied the last time, keyed by the chat IDs.
+    #[serde(default)]
+    pub replied: HashMap<i32, i64>,
+}
+
+impl AfkStatus {
+    /// How long the owner has been away.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs((Utc::now().timestamp() - self.since).max(0) as u64)
+    }
+
+    /// The number of pings received meanwhile, including the dropped ones.
+    pub fn ping_count(&self) -> usize {
+        self.pings.len() + self.dropped
+    }
+}
+
+/// The persisted AFK status.
+pub struct AfkState {
+    /// The status, or `None` if the owner is not away.
+    ///
+    /// It is persisted as `null` after coming back.
+    status: Persisted<Option<AfkStatus>>,
+}
+
+impl AfkState {
+    /// Load the AFK status from the storage.
+    pub async fn load(storage: Addr<StorageActor>) -> anyhow::Result<Self> {
+        Ok(Self {
+            status: Persisted::load(storage, STORAGE_NAMESPACE).await?,
+        })
+    }
+
+    /// Mark the owner away with the reason, and persist it.
+    ///
+    /// If the owner is already away, only the reason is updated,
+    /// and the pings received so far are kept.
+    pub async fn set(&self, reason: Option<String>) -> anyhow::Result<()> {
+        self.status
+            .update(|status| {
+                let status = status.get_or_insert_with(|| AfkStatus {
+                    since: Utc::now().timestamp(),
+                    ..Default::default()
+                });
+                status.reason = reason;
+                Some(())
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Mark the owner back, and persist it.
+    ///
+    /// It returns the status before coming back, or `None`
+    /// if the owner was not away.
+    pub async fn clear(&self) -> anyhow::Result<Option<AfkStatus>> {
+        self.status.update(Option::take).await
+    }
+
+    /// Record the ping if the owner is away, and persist it.
+    ///
+    /// Only the first [`MAX_PINGS`] pings are kept, the rest are counted.
+    ///
+    /// It returns the status if the chat of the ping should be
+    /// auto-replied, which happens once per `interval`.
+    pub async fn ping(&self, ping: Ping, interval: Duration) -> anyhow::Result<Option<AfkStatus>> {
+        let status = self
+            .status
+            .update(|status| {
+                let status = status.as_mut()?;
+
+                let now = Utc::now().timestamp();
+                let due = status
+                    .replied
+                    .get(&ping.chat_id)
+                    .is_none_or(|last| now - last >= interval.as_secs() as i64);
+                if due {
+                    status.replied.insert(ping.chat_id, now);
+                }
+                if status.pings.len() < MAX_PINGS {
+                    status.pings.push(ping);
+                } else {
+                    status.dropped += 1;
+                }
+
+                Some(due.then(|| status.clone()))
+            })
+            .await?;
+
+        Ok(status.flatten())
+    }
+}
@@ -0,0 +1,93 @@
+//! PBot: Modules: AutoFwdModule
+//!
+//! Forward the incoming messages automatically according to
+//! the configured rules, without any command.
+//!
+//! See [`rules`] for the format of the rules file.
+
+pub mod rules;
+
+use std::sync::Arc;
+
+use actix::prelude::*;
+use log::{error, info, warn};
+use pbot_modules_derive::{ModuleActivator, ModuleActor, ModuleMeta};
+
+use crate::telegram::client::commands::ForwardMessagesCommand;
+
+use self::rules::Rule;
+
+use super::base::{error::ModuleResult, response::RESPONSE_PREFIX, ModuleMessage};
+
+/// The AutoFwdModule actor.
+#[derive(Clone, ModuleActor, ModuleActivator, ModuleMeta)]
+#[name = "AutoFwdModule"]
+pub struct AutoFwdModuleActor {
+    /// The rules to match the messages with.
+    pub rules: Arc<Vec<Rule>>,
+    /// Only log the matching messages of every rule instead of forwarding them.
+    pub dry_run: bool,
+}
+
+impl Handler<ModuleMessage> for AutoFwdModuleActor {
+    type Result = ResponseActFuture<Self, ModuleResult>;
+
+    fn handle(&mut self, msg: ModuleMessage, _: &mut Self::Context) -> Self::Result {
+        // Clone the fields of self to move into the following block.
+        let rules = self.rules.clone();
+        let dry_run = self.dry_run;
+
+        async move {
+            // Take a snapshot of the message, so we don't need to lock it again.
+            let message = msg.snapshot().await;
+
+            // Don't forward our own responses. The others may start
+            // their messages with the prefix too, so check the sender.
+            if message.outgoing() && message.text().starts_with(RESPONSE_PREFIX) {
+                return Ok(());
+            }
+
+            for rule in rules.iter().filter(|rule| rule.matches(&message)) {
+                if !rule.acquire().await {
+                    warn!(
+                        "AutoFwd rule `{}` reached its rate limit; message {} dropped.",
+                        rule.name,
+                        message.id()
+                    );
+                    continue;
+                }
+
+                if dry_run || rule.dry_run {
+                    info!(
+                        "AutoFwd rule `{}` matched message {} in {}. (dry run)",
+                        rule.name,
+                        message.id(),
+                        message.chat().id()
+                    );
+                    continue;
+                }
+
+                let result = msg
+                    .handle
+                    .send(ForwardMessagesCommand {
+                        forward_to: rule.target.clone(),
+                        message_ids: vec![message.id()],
+                        message_chat: Arc::new(message.chat()),
+                        options: Default::default(),
+                    })
+                    .await?;
+
+                // Nobody is waiting for the automatic forwards, so we only log the failures.
+                match result.get(&message.id()) {
+                    Some(Ok(_)) => info!("💬 AutoFwd rule `{}` forwarded a message!", rule.name),
+                    Some(Err(e)) => error!("AutoFwd rule `{}` failed to forward: {}", rule.name, e),
+                    None => error!("AutoFwd rule `{}` got no forward result.", rule.name),
+                }
+            }
+
+            Ok(())
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
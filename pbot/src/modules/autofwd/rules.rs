@@ -0,0 +1,344 @@
+//! PBot: Modules: AutoFwdModule: Rules
+//!
+//! The rules deciding which messages to forward automatically.
+//!
+//! The rules are configured in a JSON file, for example:
+//!
+//! ```json
+//! [
+//!   {
+//!     "name": "news",
+//!     "sources": [1145141919],
+//!     "senders": [],
+//!     "keywords": ["release"],
+//!     "pattern": "v\\d+\\.\\d+",
+//!     "media": ["text", "photo"],
+//!     "target": 1919810,
+//!     "rate_limit": { "count": 10, "per_secs": 60 },
+//!     "dry_run": false
+//!   }
+//! ]
+//! ```
+
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use grammers_client::types::{Chat, Media, Message};
+use regex::Regex;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+/// The kinds of the message content which a rule can filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    /// The message without media.
+    Text,
+    /// The photo.
+    Photo,
+    /// The document with a video MIME type.
+    Video,
+    /// The document with an audio MIME type.
+    Audio,
+    /// The other documents.
+    Document,
+    /// The sticker.
+    Sticker,
+}
+
+impl MediaKind {
+    /// Get the kind of the message content.
+    pub fn of(message: &Message) -> Self {
+        match message.media() {
+            None => Self::Text,
+            Some(Media::Photo(_)) => Self::Photo,
+            Some(Media::Sticker(_)) => Self::Sticker,
+            Some(Media::Document(document)) => match document.mime_type() {
+                Some(mime) if mime.starts_with("video/") => Self::Video,
+                Some(mime) if mime.starts_with("audio/") => Self::Audio,
+                _ => Self::Document,
+            },
+            // The media we don't know yet are treated as documents.
+            Some(_) => Self::Document,
+        }
+    }
+}
+
+/// The rate limit of a rule.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct RateLimit {
+    /// The maximum amount of forwards in the window.
+    pub count: usize,
+    /// The length of the window in seconds.
+    pub per_secs: u64,
+}
+
+/// The configuration of a rule, as written in the rules file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RuleConfig {
+    /// The name of the rule, for logging.
+    pub name: String,
+    /// The IDs of the chats to watch.
+    pub sources: Vec<i32>,
+    /// The IDs of the senders to accept. Empty means anyone.
+    #[serde(default)]
+    pub senders: Vec<i32>,
+    /// The keywords to look for, case-insensitively.
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// The regular expression to look for.
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// The kinds of the content to accept. Empty means any kind.
+    #[serde(default)]
+    pub media: Vec<MediaKind>,
+    /// The ID of the chat to forward to.
+    pub target: i32,
+    /// The rate limit of this rule, if any.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    /// Only log the matching messages instead of forwarding them.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Read the rule configurations from the JSON file.
+pub fn load_rules(path: impl AsRef<Path>) -> anyhow::Result<Vec<RuleConfig>> {
+    let content = std::fs::read(path)?;
+
+    Ok(serde_json::from_slice(&content)?)
+}
+
+/// A compiled rule.
+pub struct Rule {
+    /// The name of the rule, for logging.
+    pub name: String,
+    /// The chat to forward to.
+    pub target: Arc<Chat>,
+    /// Only log the matching messages instead of forwarding them.
+    pub dry_run: bool,
+    /// The IDs of the chats to watch.
+    sources: HashSet<i32>,
+    /// The IDs of the senders to accept.
+    senders: HashSet<i32>,
+    /// The lowercased keywords to look for.
+    keywords: Vec<String>,
+    /// The regular expression to look for.
+    pattern: Option<Regex>,
+    /// The kinds of the content to accept.
+    media: HashSet<MediaKind>,
+    /// The rate limit of this rule.
+    rate_limit: Option<RateLimit>,
+    /// When the recent forwards in the rate limit window happened.
+    recent: Mutex<VecDeque<Instant>>,
+}
+
+impl Rule {
+    /// Compile the rule with the resolved target chat.
+    pub fn compile(config: RuleConfig, target: Chat) -> anyhow::Result<Self> {
+        let pattern = config.pattern.as_deref().map(Regex::new).transpose()?;
+
+        Ok(Self {
+            name: config.name,
+            target: Arc::new(target),
+            dry_run: config.dry_run,
+            sources: config.sources.into_iter().collect(),
+            senders: config.senders.into_iter().collect(),
+            keywords: config
+                .keywords
+                .iter()
+                .map(|keyword| keyword.to_lowercase())
+                .collect(),
+            pattern,
+            media: config.media.into_iter().collect(),
+            rate_limit: config.rate_limit,
+            recent: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Whether the message matches this rule.
+    pub fn matches(&self, message: &Message) -> bool {
+        self.matches_content(
+            message.chat().id(),
+            message.sender().as_ref().map(Chat::id),
+            MediaKind::of(message),
+            message.text(),
+        )
+    }
+
+    /// Whether the message in the chat, from the sender and
+    /// with the content matches this rule.
+    fn matches_content(
+        &self,
+        chat_id: i32,
+        sender_id: Option<i32>,
+        kind: MediaKind,
+        text: &str,
+    ) -> bool {
+        // Never forward the messages in the target back to itself.
+        if !self.sources.contains(&chat_id) || chat_id == self.target.id() {
+            return false;
+        }
+
+        if !self.senders.is_empty() {
+            match sender_id {
+                Some(sender_id) if self.senders.contains(&sender_id) => {}
+                _ => return false,
+            }
+        }
+
+        if !self.media.is_empty() && !self.media.contains(&kind) {
+            return false;
+        }
+
+        // The text matches if it has any keyword or matches the pattern.
+        if self.keywords.is_empty() && self.pattern.is_none() {
+            return true;
+        }
+        let lowercased_text = text.to_lowercase();
+
+        self.keywords
+            .iter()
+            .any(|keyword| lowercased_text.contains(keyword))
+            || matches!(&self.pattern, Some(pattern) if pattern.is_match(text))
+    }
+
+    /// Take a slot of the rate limit.
+    ///
+    /// It returns `false` if the rule has reached its rate limit.
+    pub async fn acquire(&self) -> bool {
+        self.acquire_at(Instant::now()).await
+    }
+
+    /// Take a slot of the rate limit at the moment.
+    async fn acquire_at(&self, now: Instant) -> bool {
+        let limit = match self.rate_limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+        let window = Duration::from_secs(limit.per_secs);
+        let mut recent = self.recent.lock().await;
+
+        // Forget the forwards outside the window.
+        while matches!(recent.front(), Some(at) if now.duration_since(*at) >= window) {
+            recent.pop_front();
+        }
+
+        if recent.len() < limit.count {
+            recent.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grammers_client::types::chat::PackedChat;
+
+    use super::*;
+
+    /// The ID of the target chat of the rules.
+    const TARGET: i32 = 1919810;
+
+    fn rule(config: &str) -> Rule {
+        let config = serde_json::from_str(config).expect("malformed rule");
+        // A packed user, which is `[type, length, id]`.
+        let mut target = vec![0b0000_0010, 6];
+        target.extend(TARGET.to_le_bytes());
+        let target = PackedChat::from_bytes(&target).unwrap().unpack();
+
+        Rule::compile(config, target).expect("failed to compile the rule")
+    }
+
+    #[test]
+    fn matches_the_sources_only() {
+        let rule = rule(r#"{"name": "all", "sources": [1, 1919810], "target": 1919810}"#);
+
+        assert!(rule.matches_content(1, None, MediaKind::Text, ""));
+        assert!(!rule.matches_content(2, None, MediaKind::Text, ""));
+        // Never forward the target back to itself.
+        assert!(!rule.matches_content(TARGET, None, MediaKind::Text, ""));
+    }
+
+    #[test]
+    fn matches_the_senders() {
+        let rule = rule(r#"{"name": "s", "sources": [1], "senders": [42], "target": 1919810}"#);
+
+        assert!(rule.matches_content(1, Some(42), MediaKind::Text, ""));
+        assert!(!rule.matches_content(1, Some(43), MediaKind::Text, ""));
+        assert!(!rule.matches_content(1, None, MediaKind::Text, ""));
+    }
+
+    #[test]
+    fn matches_the_media_kinds() {
+        let rule = rule(
+            r#"{"name": "m", "sources": [1], "media": ["photo", "video"], "target": 1919810}"#,
+        );
+
+        assert!(rule.matches_content(1, None, MediaKind::Photo, ""));
+        assert!(rule.matches_content(1, None, MediaKind::Video, ""));
+        assert!(!rule.matches_content(1, None, MediaKind::Text, ""));
+        assert!(!rule.matches_content(1, None, MediaKind::Sticker, ""));
+    }
+
+    #[test]
+    fn matches_any_keyword_or_the_pattern() {
+        let rule = rule(
+            r#"{
+                "name": "news",
+                "sources": [1],
+                "keywords": ["Release"],
+                "pattern": "v\\d+\\.\\d+",
+                "target": 1919810
+            }"#,
+        );
+
+        assert!(rule.matches_content(1, None, MediaKind::Text, "New RELEASE today"));
+        assert!(rule.matches_content(1, None, MediaKind::Text, "pbot v0.2 is out"));
+        assert!(!rule.matches_content(1, None, MediaKind::Text, "pbot v2 is out"));
+        assert!(!rule.matches_content(1, None, MediaKind::Photo, ""));
+    }
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        let config =
+            serde_json::from_str(r#"{"name": "bad", "sources": [1], "pattern": "(", "target": 1}"#)
+                .unwrap();
+        let target = PackedChat::from_bytes(&[0b0000_0010, 6, 1, 0, 0, 0])
+            .unwrap()
+            .unpack();
+
+        assert!(Rule::compile(config, target).is_err());
+    }
+
+    #[tokio::test]
+    async fn acquire_without_rate_limit() {
+        let rule = rule(r#"{"name": "all", "sources": [1], "target": 1919810}"#);
+
+        for _ in 0..100 {
+            assert!(rule.acquire().await);
+        }
+    }
+
+    #[tokio::test]
+    async fn acquire_slides_the_window() {
+        let rule = rule(
+            r#"{"name": "r", "sources": [1], "target": 1919810,
+                "rate_limit": {"count": 2, "per_secs": 60}}"#,
+        );
+        let start = Instant::now();
+        let after = |secs| start + Duration::from_secs(secs);
+
+        assert!(rule.acquire_at(after(0)).await);
+        assert!(rule.acquire_at(after(30)).await);
+        assert!(!rule.acquire_at(after(59)).await);
+        // The first slot is freed once it leaves the window.
+        assert!(rule.acquire_at(after(60)).await);
+        assert!(!rule.acquire_at(after(89)).await);
+        assert!(rule.acquire_at(after(90)).await);
+    }
+}
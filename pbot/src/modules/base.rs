@@ -2,6 +2,9 @@
 //!
 //! The base structure and traits of the PBot modules.
 
+pub mod error;
+pub mod response;
+
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -10,6 +13,9 @@ use actix::prelude::*;
 use crate::telegram::client::ClientActor;
 use grammers_client::types;
 
+use self::error::ModuleResult;
+use self::response::ResponseOptions;
+
 /// The information of the module which has been initiated and activated.
 #[derive(Clone)]
 pub struct ActivatedModuleInfo {
@@ -22,13 +28,20 @@ pub struct ActivatedModuleInfo {
 }
 
 /// The message that a PBot Module would receive.
+///
+/// The module returns [`error::ModuleError`] if it failed to handle
+/// the message, and the error will be rendered to the invoking chat.
 #[derive(Message)]
-#[rtype(result = "anyhow::Result<()>")]
+#[rtype(result = "ModuleResult")]
 pub struct ModuleMessage {
     /// The address to a [`ClientActor`] instance.
     pub handle: Addr<ClientActor>,
     /// The message received.
     pub message: Arc<RwLock<types::Message>>,
+    /// The name of the module receiving this message.
+    pub module: &'static str,
+    /// The options of the responses to this message.
+    pub options: Arc<ResponseOptions>,
 }
 
 /// The metadata that a PBot Module should have.
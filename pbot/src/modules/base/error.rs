@@ -0,0 +1,296 @@
+//! PBot: Modules: Module Errors
+//!
+//! The errors which a module can return from handling [`super::ModuleMessage`].
+//!
+//! [`crate::telegram::update::ClientModuleExecutor`] logs the full
+//! context of the errors, and renders them back to the invoking chat
+//! with [`ModuleError::render`], so the user knows what happened.
+
+use std::fmt;
+
+use actix::MailboxError;
+use grammers_client::types::iter_buffer::InvocationError;
+
+use crate::telegram::format::FormattedText;
+
+/// The RPC errors about the missing privileges, besides the ones
+/// with the code 403 or ending with `_FORBIDDEN`.
+///
+/// Telegram reports most of them with the code 400.
+const PRIVILEGE_ERRORS: &[&str] = &[
+    "CHAT_ADMIN_REQUIRED",
+    "CHAT_FORWARDS_RESTRICTED",
+    "USER_ADMIN_INVALID",
+    "USER_NOT_MUTUAL_CONTACT",
+    "USER_NOT_PARTICIPANT",
+    "USER_PRIVACY_RESTRICTED",
+    "USER_RESTRICTED",
+];
+
+/// Check if the RPC error is about the missing privileges.
+fn is_privilege_error(code: i32, name: &str) -> bool {
+    code == 403 || name.ends_with("_FORBIDDEN") || PRIVILEGE_ERRORS.contains(&name)
+}
+
+/// The result of handling [`super::ModuleMessage`].
+pub type ModuleResult = Result<(), ModuleError>;
+
+/// The error which a module can return.
+#[derive(Debug)]
+pub enum ModuleError {
+    /// The command was used incorrectly.
+    Usage {
+        /// What was wrong.
+        reason: String,
+        /// The correct usage of the command, such as `!addrank <頭銜>`.
+        usage: Option<String>,
+    },
+    /// The user or the account operator is not allowed to do this.
+    PermissionDenied {
+        /// Why it is not allowed.
+        reason: String,
+    },
+    /// Telegram refused or failed the request.
+    Rpc {
+        /// What we were requesting, such as `forwarding the message`.
+        context: Option<String>,
+        /// The error from Telegram.
+        error: InvocationError,
+    },
+    /// Something went wrong inside PBot.
+    Internal(anyhow::Error),
+}
+
+impl ModuleError {
+    /// Create a [`ModuleError::Usage`] error without the usage.
+    pub fn usage(reason: impl Into<String>) -> Self {
+        Self::Usage {
+            reason: reason.into(),
+            usage: None,
+        }
+    }
+
+    /// Create a [`ModuleError::Usage`] error with the correct usage.
+    pub fn usage_with(reason: impl Into<String>, usage: impl Into<String>) -> Self {
+        Self::Usage {
+            reason: reason.into(),
+            usage: Some(usage.into()),
+        }
+    }
+
+    /// Create a [`ModuleError::PermissionDenied`] error.
+    pub fn permission_denied(reason: impl Into<String>) -> Self {
+        Self::PermissionDenied {
+            reason: reason.into(),
+        }
+    }
+
+    /// Create a [`ModuleError::Rpc`] error with what we were requesting.
+    pub fn rpc(context: impl Into<String>, error: InvocationError) -> Self {
+        Self::Rpc {
+            context: Some(context.into()),
+            error,
+        }
+    }
+
+    /// Render the error into the text to show in the chat.
+    ///
+    /// The details, such as the name of RPC errors and the causes
+    /// of internal errors, are only included if `with_details` is set.
+    pub fn render(&self, with_details: bool) -> FormattedText {
+        match self {
+            Self::Usage { reason, usage } => {
+                let text = FormattedText::plain(format!("⚠️ {}", reason));
+
+                match usage {
+                    Some(usage) => text.push("\n用法：").code(usage),
+                    None => text,
+                }
+            }
+            Self::PermissionDenied { reason } => {
+                FormattedText::plain(format!("🚫 權限不足：{}", reason))
+            }
+            Self::Rpc { error, .. } => {
+                let text = match error {
+                    InvocationError::Rpc(e) if is_privilege_error(e.code, &e.name) => {
+                        FormattedText::plain("🚫 權限不足，Telegram 拒絕了這個請求。")
+                    }
+                    InvocationError::Rpc(e) if e.code == 420 => FormattedText::plain(format!(
+                        "⏳ 請求過於頻繁，請於 {} 秒後再試。",
+                        e.value.unwrap_or_default()
+                    )),
+                    _ => FormattedText::plain("📡 Telegram 請求失敗。"),
+                };
+
+                if with_details {
+                    text.push("\n").code(self.to_string())
+                } else {
+                    text
+                }
+            }
+            Self::Internal(e) => {
+                let text = FormattedText::plain("💥 PBot 發生內部錯誤。");
+
+                if with_details {
+                    text.push("\n").code(format!("{:#}", e))
+                } else {
+                    text
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Usage { reason, .. } => write!(f, "usage error: {}", reason),
+            Self::PermissionDenied { reason } => write!(f, "permission denied: {}", reason),
+            Self::Rpc {
+                context: Some(context),
+                error,
+            } => write!(f, "{}: {}", context, error),
+            Self::Rpc {
+                context: None,
+                error,
+            } => write!(f, "{}", error),
+            Self::Internal(e) => write!(f, "internal error: {:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for ModuleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Rpc { error, .. } => Some(error),
+            Self::Internal(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<InvocationError> for ModuleError {
+    fn from(error: InvocationError) -> Self {
+        Self::Rpc {
+            context: None,
+            error,
+        }
+    }
+}
+
+impl From<MailboxError> for ModuleError {
+    fn from(error: MailboxError) -> Self {
+        Self::Internal(error.into())
+    }
+}
+
+impl From<anyhow::Error> for ModuleError {
+    fn from(error: anyhow::Error) -> Self {
+        // Keep the RPC errors wrapped by anyhow as RPC errors,
+        // along with the context attached to them.
+        let message = error.to_string();
+
+        match error.downcast::<InvocationError>() {
+            Ok(error) => Self::Rpc {
+                context: (message != error.to_string()).then_some(message),
+                error,
+            },
+            Err(error) => Self::Internal(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grammers_mtproto::mtp::RpcError;
+
+    use super::*;
+
+    fn rpc_error(code: i32, name: &str, value: Option<u32>) -> InvocationError {
+        InvocationError::Rpc(RpcError {
+            code,
+            name: name.to_string(),
+            value,
+        })
+    }
+
+    fn render(error: ModuleError) -> String {
+        error.render(false).text().to_string()
+    }
+
+    #[test]
+    fn render_privilege_errors() {
+        for (code, name) in [
+            (403, "MESSAGE_AUTHOR_REQUIRED"),
+            (400, "CHAT_ADMIN_REQUIRED"),
+            (400, "RIGHT_FORBIDDEN"),
+            (400, "USER_NOT_MUTUAL_CONTACT"),
+        ] {
+            assert_eq!(
+                render(rpc_error(code, name, None).into()),
+                "🚫 權限不足，Telegram 拒絕了這個請求。",
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn render_other_rpc_errors() {
+        assert_eq!(
+            render(rpc_error(420, "FLOOD_WAIT", Some(17)).into()),
+            "⏳ 請求過於頻繁，請於 17 秒後再試。"
+        );
+        assert_eq!(
+            render(rpc_error(400, "MESSAGE_ID_INVALID", None).into()),
+            "📡 Telegram 請求失敗。"
+        );
+    }
+
+    #[test]
+    fn render_details_only_when_asked() {
+        let error = ModuleError::rpc("forwarding", rpc_error(400, "MESSAGE_ID_INVALID", None));
+
+        assert!(!error.render(false).text().contains("MESSAGE_ID_INVALID"));
+        assert!(error.render(true).text().contains("forwarding"));
+        assert!(error.render(true).text().contains("MESSAGE_ID_INVALID"));
+    }
+
+    #[test]
+    fn render_usage_and_permission_errors() {
+        let text = ModuleError::usage_with("缺少頭銜。", "!addrank <頭銜>").render(false);
+        assert_eq!(text.text(), "⚠️ 缺少頭銜。\n用法：!addrank <頭銜>");
+        assert_eq!(text.entities().len(), 1);
+
+        assert_eq!(
+            render(ModuleError::permission_denied("只有擁有者可以使用。")),
+            "🚫 權限不足：只有擁有者可以使用。"
+        );
+    }
+
+    #[test]
+    fn anyhow_keeps_rpc_errors() {
+        let error = ModuleError::from(anyhow::Error::from(rpc_error(400, "PEER_ID_INVALID", None)));
+        assert!(matches!(
+            error,
+            ModuleError::Rpc { context: None, error: InvocationError::Rpc(ref e) }
+                if e.name == "PEER_ID_INVALID"
+        ));
+
+        let error = ModuleError::from(
+            anyhow::Error::from(rpc_error(400, "PEER_ID_INVALID", None)).context("resolving"),
+        );
+        assert!(matches!(
+            error,
+            ModuleError::Rpc { context: Some(ref context), .. } if context == "resolving"
+        ));
+    }
+
+    #[test]
+    fn anyhow_keeps_other_errors_internal() {
+        let error = ModuleError::from(anyhow::anyhow!("broken storage"));
+
+        assert!(matches!(error, ModuleError::Internal(_)));
+        assert_eq!(error.to_string(), "internal error: broken storage");
+    }
+}
@@ -0,0 +1,427 @@
+//! PBot: Modules: Response Helpers
+//!
+//! The helpers for modules to answer the received [`ModuleMessage`].
+//!
+//! They send every request through [`crate::telegram::client::ClientActor`],
+//! add the `[PBOT]` prefix, split the text exceeding the length limit
+//! of Telegram, and never hold the lock of the received message
+//! while waiting for Telegram.
+//!
+//! They accept anything converting into [`FormattedText`], so
+//! a plain `&str` works as well as a formatted text.
+//!
+//! The responses can self-destruct after a while according to
+//! [`SelfDestructConfig`], which is configurable per module and per chat.
+//!
+//! They return the [`SentMessage`]s carrying the response, which are
+//! only stand-ins when the client is in the dry-run mode.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix::Addr;
+use anyhow::Context as _;
+use grammers_client::types;
+
+use crate::telegram::cleanup::{commands::ScheduleDeletionCommand, CleanupActor};
+use crate::telegram::client::{
+    commands::{EditMessageCommand, GetMeCommand, SendMessageCommand, UploadFileCommand},
+    outgoing::{OutgoingMessage, SentMessage, UploadedFile},
+};
+use crate::telegram::format::{message_link, FormattedText};
+use crate::utils::getenv_pairs;
+
+use super::ModuleMessage;
+
+/// The prefix of every response sent by PBot.
+pub const RESPONSE_PREFIX: &str = "[PBOT]";
+
+/// The maximum length of a message, in UTF-16 code units.
+pub const MAX_MESSAGE_LENGTH: usize = 4096;
+
+/// What to delete when a response self-destructs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelfDestructTarget {
+    /// Delete the feedback sent by PBot.
+    #[default]
+    Feedback,
+    /// Delete the command message which PBot answered.
+    Command,
+    /// Delete both the feedback and the command message.
+    Both,
+}
+
+/// The policy of self-destructing responses.
+///
+/// The delay of a chat takes precedence over the delay of a module,
+/// and the delay of a module takes precedence over the default delay.
+/// A zero delay means never self-destructing.
+#[derive(Clone, Debug, Default)]
+pub struct SelfDestructConfig {
+    /// The default delay of all modules.
+    pub default: Option<Duration>,
+    /// The delays of the modules, keyed by the module names.
+    pub modules: HashMap<String, Duration>,
+    /// The delays of the chats, keyed by the chat IDs.
+    pub chats: HashMap<i32, Duration>,
+    /// What to delete.
+    pub target: SelfDestructTarget,
+}
+
+impl SelfDestructConfig {
+    /// Read the policy from the environment variables.
+    ///
+    /// * `TG_SELF_DESTRUCT_AFTER`: the default delay in seconds.
+    /// * `TG_SELF_DESTRUCT_MODULES`: the delays of modules, such as `FwdModule:10,AddRankModule:0`.
+    /// * `TG_SELF_DESTRUCT_CHATS`: the delays of chats, such as `1145141919:30`.
+    /// * `TG_SELF_DESTRUCT_TARGET`: `feedback`, `command` or `both`.
+    pub fn from_env() -> Self {
+        /// Parse the list of `key:seconds`.
+        fn parse_delays(envvar: &str) -> Vec<(String, Duration)> {
+            getenv_pairs(envvar)
+                .into_iter()
+                .map(|(key, secs)| {
+                    let secs = secs
+                        .parse()
+                        .unwrap_or_else(|_| panic!("{} should have seconds in u64", envvar));
+
+                    (key, Duration::from_secs(secs))
+                })
+                .collect()
+        }
+
+        Self {
+            default: crate::getenv_opt!("TG_SELF_DESTRUCT_AFTER", u64).map(Duration::from_secs),
+            modules: parse_delays("TG_SELF_DESTRUCT_MODULES")
+                .into_iter()
+                .collect(),
+            chats: parse_delays("TG_SELF_DESTRUCT_CHATS")
+                .into_iter()
+                .map(|(chat, delay)| {
+                    let chat = chat
+                        .parse()
+                        .expect("TG_SELF_DESTRUCT_CHATS should have chat IDs in i32");
+                    (chat, delay)
+                })
+                .collect(),
+            target: match crate::getenv_opt!("TG_SELF_DESTRUCT_TARGET").as_deref() {
+                None | Some("feedback") => SelfDestructTarget::Feedback,
+                Some("command") => SelfDestructTarget::Command,
+                Some("both") => SelfDestructTarget::Both,
+                Some(_) => panic!("TG_SELF_DESTRUCT_TARGET should be feedback, command or both"),
+            },
+        }
+    }
+
+    /// Get the delay of the module in the chat.
+    ///
+    /// It returns `None` if the responses should not self-destruct.
+    pub fn delay_of(&self, module: &str, chat_id: i32) -> Option<Duration> {
+        self.chats
+            .get(&chat_id)
+            .or_else(|| self.modules.get(module))
+            .or(self.default.as_ref())
+            .copied()
+            .filter(|delay| !delay.is_zero())
+    }
+}
+
+/// The options of the responses, shared by every module.
+#[derive(Clone, Default)]
+pub struct ResponseOptions {
+    /// The actor deleting the self-destructing responses.
+    ///
+    /// The responses never self-destruct if it is `None`.
+    pub cleanup: Option<Addr<CleanupActor>>,
+    /// The policy of self-destructing responses.
+    pub self_destruct: SelfDestructConfig,
+    /// Send the errors to Saved Messages instead of the chat.
+    pub errors_to_saved_messages: bool,
+    /// Show the details of the errors, such as the names of RPC errors.
+    pub error_details: bool,
+}
+
+impl ModuleMessage {
+    /// Get a snapshot of the received message.
+    ///
+    /// The lock of the message is released immediately,
+    /// so you can call any method of the snapshot without
+    /// worrying about deadlocks.
+    pub async fn snapshot(&self) -> types::Message {
+        self.message.read().await.clone()
+    }
+
+    /// Send the text to the chat of the received message, without replying to it.
+    ///
+    /// It returns the messages carrying the response in order.
+    pub async fn respond(
+        &self,
+        text: impl Into<FormattedText>,
+    ) -> anyhow::Result<Vec<SentMessage>> {
+        let message = self.snapshot().await;
+        let messages = self
+            .send_chunks(&message.chat(), None, prefixed(text))
+            .await?;
+
+        self.self_destruct(&message, &messages);
+        Ok(messages)
+    }
+
+    /// Reply the text to the received message.
+    ///
+    /// It returns the messages carrying the response in order.
+    pub async fn reply(&self, text: impl Into<FormattedText>) -> anyhow::Result<Vec<SentMessage>> {
+        let message = self.snapshot().await;
+        let messages = self
+            .send_chunks(&message.chat(), Some(message.id()), prefixed(text))
+            .await?;
+
+        self.self_destruct(&message, &messages);
+        Ok(messages)
+    }
+
+    /// Edit the received message if it is ours, otherwise reply to it.
+    ///
+    /// When editing, the first element of the returned messages is
+    /// the received message itself, which still carries the old content.
+    pub async fn edit_or_reply(
+        &self,
+        text: impl Into<FormattedText>,
+    ) -> anyhow::Result<Vec<SentMessage>> {
+        let message = self.snapshot().await;
+
+        if !message.outgoing() {
+            return self.reply(text).await;
+        }
+
+        let mut chunks = prefixed(text).split(MAX_MESSAGE_LENGTH).into_iter();
+        let chat = message.chat();
+
+        // Put the first chunk into our message.
+        self.handle
+            .send(EditMessageCommand {
+                chat: chat.clone(),
+                message_id: message.id(),
+                message: chunks.next().unwrap_or_default(),
+            })
+            .await?
+            .context("failed to edit the message")?;
+
+        // Send the remaining chunks as replies.
+        let mut messages = vec![SentMessage::from(message.clone())];
+        for chunk in chunks {
+            let reply_to = messages.last().and_then(SentMessage::id);
+            messages.push(self.send(&chat, reply_to, chunk).await?);
+        }
+
+        self.self_destruct(&message, &messages);
+        Ok(messages)
+    }
+
+    /// Edit our message in the chat of the received message.
+    ///
+    /// Only the first chunk of the text is kept if it exceeds the length limit.
+    pub async fn edit(
+        &self,
+        message_id: i32,
+        text: impl Into<FormattedText>,
+    ) -> anyhow::Result<()> {
+        let message = self.snapshot().await;
+
+        self.handle
+            .send(EditMessageCommand {
+                chat: message.chat(),
+                message_id,
+                message: prefixed(text)
+                    .split(MAX_MESSAGE_LENGTH)
+                    .into_iter()
+                    .next()
+                    .unwrap_or_default(),
+            })
+            .await?
+            .context("failed to edit the message")?;
+
+        Ok(())
+    }
+
+    /// Report the error of the received message.
+    ///
+    /// It is sent to Saved Messages if [`ResponseOptions::errors_to_saved_messages`]
+    /// is set, otherwise it is the same as [`ModuleMessage::edit_or_reply`].
+    pub async fn report_error(
+        &self,
+        text: impl Into<FormattedText>,
+    ) -> anyhow::Result<Vec<SentMessage>> {
+        if !self.options.errors_to_saved_messages {
+            return self.edit_or_reply(text).await;
+        }
+
+        let message = self.snapshot().await;
+        let chat = message.chat();
+        let saved_messages = self.saved_messages().await?;
+
+        // Let the user know where the error comes from.
+        let context = FormattedText::new().push("\n— ");
+        let context = match message_link(&chat, message.id()) {
+            Some(link) => context.link(chat.name(), link),
+            None => context.push(chat.name()),
+        };
+
+        let messages = self
+            .send_chunks(&saved_messages, None, prefixed(text).append(context))
+            .await?;
+
+        // The feedback is not in the chat, so we can only clean the command up.
+        self.self_destruct(&message, &[]);
+        Ok(messages)
+    }
+
+    /// Send the text to Saved Messages, such as the notifications for the owner.
+    ///
+    /// It returns the messages carrying the response in order.
+    pub async fn notify(&self, text: impl Into<FormattedText>) -> anyhow::Result<Vec<SentMessage>> {
+        let saved_messages = self.saved_messages().await?;
+
+        self.send_chunks(&saved_messages, None, prefixed(text))
+            .await
+    }
+
+    /// Reply the file to the received message, with the text as its caption.
+    pub async fn reply_file(
+        &self,
+        name: impl Into<String>,
+        content: Vec<u8>,
+        caption: impl Into<FormattedText>,
+    ) -> anyhow::Result<SentMessage> {
+        let message = self.snapshot().await;
+        let uploaded = self
+            .handle
+            .send(UploadFileCommand {
+                name: name.into(),
+                content,
+            })
+            .await?
+            .context("failed to upload the file")?;
+
+        let mut input = OutgoingMessage::from(prefixed(caption)).reply_to(Some(message.id()));
+        if let UploadedFile::Uploaded(uploaded) = uploaded {
+            input = input.file(uploaded);
+        }
+
+        let sent = self
+            .handle
+            .send(SendMessageCommand(message.chat(), input))
+            .await?
+            .context("failed to send the file")?;
+
+        self.self_destruct(&message, std::slice::from_ref(&sent));
+        Ok(sent)
+    }
+
+    /// React to the received message with the emoji.
+    ///
+    /// The Telegram layer we are using predates message reactions,
+    /// so it replies the bare emoji instead.
+    pub async fn react(&self, emoji: &str) -> anyhow::Result<SentMessage> {
+        let message = self.snapshot().await;
+        let sent = self
+            .send(&message.chat(), Some(message.id()), emoji.into())
+            .await?;
+
+        self.self_destruct(&message, std::slice::from_ref(&sent));
+        Ok(sent)
+    }
+
+    /// Schedule the deletion of the response according to [`SelfDestructConfig`].
+    ///
+    /// The received message is only deleted if it is ours, so
+    /// the messages of the others are never deleted.
+    fn self_destruct(&self, message: &types::Message, feedback: &[SentMessage]) {
+        let cleanup = match &self.options.cleanup {
+            Some(cleanup) => cleanup,
+            None => return,
+        };
+        let chat = message.chat();
+        let after = match self.options.self_destruct.delay_of(self.module, chat.id()) {
+            Some(after) => after,
+            None => return,
+        };
+
+        let mut message_ids = Vec::new();
+        let target = self.options.self_destruct.target;
+        if matches!(
+            target,
+            SelfDestructTarget::Feedback | SelfDestructTarget::Both
+        ) {
+            message_ids.extend(feedback.iter().filter_map(SentMessage::id));
+        }
+        if matches!(
+            target,
+            SelfDestructTarget::Command | SelfDestructTarget::Both
+        ) && message.outgoing()
+        {
+            message_ids.push(message.id());
+        }
+        message_ids.sort_unstable();
+        message_ids.dedup();
+
+        if !message_ids.is_empty() {
+            cleanup.do_send(ScheduleDeletionCommand {
+                chat,
+                message_ids,
+                after,
+            });
+        }
+    }
+
+    /// Get the Saved Messages of the logged-in account.
+    async fn saved_messages(&self) -> anyhow::Result<types::Chat> {
+        Ok(types::Chat::User(
+            self.handle
+                .send(GetMeCommand)
+                .await?
+                .context("failed to get the logged-in user")?,
+        ))
+    }
+
+    /// Send the text in chunks, chaining the chunks as replies.
+    async fn send_chunks(
+        &self,
+        chat: &types::Chat,
+        reply_to: Option<i32>,
+        text: FormattedText,
+    ) -> anyhow::Result<Vec<SentMessage>> {
+        let mut messages: Vec<SentMessage> = Vec::new();
+
+        for chunk in text.split(MAX_MESSAGE_LENGTH) {
+            let reply_to = messages.last().and_then(SentMessage::id).or(reply_to);
+            messages.push(self.send(chat, reply_to, chunk).await?);
+        }
+
+        Ok(messages)
+    }
+
+    /// Send a single message through the client actor.
+    async fn send(
+        &self,
+        chat: &types::Chat,
+        reply_to: Option<i32>,
+        text: FormattedText,
+    ) -> anyhow::Result<SentMessage> {
+        let message = self
+            .handle
+            .send(SendMessageCommand(
+                chat.clone(),
+                OutgoingMessage::from(text).reply_to(reply_to),
+            ))
+            .await?
+            .context("failed to send the message")?;
+
+        Ok(message)
+    }
+}
+
+/// Add [`RESPONSE_PREFIX`] to the text.
+fn prefixed(text: impl Into<FormattedText>) -> FormattedText {
+    FormattedText::plain(format!("{} ", RESPONSE_PREFIX)).append(text)
+}
@@ -0,0 +1,432 @@
+//! PBot: Modules: FiltersModule
+//!
+//! Respond to the messages matching the triggers automatically.
+//!
+//! The filters belong to a chat, and respond to the incoming messages
+//! of anyone in the chat, only if the filters are enabled there with
+//! `!filter on`. A trigger is a keyword, a whole word with `--word`,
+//! or a regular expression with `--regex`. Every filter cools down
+//! after responding, so it won't flood the chat.
+//!
+//! The response is the text following the trigger, or the message
+//! replied to, including its media:
+//!
+//! ```text
+//! !filter add [--word|--regex] [--cooldown <期限>] <觸發詞> [回應]
+//! !filter del <觸發詞>
+//! !filter list
+//! !filter on|off
+//! ```
+
+pub mod store;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::prelude::*;
+use grammers_client::types::Message;
+use log::{error, info};
+use pbot_modules_derive::{ModuleActivator, ModuleActor, ModuleMeta};
+
+use crate::telegram::{
+    client::{commands::SendMessageCommand, outgoing::OutgoingMessage},
+    format::FormattedText,
+    stash::{drop_media, fetch_media, stash_media},
+    user::is_root_user,
+};
+use crate::utils::{format_duration, parse_duration};
+
+use self::store::{compile, Filter, FilterStore, MatchKind};
+
+use super::base::{
+    error::{ModuleError, ModuleResult},
+    response::RESPONSE_PREFIX,
+    ModuleMessage,
+};
+
+const CMD: &str = "!filter";
+
+/// The usage of `!filter`.
+const USAGE: &str = "!filter add [--word|--regex] [--cooldown <期限>] <觸發詞> [回應]
+!filter del <觸發詞>
+!filter list
+!filter on|off";
+
+/// The flag to match the trigger as a whole word.
+const WORD_FLAG: &str = "--word";
+
+/// The flag to match the trigger as a regular expression.
+const REGEX_FLAG: &str = "--regex";
+
+/// The flag to set the cooldown of the filter, such as `--cooldown 5m`.
+const COOLDOWN_FLAG: &str = "--cooldown";
+
+/// The FiltersModule actor.
+#[derive(Clone, ModuleActor, ModuleActivator, ModuleMeta)]
+#[name = "FiltersModule"]
+pub struct FiltersModuleActor {
+    /// The filters of the chats.
+    pub filters: Arc<FilterStore>,
+    /// The cooldown of the filters without their own cooldown.
+    pub cooldown: Duration,
+}
+
+impl Handler<ModuleMessage> for FiltersModuleActor {
+    type Result = ResponseActFuture<Self, ModuleResult>;
+
+    fn handle(&mut self, msg: ModuleMessage, _: &mut Self::Context) -> Self::Result {
+        // Clone the fields of self to move into the following block.
+        let filters = self.filters.clone();
+        let cooldown = self.cooldown;
+
+        async move {
+            // Take a snapshot of the message, so we don't need to lock it again.
+            let mut message = msg.snapshot().await;
+
+            if let Some(args) = extract_args(&message).map(str::to_string) {
+                handle_filter(&msg, &mut message, &filters, &args).await
+            } else if !message.outgoing() {
+                handle_incoming(&msg, &message, &filters, cooldown).await
+            } else {
+                Ok(())
+            }
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Manage the filters of the chat with `!filter <subcommand>`.
+async fn handle_filter(
+    msg: &ModuleMessage,
+    message: &mut Message,
+    filters: &FilterStore,
+    args: &str,
+) -> ModuleResult {
+    let (subcommand, rest) = next_word(args).unwrap_or_default();
+    let chat_id = message.chat().id();
+
+    match subcommand.as_str() {
+        "add" => handle_add(msg, message, filters, rest).await,
+        "del" => {
+            let trigger = match next_word(rest) {
+                Some((trigger, _)) => trigger,
+                None => return Err(ModuleError::usage_with("請指定觸發詞。", USAGE)),
+            };
+            let removed = match filters.remove(chat_id, &trigger).await? {
+                Some(removed) => removed,
+                None => return Err(ModuleError::usage(format!("找不到過濾器「{}」。", trigger))),
+            };
+            if let Some(media) = &removed.media {
+                drop_media(&msg.handle, media).await;
+            }
+
+            msg.edit_or_reply(
+                FormattedText::new()
+                    .push("🗑️ 已刪除過濾器 ")
+                    .code(&trigger)
+                    .push("。"),
+            )
+            .await?;
+            Ok(())
+        }
+        "list" => handle_list(msg, chat_id, filters).await,
+        "on" | "off" => {
+            let enabled = subcommand == "on";
+            filters.set_enabled(chat_id, enabled).await?;
+
+            msg.edit_or_reply(if enabled {
+                "✅ 已在此聊天啟用過濾器。"
+            } else {
+                "⏸️ 已在此聊天停用過濾器。"
+            })
+            .await?;
+            Ok(())
+        }
+        _ => Err(ModuleError::usage_with("未知的子命令。", USAGE)),
+    }
+}
+
+/// Add a filter with `!filter add [--word|--regex] [--cooldown <duration>] <trigger> [response]`.
+async fn handle_add(
+    msg: &ModuleMessage,
+    message: &mut Message,
+    filters: &FilterStore,
+    args: &str,
+) -> ModuleResult {
+    let mut kind = MatchKind::Keyword;
+    let mut cooldown = None;
+    let mut rest = args;
+
+    // Take the flags until the trigger.
+    let trigger = loop {
+        let (word, remaining) = match next_word(rest) {
+            Some(next) => next,
+            None => return Err(ModuleError::usage_with("請指定觸發詞。", USAGE)),
+        };
+        rest = remaining;
+
+        match word.as_str() {
+            WORD_FLAG => kind = MatchKind::Word,
+            REGEX_FLAG => kind = MatchKind::Regex,
+            COOLDOWN_FLAG => {
+                let (value, remaining) = next_word(rest).unwrap_or_default();
+                rest = remaining;
+                cooldown = match parse_duration(&value) {
+                    Some(cooldown) => Some(cooldown),
+                    None => {
+                        return Err(ModuleError::usage_with(
+                            "無法解析冷卻時間，例如 30s、5m 或 1h。",
+                            USAGE,
+                        ))
+                    }
+                };
+            }
+            _ => break word,
+        }
+    };
+    if let Err(e) = compile(kind, &trigger) {
+        return Err(ModuleError::usage(format!("正規表示式無效：{}", e)));
+    }
+
+    // The text following the trigger takes precedence over the message replied to.
+    let inline = rest.trim();
+    let replied = if inline.is_empty() {
+        message.get_reply().await?
+    } else {
+        None
+    };
+    let (text, media) = match &replied {
+        Some(replied) => (
+            FormattedText::with_entities(
+                replied.text(),
+                replied.fmt_entities().cloned().unwrap_or_default(),
+            ),
+            replied.media(),
+        ),
+        None => (FormattedText::plain(inline), None),
+    };
+    if text.is_empty() && media.is_none() {
+        return Err(ModuleError::usage_with(
+            "請在觸發詞後寫下回應，或回覆要作為回應的訊息。",
+            USAGE,
+        ));
+    }
+
+    let media = match media {
+        Some(media) => {
+            let caption = format!("{} 🔍 過濾器「{}」的媒體", RESPONSE_PREFIX, trigger);
+            stash_media(&msg.handle, &media, caption)
+                .await?
+                .map_err(|e| ModuleError::rpc("failed to stash the media", e))?
+        }
+        None => None,
+    };
+
+    let chat_id = message.chat().id();
+    let replaced = filters
+        .insert(Filter {
+            chat_id,
+            trigger: trigger.clone(),
+            kind,
+            response: text.into(),
+            media,
+            cooldown_secs: cooldown.map(|cooldown| cooldown.as_secs()),
+        })
+        .await?;
+    if let Some(media) = replaced.as_ref().and_then(|filter| filter.media.as_ref()) {
+        drop_media(&msg.handle, media).await;
+    }
+    info!("🔍 Added the filter `{}` in {}.", trigger, chat_id);
+
+    let mut text = FormattedText::new()
+        .push(match replaced {
+            Some(_) => "✅ 已更新過濾器 ",
+            None => "✅ 已新增過濾器 ",
+        })
+        .code(&trigger)
+        .push(format!("（{}）。", describe(kind, cooldown)));
+    if !filters.is_enabled(chat_id).await {
+        text = text
+            .push("\n此聊天尚未啟用過濾器，輸入 ")
+            .code(format!("{} on", CMD))
+            .push(" 以啟用。");
+    }
+    msg.edit_or_reply(text).await?;
+
+    Ok(())
+}
+
+/// List the filters of the chat with `!filter list`.
+async fn handle_list(msg: &ModuleMessage, chat_id: i32, filters: &FilterStore) -> ModuleResult {
+    let list = filters.list(chat_id).await;
+    let status = if filters.is_enabled(chat_id).await {
+        "已啟用"
+    } else {
+        "未啟用"
+    };
+
+    if list.is_empty() {
+        msg.edit_or_reply(format!("🔍 此聊天沒有過濾器（{}）。", status))
+            .await?;
+        return Ok(());
+    }
+
+    let mut text = FormattedText::new().push(format!("🔍 此聊天的過濾器（{}）：", status));
+    for filter in list {
+        let cooldown = filter.cooldown_secs.map(Duration::from_secs);
+        text = text
+            .push("\n• ")
+            .code(&filter.trigger)
+            .push(format!(" {}", describe(filter.kind, cooldown)));
+        if filter.media.is_some() {
+            text = text.push(" 📎");
+        }
+    }
+    msg.edit_or_reply(text).await?;
+
+    Ok(())
+}
+
+/// Respond to the incoming message if it matches any filter of the chat.
+async fn handle_incoming(
+    msg: &ModuleMessage,
+    message: &Message,
+    filters: &FilterStore,
+    cooldown: Duration,
+) -> ModuleResult {
+    let chat_id = message.chat().id();
+    let filter = match filters.fire(chat_id, message.text(), cooldown).await {
+        Some(filter) => filter,
+        None => return Ok(()),
+    };
+    info!("🔍 The filter `{}` fired in {}.", filter.trigger, chat_id);
+
+    // Don't bother the others in the chat with our errors.
+    if let Err(e) = respond(msg, message, &filter).await {
+        error!(
+            "Failed to respond with the filter `{}`: {:?}",
+            filter.trigger, e
+        );
+    }
+
+    Ok(())
+}
+
+/// Reply the response of the filter to the message.
+async fn respond(msg: &ModuleMessage, message: &Message, filter: &Filter) -> anyhow::Result<()> {
+    let mut input = OutgoingMessage::from(filter.response.formatted()).reply_to(Some(message.id()));
+
+    if let Some(media) = &filter.media {
+        match fetch_media(&msg.handle, media).await?? {
+            Some(media) => input = input.copy_media(&media),
+            None => anyhow::bail!("the media of the response has been deleted"),
+        }
+    }
+
+    msg.handle
+        .send(SendMessageCommand(message.chat(), input))
+        .await??;
+    Ok(())
+}
+
+/// Describe how the filter matches and cools down, such as `整個詞，冷卻 5 分鐘`.
+fn describe(kind: MatchKind, cooldown: Option<Duration>) -> String {
+    let kind = match kind {
+        MatchKind::Keyword => "關鍵字",
+        MatchKind::Word => "整個詞",
+        MatchKind::Regex => "正規表示式",
+    };
+
+    match cooldown {
+        Some(cooldown) => format!("{}，冷卻 {}", kind, format_duration(cooldown)),
+        None => kind.to_string(),
+    }
+}
+
+/// Take the first word of the text, keeping the quoted words together.
+///
+/// It returns the word and the rest of the text,
+/// or `None` if there is no word left.
+fn next_word(text: &str) -> Option<(String, &str)> {
+    let text = text.trim_start();
+    let first = text.chars().next()?;
+
+    let closing = match first {
+        '"' => '"',
+        '「' => '」',
+        '“' => '”',
+        _ => {
+            let end = text.find(char::is_whitespace).unwrap_or(text.len());
+            return Some((text[..end].to_string(), &text[end..]));
+        }
+    };
+
+    let quoted = &text[first.len_utf8()..];
+    match quoted.find(closing) {
+        Some(end) => Some((
+            quoted[..end].to_string(),
+            &quoted[end + closing.len_utf8()..],
+        )),
+        None => Some((quoted.to_string(), "")),
+    }
+}
+
+/// Get the text following the command if the message is `!filter` sent by us.
+fn extract_args(message: &Message) -> Option<&str> {
+    let rest = message.text().trim_start().strip_prefix(CMD)?;
+
+    if (rest.is_empty() || rest.starts_with(char::is_whitespace)) && is_root_user(message) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_split_at_whitespace() {
+        assert_eq!(
+            next_word("  add  hi there"),
+            Some(("add".to_string(), "  hi there"))
+        );
+        assert_eq!(next_word("hi"), Some(("hi".to_string(), "")));
+        assert_eq!(next_word("   "), None);
+        assert_eq!(next_word(""), None);
+    }
+
+    #[test]
+    fn quoted_words_are_kept_together() {
+        assert_eq!(
+            next_word("\"good morning\" 早安"),
+            Some(("good morning".to_string(), " 早安"))
+        );
+        assert_eq!(
+            next_word("「早 安」 hi"),
+            Some(("早 安".to_string(), " hi"))
+        );
+        assert_eq!(
+            next_word("“c++ rocks” x"),
+            Some(("c++ rocks".to_string(), " x"))
+        );
+        // The quotes only count at the start of the word.
+        assert_eq!(
+            next_word("say\"hi there\""),
+            Some(("say\"hi".to_string(), " there\""))
+        );
+    }
+
+    #[test]
+    fn unclosed_quotes_take_the_rest() {
+        assert_eq!(next_word("\"hi there"), Some(("hi there".to_string(), "")));
+        assert_eq!(next_word("「hi"), Some(("hi".to_string(), "")));
+    }
+
+    #[test]
+    fn mismatched_quotes_are_not_closed() {
+        assert_eq!(next_word("「hi\" x"), Some(("hi\" x".to_string(), "")));
+    }
+}
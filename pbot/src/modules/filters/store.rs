@@ -0,0 +1,463 @@
+//! PBot: Modules: FiltersModule: Filter Store
+//!
+//! The filters of the chats, and the chats where they are enabled.
+//!
+//! The filters are compiled when loading or adding them, and the
+//! times they fired are kept in memory for the cooldowns. The media
+//! of the responses are stashed in Saved Messages; see
+//! [`crate::telegram::stash`] for details.
+//!
+//! They are persisted in the storage, so the filters survive restarts.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use actix::Addr;
+use log::warn;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::storage::{Persisted, StorageActor};
+use crate::telegram::stash::{MediaRef, StashedText};
+
+/// The storage namespace of the filters.
+const STORAGE_NAMESPACE: &str = "filters";
+
+/// How the trigger matches the messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchKind {
+    /// The message contains the keyword, case-insensitively.
+    Keyword,
+    /// The message contains the keyword as a whole word, case-insensitively.
+    Word,
+    /// The message matches the regular expression.
+    Regex,
+}
+
+/// A filter responding to the messages matching its trigger.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Filter {
+    /// The ID of the chat which the filter belongs to.
+    pub chat_id: i32,
+    /// The keyword or the regular expression.
+    pub trigger: String,
+    /// How the trigger matches the messages.
+    pub kind: MatchKind,
+    /// The text of the response.
+    #[serde(flatten)]
+    pub response: StashedText,
+    /// The media of the response, if any.
+    pub media: Option<MediaRef>,
+    /// The cooldown of this filter in seconds, overriding the default one.
+    pub cooldown_secs: Option<u64>,
+}
+
+/// A filter along with its compiled trigger.
+#[derive(Clone)]
+struct CompiledFilter {
+    /// The filter.
+    filter: Filter,
+    /// The compiled trigger.
+    pattern: Regex,
+}
+
+impl CompiledFilter {
+    /// Compile the trigger of the filter.
+    fn new(filter: Filter) -> Result<Self, regex::Error> {
+        let pattern = compile(filter.kind, &filter.trigger)?;
+
+        Ok(Self { filter, pattern })
+    }
+}
+
+/// Compile the trigger into a regular expression.
+///
+/// A whole word is delimited by the non-word characters or the ends of
+/// the text. `\b` would require a word character inside the trigger,
+/// which triggers such as `c++` and `!help` don't have on their edges.
+///
+/// It fails only if the kind is [`MatchKind::Regex`] and the trigger is malformed.
+pub fn compile(kind: MatchKind, trigger: &str) -> Result<Regex, regex::Error> {
+    match kind {
+        MatchKind::Keyword => RegexBuilder::new(&regex::escape(trigger))
+            .case_insensitive(true)
+            .build(),
+        MatchKind::Word => {
+            RegexBuilder::new(&format!(r"(?:^|\W){}(?:$|\W)", regex::escape(trigger)))
+                .case_insensitive(true)
+                .build()
+        }
+        MatchKind::Regex => Regex::new(trigger),
+    }
+}
+
+/// The persisted state of the filters.
+#[derive(Default, Serialize, Deserialize)]
+struct FilterState {
+    /// The chats where the filters are enabled.
+    enabled_chats: Vec<i32>,
+    /// The filters, from the oldest to the newest.
+    filters: Vec<Filter>,
+}
+
+/// The in-memory state of the filters.
+///
+/// It is persisted as a [`FilterState`].
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(from = "FilterState", into = "FilterState")]
+struct Filters {
+    /// The chats where the filters are enabled.
+    enabled_chats: Vec<i32>,
+    /// The compiled filters, from the oldest to the newest.
+    filters: Vec<CompiledFilter>,
+}
+
+impl From<FilterState> for Filters {
+    /// Compile the filters.
+    ///
+    /// The filters failing to compile are skipped with a warning.
+    fn from(state: FilterState) -> Self {
+        let filters = state
+            .filters
+            .into_iter()
+            .filter_map(|filter| {
+                let trigger = filter.trigger.clone();
+                CompiledFilter::new(filter)
+                    .map_err(|e| warn!("Skipped the filter `{}`: {:?}", trigger, e))
+                    .ok()
+            })
+            .collect();
+
+        Self {
+            enabled_chats: state.enabled_chats,
+            filters,
+        }
+    }
+}
+
+impl From<Filters> for FilterState {
+    fn from(filters: Filters) -> Self {
+        Self {
+            enabled_chats: filters.enabled_chats,
+            filters: filters.filters.into_iter().map(|f| f.filter).collect(),
+        }
+    }
+}
+
+/// When the filters fired the last time, by the IDs of the chats and the triggers.
+type FiredAt = HashMap<i32, HashMap<String, Instant>>;
+
+/// The persisted filters.
+pub struct FilterStore {
+    /// The filters and the chats where they are enabled.
+    filters: Persisted<Filters>,
+    /// When the filters fired the last time, which is kept in memory only.
+    fired_at: Mutex<FiredAt>,
+}
+
+impl FilterStore {
+    /// Load the filters from the storage.
+    ///
+    /// The filters failing to compile are skipped with a warning.
+    pub async fn load(storage: Addr<StorageActor>) -> anyhow::Result<Self> {
+        Ok(Self {
+            filters: Persisted::load(storage, STORAGE_NAMESPACE).await?,
+            fired_at: Mutex::default(),
+        })
+    }
+
+    /// Forget when the filter with the trigger in the chat fired.
+    async fn reset_cooldown(&self, chat_id: i32, trigger: &str) {
+        if let Some(triggers) = self.fired_at.lock().await.get_mut(&chat_id) {
+            triggers.remove(trigger);
+        }
+    }
+
+    /// Add the filter and persist it.
+    ///
+    /// It returns the filter replaced, which has the same trigger in the same chat.
+    pub async fn insert(&self, filter: Filter) -> anyhow::Result<Option<Filter>> {
+        let compiled = CompiledFilter::new(filter)?;
+        let (chat_id, trigger) = (compiled.filter.chat_id, compiled.filter.trigger.clone());
+
+        let replaced = self
+            .filters
+            .update(|filters| {
+                let index = filters
+                    .filters
+                    .iter()
+                    .position(|f| f.filter.chat_id == chat_id && f.filter.trigger == trigger);
+                let replaced = index.map(|index| filters.filters.remove(index).filter);
+                filters.filters.push(compiled);
+                Some(replaced)
+            })
+            .await?
+            .flatten();
+
+        self.reset_cooldown(chat_id, &trigger).await;
+        Ok(replaced)
+    }
+
+    /// Remove the filter with the trigger in the chat, and persist the remaining filters.
+    ///
+    /// It returns the removed filter, or `None` if there is no such filter.
+    pub async fn remove(&self, chat_id: i32, trigger: &str) -> anyhow::Result<Option<Filter>> {
+        let removed = self
+            .filters
+            .update(|filters| {
+                let index = filters
+                    .filters
+                    .iter()
+                    .position(|f| f.filter.chat_id == chat_id && f.filter.trigger == trigger)?;
+                Some(filters.filters.remove(index).filter)
+            })
+            .await?;
+
+        self.reset_cooldown(chat_id, trigger).await;
+        Ok(removed)
+    }
+
+    /// List the filters of the chat.
+    pub async fn list(&self, chat_id: i32) -> Vec<Filter> {
+        self.filters
+            .read(|filters| {
+                filters
+                    .filters
+                    .iter()
+                    .filter(|f| f.filter.chat_id == chat_id)
+                    .map(|f| f.filter.clone())
+                    .collect()
+            })
+            .await
+    }
+
+    /// Check if the filters are enabled in the chat.
+    pub async fn is_enabled(&self, chat_id: i32) -> bool {
+        self.filters
+            .read(|filters| filters.enabled_chats.contains(&chat_id))
+            .await
+    }
+
+    /// Enable or disable the filters in the chat, and persist it.
+    pub async fn set_enabled(&self, chat_id: i32, enabled: bool) -> anyhow::Result<()> {
+        self.filters
+            .update(|filters| {
+                filters.enabled_chats.retain(|id| *id != chat_id);
+                if enabled {
+                    filters.enabled_chats.push(chat_id);
+                }
+                Some(())
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Find the filter of the chat matching the text, and mark it fired.
+    ///
+    /// The filters cooling down are skipped, and the oldest matching
+    /// filter wins. It returns `None` if the filters are not enabled
+    /// in the chat.
+    pub async fn fire(
+        &self,
+        chat_id: i32,
+        text: &str,
+        default_cooldown: Duration,
+    ) -> Option<Filter> {
+        let mut fired_at = self.fired_at.lock().await;
+
+        self.filters
+            .read(|filters| {
+                fire(
+                    filters,
+                    &mut fired_at,
+                    chat_id,
+                    text,
+                    default_cooldown,
+                    Instant::now(),
+                )
+            })
+            .await
+    }
+}
+
+/// Find the filter of the chat matching the text at `now`, and mark it fired.
+///
+/// See [`FilterStore::fire`] for details.
+fn fire(
+    filters: &Filters,
+    fired_at: &mut FiredAt,
+    chat_id: i32,
+    text: &str,
+    default_cooldown: Duration,
+    now: Instant,
+) -> Option<Filter> {
+    if !filters.enabled_chats.contains(&chat_id) {
+        return None;
+    }
+
+    let fired = filters.filters.iter().find(|f| {
+        let cooldown = f
+            .filter
+            .cooldown_secs
+            .map(Duration::from_secs)
+            .unwrap_or(default_cooldown);
+
+        f.filter.chat_id == chat_id
+            && fired_at
+                .get(&chat_id)
+                .and_then(|triggers| triggers.get(&f.filter.trigger))
+                .is_none_or(|last| now.duration_since(*last) >= cooldown)
+            && f.pattern.is_match(text)
+    })?;
+    fired_at
+        .entry(chat_id)
+        .or_default()
+        .insert(fired.filter.trigger.clone(), now);
+
+    Some(fired.filter.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_secs(60);
+
+    fn filter(chat_id: i32, trigger: &str, kind: MatchKind) -> Filter {
+        Filter {
+            chat_id,
+            trigger: trigger.to_string(),
+            kind,
+            response: StashedText {
+                text: format!("re: {}", trigger),
+                entities: Vec::new(),
+            },
+            media: None,
+            cooldown_secs: None,
+        }
+    }
+
+    /// The filters, which are enabled in chat 1 only.
+    fn filters(filters: Vec<Filter>) -> Filters {
+        Filters::from(FilterState {
+            enabled_chats: vec![1],
+            filters,
+        })
+    }
+
+    fn matches(kind: MatchKind, trigger: &str, text: &str) -> bool {
+        compile(kind, trigger).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn keywords_match_anywhere_case_insensitively() {
+        assert!(matches(MatchKind::Keyword, "Cat", "concatenate"));
+        assert!(matches(MatchKind::Keyword, "a.b", "see A.B here"));
+        assert!(!matches(MatchKind::Keyword, "a.b", "axb"));
+    }
+
+    #[test]
+    fn words_match_whole_words_only() {
+        assert!(matches(MatchKind::Word, "cat", "a Cat!"));
+        assert!(matches(MatchKind::Word, "cat", "cat"));
+        assert!(!matches(MatchKind::Word, "cat", "concatenate"));
+        assert!(matches(MatchKind::Word, "你好", "嗨 你好 啊"));
+        assert!(!matches(MatchKind::Word, "你好", "你好嗎"));
+    }
+
+    #[test]
+    fn words_with_symbols_on_the_edges() {
+        assert!(matches(MatchKind::Word, "c++", "I like C++."));
+        assert!(matches(MatchKind::Word, "c++", "c++"));
+        assert!(!matches(MatchKind::Word, "c++", "c++x"));
+        assert!(!matches(MatchKind::Word, "c++", "abc++"));
+
+        assert!(matches(MatchKind::Word, "!help", "!help"));
+        assert!(matches(MatchKind::Word, "!help", "try !help now"));
+        assert!(!matches(MatchKind::Word, "!help", "!helpful"));
+        assert!(!matches(MatchKind::Word, "!help", "hey!help"));
+    }
+
+    #[test]
+    fn regexes_are_compiled_as_is() {
+        assert!(matches(MatchKind::Regex, r"^\d{3}$", "123"));
+        assert!(!matches(MatchKind::Regex, r"^\d{3}$", "1234"));
+        assert!(compile(MatchKind::Regex, "(unclosed").is_err());
+    }
+
+    #[test]
+    fn malformed_filters_are_skipped_when_loading() {
+        let filters = filters(vec![
+            filter(1, "(unclosed", MatchKind::Regex),
+            filter(1, "(unclosed", MatchKind::Keyword),
+        ]);
+
+        assert_eq!(filters.filters.len(), 1);
+        assert_eq!(filters.filters[0].filter.kind, MatchKind::Keyword);
+    }
+
+    #[test]
+    fn only_enabled_chats_fire() {
+        let filters = filters(vec![
+            filter(1, "hi", MatchKind::Keyword),
+            filter(2, "hi", MatchKind::Keyword),
+        ]);
+        let mut fired_at = FiredAt::new();
+        let now = Instant::now();
+
+        assert!(fire(&filters, &mut fired_at, 2, "hi", COOLDOWN, now).is_none());
+        assert!(fire(&filters, &mut fired_at, 3, "hi", COOLDOWN, now).is_none());
+        assert_eq!(
+            fire(&filters, &mut fired_at, 1, "hi", COOLDOWN, now).map(|f| f.chat_id),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn the_oldest_matching_filter_fires() {
+        let filters = filters(vec![
+            filter(1, "hello", MatchKind::Keyword),
+            filter(1, "hell", MatchKind::Keyword),
+        ]);
+        let mut fired_at = FiredAt::new();
+        let now = Instant::now();
+        let fire = |fired_at: &mut FiredAt| {
+            fire(&filters, fired_at, 1, "hello", COOLDOWN, now).map(|f| f.trigger)
+        };
+
+        assert_eq!(fire(&mut fired_at).as_deref(), Some("hello"));
+        // The oldest one is cooling down, so the next one fires.
+        assert_eq!(fire(&mut fired_at).as_deref(), Some("hell"));
+        assert_eq!(fire(&mut fired_at), None);
+    }
+
+    #[test]
+    fn filters_cool_down() {
+        let mut quick = filter(1, "quick", MatchKind::Keyword);
+        quick.cooldown_secs = Some(5);
+        let filters = filters(vec![filter(1, "slow", MatchKind::Keyword), quick]);
+        let mut fired_at = FiredAt::new();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut fire =
+            |text, secs| fire(&filters, &mut fired_at, 1, text, COOLDOWN, at(secs)).is_some();
+
+        // The default cooldown.
+        assert!(fire("slow", 0));
+        assert!(!fire("slow", 59));
+        assert!(fire("slow", 60));
+
+        // The cooldown of the filter overrides the default one.
+        assert!(fire("quick", 0));
+        assert!(!fire("quick", 4));
+        assert!(fire("quick", 5));
+
+        // A filter not matching doesn't start its cooldown.
+        assert!(!fire("nothing", 100));
+        assert!(fire("quick", 100));
+    }
+}
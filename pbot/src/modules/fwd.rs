@@ -2,99 +2,368 @@
 //!
 //! Simply forward the message to your specified chat
 //! with `!cufwd`.
+//!
+//! You can name more chats as the targets, and forward
+//! to them with `!cufwd <target>`, such as `!cufwd work`.
+//!
+//! You can forward more messages at once with `!cufwd <N>`, which
+//! forwards the replied message and the next N-1 messages, or
+//! with `!cufwd ..`, which forwards the messages from the replied
+//! message to the command. The albums are always forwarded as a whole.
+//!
+//! With `!cufwd copy`, the messages are re-sent as copies with an
+//! attribution footer instead. The messages which the source chat
+//! refuses to forward are always copied.
+//!
+//! The forwards are recorded, so you can retract them with
+//! `!cufwd undo`, and the original sender can retract them by
+//! replying `撤下` to the confirmation.
+//!
+//! The messages which have been archived in the target are not
+//! forwarded again; a link to the existing copy is replied instead.
+//!
+//! You can tag the forwards with `!cufwd #tag1 #tag2`, search the
+//! records with `!fwdsearch <query|#tag>`, and export them as JSONL
+//! with `!fwdexport`.
+
+pub mod records;
 
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use actix::{fut::WrapFuture, Actor, ActorFutureExt, Context, Handler, ResponseActFuture};
-use grammers_client::{
-    types::{Chat, Message},
-    InputMessage,
-};
+use chrono::{TimeZone, Utc};
+use grammers_client::types::{Chat, Message};
 use log::{error, info, warn};
 use pbot_modules_derive::{ModuleActivator, ModuleActor, ModuleMeta};
 
-use crate::telegram::{client::commands::ForwardSingleMessageCommand, user::is_root_user};
+use crate::telegram::{
+    client::{
+        commands::{
+            DeleteMessagesCommand, ForwardMessagesCommand, GetHistoryIdsCommand, GetMessagesCommand,
+        },
+        forward::{ForwardError, ForwardOptions},
+        outgoing::SentMessage,
+    },
+    format::FormattedText,
+    user::is_root_user,
+};
+
+use self::records::{content_hash, ForwardRecord, ForwardRecords};
 
-use super::base::ModuleMessage;
+use super::base::{
+    error::{ModuleError, ModuleResult},
+    ModuleMessage,
+};
 
 const CMD: &str = "!cufwd";
 
+/// The command to search the forward records, such as `!fwdsearch #tag`.
+const SEARCH_CMD: &str = "!fwdsearch";
+
+/// The command to export the forward records as JSONL.
+const EXPORT_CMD: &str = "!fwdexport";
+
+/// The maximum amount of the search results.
+const MAX_SEARCH_RESULTS: usize = 20;
+
+/// The maximum length of the text shown in the search results, in characters.
+const SNIPPET_LENGTH: usize = 40;
+
+/// The usage of `!cufwd`.
+const USAGE: &str = "!cufwd [目標] [數量|..] [copy] [#標籤…]";
+
+/// The maximum amount of messages in a range.
+const MAX_RANGE: i32 = 100;
+
+/// The subcommand to retract the forward, such as `!cufwd undo`.
+pub const UNDO_SUBCOMMAND: &str = "undo";
+
+/// The flag to re-send the messages as copies, such as `!cufwd copy`.
+pub const COPY_FLAG: &str = "copy";
+
+/// The words which can't be used as the names of the targets.
+pub const RESERVED_NAMES: &[&str] = &[UNDO_SUBCOMMAND, COPY_FLAG];
+
+/// The default attribution footer of the copies.
+///
+/// See [`ForwardOptions::attribution`] for the placeholders.
+pub const DEFAULT_ATTRIBUTION: &str = "— {sender} @ {chat}\n{link}";
+
+/// The text which the original sender replies to the confirmation to retract the forward.
+const REMOVAL_REQUEST: &str = "撤下";
+
+/// The chats where the messages can be forwarded to.
+#[derive(Clone)]
+pub struct FwdTargets {
+    /// Where the message will be forwarded to if no target is specified.
+    pub default: Arc<Chat>,
+    /// The named targets, such as `work` for `!cufwd work`.
+    pub named: BTreeMap<String, Arc<Chat>>,
+}
+
+impl FwdTargets {
+    /// Get the target by its name, or the default target if `name` is `None`.
+    pub fn get(&self, name: Option<&str>) -> Result<Arc<Chat>, ModuleError> {
+        let name = match name {
+            Some(name) => name,
+            None => return Ok(self.default.clone()),
+        };
+
+        self.named.get(name).cloned().ok_or_else(|| {
+            let valid_targets = self
+                .named
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join("、");
+
+            if valid_targets.is_empty() {
+                ModuleError::usage(format!(
+                    "沒有名為「{}」的轉錄目標，目前僅有預設目標。",
+                    name
+                ))
+            } else {
+                ModuleError::usage_with(
+                    format!(
+                        "沒有名為「{}」的轉錄目標。可用的目標：{}",
+                        name, valid_targets
+                    ),
+                    USAGE,
+                )
+            }
+        })
+    }
+}
+
 /// The FwdModule actor.
 #[derive(Clone, ModuleActor, ModuleActivator, ModuleMeta)]
 #[name = "FwdModule"]
 pub struct FwdModuleActor {
     /// Where the message will be forwarded to.
-    pub target: Arc<Chat>,
+    pub targets: Arc<FwdTargets>,
+    /// The records of the forwarded messages, for retracting them.
+    pub records: Arc<ForwardRecords>,
+    /// The attribution footer of the copies, or `None` for no footer.
+    ///
+    /// See [`ForwardOptions::attribution`] for the placeholders.
+    pub attribution: Option<String>,
+    /// Detect the duplicates by their content too, besides their IDs.
+    ///
+    /// See [`records::content_hash`].
+    pub dedup_content: bool,
 }
 
 impl Handler<ModuleMessage> for FwdModuleActor {
-    type Result = ResponseActFuture<Self, anyhow::Result<()>>;
+    type Result = ResponseActFuture<Self, ModuleResult>;
 
     fn handle(&mut self, msg: ModuleMessage, _: &mut Self::Context) -> Self::Result {
-        // Clone self.target to move into the following block.
-        let target = self.target.clone();
-
-        // It will only respond when:
-        //   * The message text is the text specified in CMD.
-        //   * The message is sent by the account operator.
-        let trigger_condition = |message: &Message| message.text() == CMD && is_root_user(message);
+        // Clone the fields of self to move into the following block.
+        let targets = self.targets.clone();
+        let records = self.records.clone();
+        let attribution = self.attribution.clone();
+        let dedup_content = self.dedup_content;
 
         async move {
-            // Destruct msg and get `handle` and `message`.
-            let ModuleMessage { handle, message } = msg;
-
-            if trigger_condition(&*message.read().await) {
-                // Get the ID of the chat where the message is sent.
-                // It is Option here. We will check if replied anyone later.
-                let reply_message_id = {
-                    let message = message.read().await;
-                    message.reply_to_message_id()
-                };
-
-                // Check if this message has been replied anyone.
-                if let Some(reply_message_id) = reply_message_id {
-                    // Yes - Get the chat with this replied message.
-                    // Since the chat of replied message and the chat of this message are the same,
-                    // we can use the chat of the command message to
-                    // represent the chat of the replied message.
-                    let reply_message_src = Arc::new(message.read().await.chat());
-
-                    // Forward the message.
-                    let forward_result = handle
-                        .send(ForwardSingleMessageCommand {
-                            forward_to: target,
-                            message_id: reply_message_id,
-                            message_chat: reply_message_src,
-                        })
-                        .await?;
-
-                    // Check if the message has been forwarded successfully.
-                    match forward_result {
-                        // 👏 Great! Let's notify the sender of replied message.
-                        Ok(_) => {
-                            info!("💬 Message forwarded!");
-
-                            message
-                                .write()
-                                .await
-                                .edit(InputMessage::text(
-                                    "[PBOT] 💬 訊息已轉錄至個人群組。若要撤下請回覆告知。",
-                                ))
-                                .await?;
-                        }
-                        // Show the error rather than panic!() it.
-                        Err(e) => error!("Failed to forward message: {:?}", e),
+            // Take a snapshot of the message, so we don't need to lock it again.
+            let mut message = msg.snapshot().await;
+
+            // The original sender may reply to the confirmation to retract the forward.
+            if is_removal_request(&message) {
+                return handle_removal_request(&msg, &message, &records).await;
+            }
+
+            let args = match extract_args(&message, CMD) {
+                Some(args) => parse_args(&args)?,
+                None => {
+                    if let Some(terms) = extract_args(&message, SEARCH_CMD) {
+                        return handle_search(&msg, &terms, &records).await;
                     }
-                } else {
+                    if extract_args(&message, EXPORT_CMD).is_some() {
+                        return handle_export(&msg, &records).await;
+                    }
+
+                    return Ok(());
+                }
+            };
+
+            // Retract the forward if requested.
+            if args.undo {
+                return handle_undo(&msg, &message, &records).await;
+            }
+
+            // Get the target to forward to.
+            let target = targets.get(args.target.as_deref())?;
+
+            // Check if this message has been replied anyone.
+            let reply_message_id = match message.reply_to_message_id() {
+                Some(reply_message_id) => reply_message_id,
+                None => {
                     // No - Let user know how to use it correctly.
                     warn!("No reply message found");
 
-                    message
-                        .write()
-                        .await
-                        .edit(InputMessage::text("[PBOT] ⚠️ 請回覆訊息。"))
-                        .await?;
+                    return Err(ModuleError::usage_with(
+                        "請回覆訊息。",
+                        format!("回覆要轉錄的訊息並輸入 {}", USAGE),
+                    ));
+                }
+            };
+
+            // Yes - Get the chat with this replied message.
+            // Since the chat of replied message and the chat of this message are the same,
+            // we can use the chat of the command message to
+            // represent the chat of the replied message.
+            let reply_message_src = Arc::new(message.chat());
+
+            // Skip the messages which have been archived in the target.
+            let message_ids = args
+                .range
+                .message_ids(&msg, &reply_message_src, reply_message_id, message.id())
+                .await?;
+            let content_hashes = if dedup_content {
+                get_content_hashes(&msg, &reply_message_src, &message_ids).await?
+            } else {
+                HashMap::new()
+            };
+            let mut duplicates = Vec::new();
+            let mut message_ids_to_forward = Vec::new();
+            for id in message_ids {
+                let content_hash = content_hashes.get(&id).copied();
+                match records
+                    .find_duplicate(reply_message_src.id(), id, content_hash, target.id())
+                    .await
+                {
+                    Some(record) => duplicates.push(record),
+                    None => message_ids_to_forward.push(id),
+                }
+            }
+
+            // Forward the messages, including the whole albums.
+            let forward_result = if message_ids_to_forward.is_empty() {
+                Default::default()
+            } else {
+                msg.handle
+                    .send(ForwardMessagesCommand {
+                        forward_to: target.clone(),
+                        message_ids: message_ids_to_forward,
+                        message_chat: reply_message_src.clone(),
+                        options: ForwardOptions {
+                            copy: args.copy,
+                            fallback_to_copy: true,
+                            attribution,
+                            with_albums: true,
+                            ..Default::default()
+                        },
+                    })
+                    .await?
+            };
+
+            // Split the forwarded messages from the failures.
+            let mut source_ids = Vec::new();
+            let mut forwarded = Vec::new();
+            let mut failures = Vec::new();
+            for (id, result) in forward_result {
+                match result {
+                    Ok(message) => {
+                        source_ids.push(id);
+                        forwarded.push(message);
+                    }
+                    // The messages in a range may have been deleted, which is fine.
+                    Err(ForwardError::NotFound) if id != reply_message_id => {}
+                    Err(e) => failures.push((id, e)),
+                }
+            }
+
+            // Let the executor tell the user why it failed if nothing is forwarded,
+            // or point to the existing copy if they have been archived.
+            let first = match forwarded.first() {
+                Some(first) => first,
+                None => {
+                    if let Some((_, e)) = failures.into_iter().next() {
+                        return Err(forward_error(e));
+                    }
+
+                    return match duplicates.first() {
+                        Some(duplicate) => reply_duplicate(&msg, duplicate).await,
+                        None => Err(ModuleError::usage("找不到要轉錄的訊息。")),
+                    };
                 }
+            };
+            for (id, e) in failures.iter() {
+                warn!("Failed to forward message {}: {}", id, e);
+            }
+
+            // 👏 Great! Let's notify the sender of replied message.
+            info!("💬 {} messages forwarded!", forwarded.len());
+            let target_chat = first
+                .message()
+                .map_or_else(|| (*target).clone(), Message::chat);
+            let mut text = FormattedText::new();
+            text = if forwarded.len() == 1 {
+                text.push("💬 訊息已")
+            } else {
+                text.push(format!("💬 {} 則訊息已", forwarded.len()))
+            };
+            let label = format!("轉錄至{}", target_chat.name());
+            text = match first.id() {
+                Some(first_id) => text.message_link(label, &target_chat, first_id),
+                // Nothing was forwarded in the dry-run mode.
+                None => text.push(label).push("（試執行）"),
+            };
+            let forwarded = forwarded
+                .iter()
+                .filter_map(SentMessage::message)
+                .collect::<Vec<_>>();
+            if !args.copy && forwarded.iter().any(|m| m.forward_header().is_none()) {
+                text = text.push("（來源禁止轉傳，已改以複本轉錄）");
+            }
+            if !duplicates.is_empty() {
+                text = text.push(format!("，{} 則訊息已轉錄過", duplicates.len()));
+            }
+            if !failures.is_empty() {
+                text = text.push(format!("，{} 則訊息轉錄失敗", failures.len()));
+            }
+            let confirmation = msg
+                .edit_or_reply(text.push(format!("。若要撤下請回覆「{}」。", REMOVAL_REQUEST)))
+                .await?;
+
+            // There is nothing to retract or search in the dry-run mode.
+            if forwarded.is_empty() {
+                return Ok(());
+            }
+
+            // Record the forward, so it can be retracted and searched later.
+            let replied = message.get_reply().await?;
+            let sender = replied.as_ref().and_then(|replied| replied.sender());
+            let record = ForwardRecord {
+                source_chat_id: reply_message_src.id(),
+                content_hashes: source_ids
+                    .iter()
+                    .filter_map(|id| content_hashes.get(id).copied())
+                    .collect(),
+                source_ids,
+                sender_id: sender.as_ref().map(|sender| sender.id()),
+                target: target_chat.pack().to_bytes(),
+                forwarded_ids: forwarded.iter().map(|m| m.id()).collect(),
+                confirmation_id: confirmation.first().and_then(SentMessage::id),
+                tags: args.tags,
+                source_chat_title: reply_message_src.name().to_string(),
+                sender_name: sender
+                    .as_ref()
+                    .map(|sender| sender.name().to_string())
+                    .unwrap_or_default(),
+                date: replied
+                    .map_or_else(|| message.date(), |replied| replied.date())
+                    .timestamp(),
+                text: forwarded
+                    .iter()
+                    .map(|m| m.text())
+                    .filter(|text| !text.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            };
+            if let Err(e) = records.insert(record).await {
+                error!("Failed to record the forward: {:?}", e);
             }
 
             // It worked with no fault errors! 👌
@@ -104,3 +373,376 @@ impl Handler<ModuleMessage> for FwdModuleActor {
         .boxed_local()
     }
 }
+
+/// Handle `!cufwd undo`, which retracts the forward of the replied message.
+///
+/// The replied message can be either the source message or the confirmation.
+async fn handle_undo(
+    msg: &ModuleMessage,
+    message: &Message,
+    records: &ForwardRecords,
+) -> ModuleResult {
+    let reply_message_id = message.reply_to_message_id().ok_or_else(|| {
+        ModuleError::usage_with(
+            "請回覆要撤下的訊息或轉錄通知。",
+            format!("{} {}", CMD, UNDO_SUBCOMMAND),
+        )
+    })?;
+    let record = records
+        .find(message.chat().id(), reply_message_id)
+        .await
+        .ok_or_else(|| ModuleError::usage("找不到這則訊息的轉錄紀錄。"))?;
+
+    let retracted = retract(msg, records, &record).await?;
+
+    // The confirmation has been updated if we are replying to it.
+    if record.confirmation_id != Some(reply_message_id) {
+        msg.edit_or_reply(format!("🗑️ 已撤下 {} 則轉錄的訊息。", retracted))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Get the content hashes of the messages, keyed by their IDs.
+async fn get_content_hashes(
+    msg: &ModuleMessage,
+    chat: &Chat,
+    message_ids: &[i32],
+) -> Result<HashMap<i32, u64>, ModuleError> {
+    let messages = msg
+        .handle
+        .send(GetMessagesCommand {
+            chat: chat.clone(),
+            message_ids: message_ids.to_vec(),
+        })
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to get the messages", e))?;
+
+    Ok(message_ids
+        .iter()
+        .zip(messages)
+        .filter_map(|(id, message)| Some((*id, content_hash(&message?)?)))
+        .collect())
+}
+
+/// Tell the user that the message has been archived, with a link to the existing copy.
+async fn reply_duplicate(msg: &ModuleMessage, duplicate: &ForwardRecord) -> ModuleResult {
+    let mut text = FormattedText::plain("💬 訊息已轉錄過了");
+    text = match (duplicate.target_chat(), duplicate.forwarded_ids.first()) {
+        (Some(chat), Some(id)) => text.push("：").message_link("查看轉錄的訊息", &chat, *id),
+        _ => text,
+    };
+
+    msg.edit_or_reply(text.push("。")).await?;
+    Ok(())
+}
+
+/// Handle `!fwdsearch`, which lists the records matching the query.
+async fn handle_search(
+    msg: &ModuleMessage,
+    terms: &[String],
+    records: &ForwardRecords,
+) -> ModuleResult {
+    if terms.is_empty() {
+        return Err(ModuleError::usage_with(
+            "請輸入要搜尋的內容或標籤。",
+            format!("{} <關鍵字|#標籤>…", SEARCH_CMD),
+        ));
+    }
+
+    let results = records.search(terms, MAX_SEARCH_RESULTS).await;
+    if results.is_empty() {
+        msg.edit_or_reply("🔎 找不到符合的轉錄紀錄。").await?;
+        return Ok(());
+    }
+
+    let mut text = FormattedText::plain(format!("🔎 找到 {} 筆轉錄紀錄：", results.len()));
+    for record in results {
+        let date = Utc
+            .timestamp_opt(record.date, 0)
+            .single()
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+
+        text = text
+            .push(format!("\n• {} ", date))
+            .bold(&record.source_chat_title);
+        if !record.sender_name.is_empty() {
+            text = text.push(format!("｜{}", record.sender_name));
+        }
+        text = text.push(format!("：{} ", snippet(&record.text)));
+
+        // Link to the archived message if it is linkable.
+        text = match (record.target_chat(), record.forwarded_ids.first()) {
+            (Some(chat), Some(id)) => text.message_link("🔗", &chat, *id),
+            _ => text,
+        };
+        for tag in record.tags.iter() {
+            text = text.push(format!(" #{}", tag));
+        }
+    }
+
+    msg.edit_or_reply(text).await?;
+    Ok(())
+}
+
+/// Handle `!fwdexport`, which sends all the records as a JSONL file.
+async fn handle_export(msg: &ModuleMessage, records: &ForwardRecords) -> ModuleResult {
+    let records = records.all().await;
+
+    let mut content = Vec::new();
+    for record in records.iter() {
+        serde_json::to_writer(&mut content, record).map_err(anyhow::Error::from)?;
+        content.push(b'\n');
+    }
+
+    msg.reply_file(
+        "pbot-fwd-records.jsonl",
+        content,
+        format!("📦 已匯出 {} 筆轉錄紀錄。", records.len()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Shorten the text for the search results.
+fn snippet(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if text.chars().count() > SNIPPET_LENGTH {
+        format!("{}…", text.chars().take(SNIPPET_LENGTH).collect::<String>())
+    } else if text.is_empty() {
+        "（無文字）".to_string()
+    } else {
+        text
+    }
+}
+
+/// Handle the removal request from the original sender.
+async fn handle_removal_request(
+    msg: &ModuleMessage,
+    message: &Message,
+    records: &ForwardRecords,
+) -> ModuleResult {
+    let reply_message_id = match message.reply_to_message_id() {
+        Some(reply_message_id) => reply_message_id,
+        None => return Ok(()),
+    };
+
+    // Only the requests replying to the confirmations count.
+    let record = match records.find(message.chat().id(), reply_message_id).await {
+        Some(record) if record.confirmation_id == Some(reply_message_id) => record,
+        _ => return Ok(()),
+    };
+
+    let sender_id = message.sender().map(|sender| sender.id());
+    if sender_id.is_none() || sender_id != record.sender_id {
+        return Err(ModuleError::permission_denied(
+            "只有原訊息的傳送者可以要求撤下。",
+        ));
+    }
+
+    retract(msg, records, &record).await?;
+    info!("🗑️ Forward retracted by the original sender.");
+
+    Ok(())
+}
+
+/// Delete the forwarded messages of the record, and update the confirmation.
+///
+/// It returns the amount of deleted messages.
+async fn retract(
+    msg: &ModuleMessage,
+    records: &ForwardRecords,
+    record: &ForwardRecord,
+) -> Result<usize, ModuleError> {
+    let target = record
+        .target_chat()
+        .ok_or_else(|| ModuleError::Internal(anyhow::anyhow!("malformed target chat")))?;
+
+    let deleted = msg
+        .handle
+        .send(DeleteMessagesCommand {
+            chat: target,
+            message_ids: record.forwarded_ids.clone(),
+        })
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to delete the forwarded messages", e))?;
+    records.remove(record).await?;
+
+    if let Some(confirmation_id) = record.confirmation_id {
+        // The confirmation may have been deleted, which is fine.
+        if let Err(e) = msg.edit(confirmation_id, "🗑️ 轉錄的訊息已撤下。").await {
+            warn!("Failed to update the confirmation: {:?}", e);
+        }
+    }
+
+    Ok(deleted)
+}
+
+/// Whether the message is a removal request, which is
+/// [`REMOVAL_REQUEST`] sent by anyone other than the account operator.
+fn is_removal_request(message: &Message) -> bool {
+    message.text().trim() == REMOVAL_REQUEST && !is_root_user(message)
+}
+
+/// The messages to forward, starting from the replied message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FwdRange {
+    /// Forward the replied message only.
+    Single,
+    /// Forward the replied message and the messages after it,
+    /// such as `!cufwd 5`.
+    Count(i32),
+    /// Forward the messages from the replied message to the command,
+    /// that is, `!cufwd ..`.
+    UntilCommand,
+}
+
+impl FwdRange {
+    /// Get the IDs of the messages to forward, oldest first.
+    ///
+    /// The channels and the supergroups number their messages on their own,
+    /// so the messages in a range are the consecutive IDs there. The private
+    /// chats and the small groups share the IDs with the other chats of the
+    /// account, so the IDs are asked from Telegram instead. The command itself
+    /// is excluded.
+    async fn message_ids(
+        self,
+        msg: &ModuleMessage,
+        chat: &Chat,
+        reply_message_id: i32,
+        command_id: i32,
+    ) -> Result<Vec<i32>, ModuleError> {
+        let too_many = || ModuleError::usage(format!("一次最多只能轉錄 {} 則訊息。", MAX_RANGE));
+        let count = match self {
+            Self::Single => return Ok(vec![reply_message_id]),
+            Self::Count(count) if count > MAX_RANGE => return Err(too_many()),
+            Self::Count(count) => count,
+            Self::UntilCommand => command_id - reply_message_id,
+        };
+
+        let numbered_by_chat = match chat {
+            Chat::Channel(_) => true,
+            Chat::Group(group) => group.is_megagroup(),
+            Chat::User(_) => false,
+        };
+        if numbered_by_chat {
+            let end = reply_message_id.saturating_add(count).min(command_id);
+            if end - reply_message_id > MAX_RANGE {
+                return Err(too_many());
+            }
+            return Ok((reply_message_id..end.max(reply_message_id + 1)).collect());
+        }
+
+        // Take one more message to tell if `..` covers too many messages.
+        let (oldest_first, limit) = match self {
+            Self::Count(count) => (true, count as usize),
+            _ => (false, MAX_RANGE as usize + 1),
+        };
+        let mut message_ids = msg
+            .handle
+            .send(GetHistoryIdsCommand {
+                chat: chat.clone(),
+                min_id: reply_message_id,
+                max_id: command_id - 1,
+                from_self: false,
+                oldest_first,
+                limit,
+            })
+            .await?
+            .map_err(|e| ModuleError::rpc("failed to get the messages to forward", e))?;
+        if message_ids.len() > MAX_RANGE as usize {
+            return Err(too_many());
+        }
+        message_ids.sort_unstable();
+
+        // The replied message may have been deleted.
+        if message_ids.is_empty() {
+            message_ids.push(reply_message_id);
+        }
+        Ok(message_ids)
+    }
+}
+
+/// The arguments of `!cufwd`.
+#[derive(Debug)]
+struct FwdArgs {
+    /// The name of the target, or `None` for the default target.
+    target: Option<String>,
+    /// The messages to forward.
+    range: FwdRange,
+    /// Re-send the messages as copies.
+    copy: bool,
+    /// Retract the forward instead.
+    undo: bool,
+    /// The tags to attach, without the leading `#`.
+    tags: Vec<String>,
+}
+
+/// Parse the arguments of `!cufwd`, such as `work 5`.
+fn parse_args(args: &[String]) -> Result<FwdArgs, ModuleError> {
+    let mut parsed = FwdArgs {
+        target: None,
+        range: FwdRange::Single,
+        copy: false,
+        undo: false,
+        tags: Vec::new(),
+    };
+
+    for arg in args {
+        let arg = arg.as_str();
+
+        if let Some(tag) = arg.strip_prefix('#').filter(|tag| !tag.is_empty()) {
+            parsed.tags.push(tag.to_string());
+        } else if arg == UNDO_SUBCOMMAND && args.len() == 1 {
+            parsed.undo = true;
+        } else if arg == COPY_FLAG && !parsed.copy {
+            parsed.copy = true;
+        } else if arg == ".." && parsed.range == FwdRange::Single {
+            parsed.range = FwdRange::UntilCommand;
+        } else if let Ok(count) = arg.parse::<i32>() {
+            if count <= 0 || parsed.range != FwdRange::Single {
+                return Err(ModuleError::usage_with("無效的訊息數量。", USAGE));
+            }
+            parsed.range = FwdRange::Count(count);
+        } else if parsed.target.is_none() && !RESERVED_NAMES.contains(&arg) {
+            parsed.target = Some(arg.to_string());
+        } else {
+            return Err(ModuleError::usage_with("參數過多。", USAGE));
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Extract the arguments from the command message.
+///
+/// It returns `None` if the message is not the command
+/// sent by the account operator.
+fn extract_args(message: &Message, command: &str) -> Option<Vec<String>> {
+    let mut words = message.text().split_whitespace();
+
+    if words.next() == Some(command) && is_root_user(message) {
+        Some(words.map(String::from).collect())
+    } else {
+        None
+    }
+}
+
+/// Convert the reason why a message was not forwarded into [`ModuleError`].
+fn forward_error(e: ForwardError) -> ModuleError {
+    match e {
+        ForwardError::NotFound => ModuleError::usage("找不到要轉錄的訊息。"),
+        ForwardError::Unsupported => ModuleError::usage("這則訊息無法轉錄。"),
+        ForwardError::NotDelivered => ModuleError::permission_denied("這個聊天室禁止轉傳訊息。"),
+        ForwardError::Invocation(e) => match Arc::try_unwrap(e) {
+            Ok(e) => ModuleError::rpc("failed to forward the message", e),
+            // The error is shared with the other messages of the batch.
+            Err(e) => {
+                ModuleError::Internal(anyhow::anyhow!("failed to forward the message: {}", e))
+            }
+        },
+    }
+}
@@ -0,0 +1,224 @@
+//! PBot: Modules: FwdModule: Forward Records
+//!
+//! The records of the forwarded messages, which map the
+//! source messages to the forwarded messages and the confirmations.
+//!
+//! They are persisted in the storage, so the messages forwarded
+//! before a restart can still be retracted. They also serve as
+//! a searchable index of the archived messages.
+
+use crate::storage::{Persisted, StorageActor};
+use actix::Addr;
+use grammers_client::types::chat::PackedChat;
+use grammers_client::types::{Chat, Media, Message};
+use serde::{Deserialize, Serialize};
+
+/// The storage namespace of the forward records.
+const STORAGE_NAMESPACE: &str = "fwd";
+
+/// The record of a forward.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ForwardRecord {
+    /// The ID of the chat where the source messages are.
+    pub source_chat_id: i32,
+    /// The IDs of the source messages.
+    pub source_ids: Vec<i32>,
+    /// The ID of the sender of the source messages, if any.
+    pub sender_id: Option<i32>,
+    /// The serialized [`PackedChat`] where the messages were forwarded to.
+    pub target: Vec<u8>,
+    /// The IDs of the forwarded messages in the target chat.
+    pub forwarded_ids: Vec<i32>,
+    /// The ID of the confirmation in the source chat, if any.
+    pub confirmation_id: Option<i32>,
+    /// The tags attached with `!cufwd #tag`, without the leading `#`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The title of the source chat.
+    #[serde(default)]
+    pub source_chat_title: String,
+    /// The name of the sender of the source messages.
+    #[serde(default)]
+    pub sender_name: String,
+    /// When the first source message was sent, in UNIX timestamp.
+    #[serde(default)]
+    pub date: i64,
+    /// The text of the source messages.
+    #[serde(default)]
+    pub text: String,
+    /// The content hashes of the source messages, if computed.
+    ///
+    /// See [`content_hash`].
+    #[serde(default)]
+    pub content_hashes: Vec<u64>,
+}
+
+impl ForwardRecord {
+    /// Get the chat where the messages were forwarded to.
+    pub fn target_chat(&self) -> Option<Chat> {
+        PackedChat::from_bytes(&self.target)
+            .ok()
+            .map(|chat| chat.unpack())
+    }
+
+    /// Get the ID of the chat where the messages were forwarded to.
+    pub fn target_id(&self) -> Option<i32> {
+        self.target_chat().map(|chat| chat.id())
+    }
+
+    /// Whether the message in the source chat is one of the source
+    /// messages or the confirmation of this record.
+    pub fn is_related_to(&self, chat_id: i32, message_id: i32) -> bool {
+        self.source_chat_id == chat_id
+            && (self.source_ids.contains(&message_id) || self.confirmation_id == Some(message_id))
+    }
+
+    /// Whether the record matches every term of the query.
+    ///
+    /// The terms starting with `#` match the tags, and the other
+    /// terms match the text, the source chat or the sender.
+    /// Both are case-insensitive.
+    pub fn matches(&self, terms: &[String]) -> bool {
+        terms.iter().all(|term| {
+            let term = term.to_lowercase();
+
+            match term.strip_prefix('#') {
+                Some(tag) => self.tags.iter().any(|t| t.to_lowercase() == tag),
+                None => [&self.text, &self.source_chat_title, &self.sender_name]
+                    .iter()
+                    .any(|field| field.to_lowercase().contains(&term)),
+            }
+        })
+    }
+}
+
+/// Compute the content hash of the message from its text and media.
+///
+/// It returns `None` if the message has neither text nor media.
+/// We use FNV-1a since the hashes are persisted and the hasher
+/// of the standard library is not stable across releases.
+pub fn content_hash(message: &Message) -> Option<u64> {
+    let media_id = match message.media() {
+        Some(Media::Photo(photo)) => Some(photo.id()),
+        Some(Media::Document(document)) => Some(document.id()),
+        Some(Media::Sticker(sticker)) => Some(sticker.document.id()),
+        _ => None,
+    };
+    if message.text().is_empty() && media_id.is_none() {
+        return None;
+    }
+
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let media_id = media_id.unwrap_or_default().to_le_bytes();
+    let bytes = message.text().as_bytes().iter().chain(media_id.iter());
+
+    Some(bytes.fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    }))
+}
+
+/// The persisted forward records.
+pub struct ForwardRecords {
+    /// The records, from the oldest to the newest.
+    records: Persisted<Vec<ForwardRecord>>,
+}
+
+impl ForwardRecords {
+    /// Load the forward records from the storage.
+    pub async fn load(storage: Addr<StorageActor>) -> anyhow::Result<Self> {
+        Ok(Self {
+            records: Persisted::load(storage, STORAGE_NAMESPACE).await?,
+        })
+    }
+
+    /// Add the record and persist it.
+    pub async fn insert(&self, record: ForwardRecord) -> anyhow::Result<()> {
+        self.records
+            .update(|records| {
+                records.push(record);
+                Some(())
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Find the newest record related to the message in the source chat.
+    ///
+    /// See [`ForwardRecord::is_related_to`].
+    pub async fn find(&self, chat_id: i32, message_id: i32) -> Option<ForwardRecord> {
+        self.records
+            .read(|records| {
+                records
+                    .iter()
+                    .rev()
+                    .find(|record| record.is_related_to(chat_id, message_id))
+                    .cloned()
+            })
+            .await
+    }
+
+    /// Find the record which has archived the message in the target chat.
+    ///
+    /// The message is a duplicate if it is one of the source messages
+    /// of the record, or if its content hash matches the record.
+    pub async fn find_duplicate(
+        &self,
+        chat_id: i32,
+        message_id: i32,
+        content_hash: Option<u64>,
+        target_id: i32,
+    ) -> Option<ForwardRecord> {
+        self.records
+            .read(|records| {
+                records
+                    .iter()
+                    .rev()
+                    .filter(|record| record.target_id() == Some(target_id))
+                    .find(|record| {
+                        (record.source_chat_id == chat_id
+                            && record.source_ids.contains(&message_id))
+                            || matches!(content_hash, Some(hash) if record.content_hashes.contains(&hash))
+                    })
+                    .cloned()
+            })
+            .await
+    }
+
+    /// Find the records matching the query, from the newest to the oldest.
+    ///
+    /// See [`ForwardRecord::matches`].
+    pub async fn search(&self, terms: &[String], limit: usize) -> Vec<ForwardRecord> {
+        self.records
+            .read(|records| {
+                records
+                    .iter()
+                    .rev()
+                    .filter(|record| record.matches(terms))
+                    .take(limit)
+                    .cloned()
+                    .collect()
+            })
+            .await
+    }
+
+    /// Get all the records, from the oldest to the newest.
+    pub async fn all(&self) -> Vec<ForwardRecord> {
+        self.records.read(Clone::clone).await
+    }
+
+    /// Remove the record and persist the remaining records.
+    pub async fn remove(&self, record: &ForwardRecord) -> anyhow::Result<()> {
+        self.records
+            .update(|records| {
+                let count = records.len();
+                records.retain(|r| r != record);
+                (records.len() != count).then_some(())
+            })
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,41 +1,311 @@
 //! PBot: Modules: GetInfoModule
 //!
-//! Get the information of the message.
+//! Get the information of the message replied to with `!info`.
 //! For debugging purpose.
+//!
+//! It shows the IDs, the sender, the chat, the reply and forward
+//! headers, the entities, the media and the other metadata of the
+//! message. With `!info --raw`, the full TL object of the message
+//! is attached as a JSON file.
 
 use actix::prelude::*;
+use chrono::{DateTime, TimeZone, Utc};
+use grammers_client::types::{Chat, Media, Message};
+use grammers_tl_types as tl;
 use log::info;
 use pbot_modules_derive::{ModuleActivator, ModuleActor, ModuleMeta};
 
-use super::base::ModuleMessage;
+use crate::telegram::{
+    client::commands::{GetMessagesCommand, GetRawMessageCommand},
+    format::{entity_bounds, FormattedText},
+    tl_json::to_json,
+    user::is_root_user,
+};
+
+use super::base::{
+    error::{ModuleError, ModuleResult},
+    ModuleMessage,
+};
+
+const CMD: &str = "!info";
+
+/// The flag to attach the full TL object, such as `!info --raw`.
+const RAW_FLAG: &str = "--raw";
 
 /// The GetInfoModule module that is for debugging.
-///
-/// We don't recommended you enabling this without a reasonable reason,
-/// since it is useless while noising.
 #[derive(Clone, ModuleActor, ModuleActivator, ModuleMeta)]
 #[name = "GetInfoModule"]
 pub struct GetInfoModuleActor;
 
 impl Handler<ModuleMessage> for GetInfoModuleActor {
-    type Result = ResponseActFuture<Self, anyhow::Result<()>>;
+    type Result = ResponseActFuture<Self, ModuleResult>;
 
     fn handle(&mut self, msg: ModuleMessage, _: &mut Self::Context) -> Self::Result {
-        // Destruct msg and get `handle` and `message`.
-        let ModuleMessage { handle: _, message } = msg;
-
         async move {
-            // Show the text, sender and chat of this message.
-            info!(
-                "MSG={:#?}; BY={:#?}; CHAT_ID={:#?}",
-                message.read().await.text(),
-                message.read().await.sender(),
-                message.read().await.chat()
-            );
+            // Take a snapshot of the message, so we don't need to lock it again.
+            let message = msg.snapshot().await;
+
+            let args = match extract_args(&message) {
+                Some(args) => args,
+                None => return Ok(()),
+            };
+            let raw = match args.first().map(String::as_str) {
+                None => false,
+                Some(RAW_FLAG) => true,
+                Some(_) => {
+                    return Err(ModuleError::usage_with(
+                        "未知的參數。",
+                        format!("{} [{}]", CMD, RAW_FLAG),
+                    ))
+                }
+            };
+
+            let reply_message_id = match message.reply_to_message_id() {
+                Some(reply_message_id) => reply_message_id,
+                None => {
+                    return Err(ModuleError::usage_with(
+                        "請回覆訊息。",
+                        format!("回覆要查看的訊息並輸入 {} [{}]", CMD, RAW_FLAG),
+                    ))
+                }
+            };
+            let chat = message.chat();
 
+            if raw {
+                let raw_message = msg
+                    .handle
+                    .send(GetRawMessageCommand {
+                        chat: chat.clone(),
+                        message_id: reply_message_id,
+                    })
+                    .await?
+                    .map_err(|e| ModuleError::rpc("failed to get the message", e))?
+                    .ok_or_else(|| ModuleError::usage("找不到這則訊息。"))?;
+                let content = serde_json::to_vec_pretty(&to_json(&raw_message))
+                    .map_err(anyhow::Error::from)?;
+
+                msg.reply_file(
+                    format!("message-{}-{}.json", chat.id(), reply_message_id),
+                    content,
+                    format!("🧾 訊息 {} 的 TL 物件。", reply_message_id),
+                )
+                .await?;
+                return Ok(());
+            }
+
+            let replied = msg
+                .handle
+                .send(GetMessagesCommand {
+                    chat,
+                    message_ids: vec![reply_message_id],
+                })
+                .await?
+                .map_err(|e| ModuleError::rpc("failed to get the message", e))?
+                .pop()
+                .flatten()
+                .ok_or_else(|| ModuleError::usage("找不到這則訊息。"))?;
+
+            info!("Showing the information of message {}.", replied.id());
+            msg.edit_or_reply(describe(&replied)).await?;
             Ok(())
         }
         .into_actor(self)
         .boxed_local()
     }
 }
+
+/// Describe the message in a formatted breakdown.
+fn describe(message: &Message) -> FormattedText {
+    let chat = message.chat();
+    let mut fields = vec![
+        ("訊息 ID", message.id().to_string()),
+        ("聊天室", describe_chat(&chat)),
+        (
+            "寄件者",
+            message
+                .sender()
+                .map(|sender| describe_chat(&sender))
+                .unwrap_or_else(|| "（無）".to_string()),
+        ),
+        ("日期", format_date(message.date())),
+    ];
+
+    if let Some(edit_date) = message.edit_date() {
+        fields.push(("編輯日期", format_date(edit_date)));
+    }
+    if let Some(tl::enums::MessageReplyHeader::Header(reply)) = message.reply_header() {
+        let mut value = format!("訊息 {}", reply.reply_to_msg_id);
+        if let Some(peer) = &reply.reply_to_peer_id {
+            value.push_str(&format!("，位於 {}", describe_peer(peer)));
+        }
+        if let Some(top_id) = reply.reply_to_top_id {
+            value.push_str(&format!("，討論串 {}", top_id));
+        }
+        fields.push(("回覆", value));
+    }
+    if let Some(tl::enums::MessageFwdHeader::Header(forward)) = message.forward_header() {
+        let from = match (&forward.from_id, &forward.from_name) {
+            (Some(peer), _) => describe_peer(peer),
+            (None, Some(name)) => name.clone(),
+            (None, None) => "（隱藏）".to_string(),
+        };
+        let mut value = format!("來自 {}，原始日期 {}", from, format_timestamp(forward.date));
+        if let Some(post) = forward.channel_post {
+            value.push_str(&format!("，頻道貼文 {}", post));
+        }
+        if let Some(author) = &forward.post_author {
+            value.push_str(&format!("，作者 {}", author));
+        }
+        if let (Some(peer), Some(id)) = (&forward.saved_from_peer, forward.saved_from_msg_id) {
+            value.push_str(&format!("，儲存自 {} 的訊息 {}", describe_peer(peer), id));
+        }
+        fields.push(("轉傳", value));
+    }
+    if let Some(entities) = message.fmt_entities().filter(|e| !e.is_empty()) {
+        let entities = entities
+            .iter()
+            .map(describe_entity)
+            .collect::<Vec<_>>()
+            .join("、");
+        fields.push(("實體", entities));
+    }
+    if let Some(media) = message.media() {
+        fields.push(("媒體", describe_media(&media)));
+    }
+    if let Some(grouped_id) = message.grouped_id() {
+        fields.push(("群組 ID", grouped_id.to_string()));
+    }
+    if let Some(via_bot_id) = message.via_bot_id() {
+        fields.push(("經由機器人", via_bot_id.to_string()));
+    }
+    if let Some(views) = message.view_count() {
+        fields.push(("瀏覽數", views.to_string()));
+    }
+    if let Some(forwards) = message.forward_count() {
+        fields.push(("轉傳數", forwards.to_string()));
+    }
+    if let Some(author) = message.post_author() {
+        fields.push(("貼文作者", author.to_string()));
+    }
+    if let Some(action) = message.action() {
+        fields.push(("服務動作", variant_name(action)));
+    }
+
+    let flags = [
+        (message.outgoing(), "outgoing"),
+        (message.mentioned(), "mentioned"),
+        (message.silent(), "silent"),
+        (message.post(), "post"),
+        (message.pinned(), "pinned"),
+        (message.from_scheduled(), "from_scheduled"),
+        (message.edit_hide(), "edit_hide"),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, name)| *name)
+    .collect::<Vec<_>>();
+    if !flags.is_empty() {
+        fields.push(("旗標", flags.join(", ")));
+    }
+
+    let mut text = FormattedText::plain("🔍 訊息資訊");
+    for (label, value) in fields {
+        text = text.push("\n").bold(label).push("：").code(value);
+    }
+    text
+}
+
+/// Describe the chat with its name and ID.
+fn describe_chat(chat: &Chat) -> String {
+    let kind = match chat {
+        Chat::User(_) => "使用者",
+        Chat::Group(_) => "群組",
+        Chat::Channel(_) => "頻道",
+    };
+
+    format!("{}（{} {}）", chat.name(), kind, chat.id())
+}
+
+/// Describe the raw peer with its type and ID.
+fn describe_peer(peer: &tl::enums::Peer) -> String {
+    match peer {
+        tl::enums::Peer::User(user) => format!("使用者 {}", user.user_id),
+        tl::enums::Peer::Chat(chat) => format!("群組 {}", chat.chat_id),
+        tl::enums::Peer::Channel(channel) => format!("頻道 {}", channel.channel_id),
+    }
+}
+
+/// Describe the entity with its type and range, such as `Bold@0+4`.
+fn describe_entity(entity: &tl::enums::MessageEntity) -> String {
+    let (offset, length) = entity_bounds(entity);
+
+    format!("{}@{}+{}", variant_name(entity), offset, length)
+}
+
+/// Describe the media with its type and size.
+fn describe_media(media: &Media) -> String {
+    match media {
+        Media::Photo(photo) => format!("相片（ID {}）", photo.id()),
+        Media::Sticker(sticker) => format!(
+            "貼圖 {}（ID {}，{}）",
+            sticker.emoji(),
+            sticker.document.id(),
+            format_size(sticker.document.size())
+        ),
+        Media::Document(document) => format!(
+            "文件 {}（ID {}，{}，{}）",
+            document.name(),
+            document.id(),
+            document.mime_type().unwrap_or("未知類型"),
+            format_size(document.size())
+        ),
+        _ => "未知媒體".to_string(),
+    }
+}
+
+/// Get the name of the enum variant from its `Debug` representation.
+fn variant_name(value: &impl std::fmt::Debug) -> String {
+    format!("{:?}", value)
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Format the size in bytes with a binary unit, such as `1.5 MiB`.
+fn format_size(size: i32) -> String {
+    let size = f64::from(size);
+
+    match size {
+        s if s >= 1024.0 * 1024.0 => format!("{:.1} MiB", s / 1024.0 / 1024.0),
+        s if s >= 1024.0 => format!("{:.1} KiB", s / 1024.0),
+        s => format!("{} B", s),
+    }
+}
+
+/// Format the date in UTC.
+fn format_date(date: DateTime<Utc>) -> String {
+    date.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+/// Format the UNIX timestamp in UTC.
+fn format_timestamp(timestamp: i32) -> String {
+    Utc.timestamp_opt(i64::from(timestamp), 0)
+        .single()
+        .map(format_date)
+        .unwrap_or_default()
+}
+
+/// Extract the arguments of `!info`.
+///
+/// It returns `None` if the message is not the command,
+/// or it is not from the account owner (root user).
+fn extract_args(message: &Message) -> Option<Vec<String>> {
+    let mut words = message.text().split_whitespace();
+
+    if words.next() == Some(CMD) && is_root_user(message) {
+        Some(words.map(String::from).collect())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,368 @@
+//! PBot: Modules: NotesModule
+//!
+//! Save the messages as notes, and send them again by their names.
+//!
+//! Reply to a message with `!save <name>` to save its text and media
+//! as a note of the chat, or `!save --global <name>` to save it as
+//! a note available in every chat. Send `#name` or `!get <name>` to
+//! send the note, replying to what the command replies to. The note
+//! of the chat takes precedence over the global one.
+//!
+//! `!notes` lists the notes available in the chat, and
+//! `!clear [--global] <name>` deletes a note.
+
+pub mod store;
+
+use std::sync::Arc;
+
+use actix::prelude::*;
+use grammers_client::types::Message;
+use log::info;
+use pbot_modules_derive::{ModuleActivator, ModuleActor, ModuleMeta};
+
+use crate::telegram::{
+    client::{
+        commands::{DeleteMessagesCommand, SendMessageCommand},
+        outgoing::OutgoingMessage,
+    },
+    format::FormattedText,
+    stash::{drop_media, fetch_media, stash_media},
+    user::is_root_user,
+};
+
+use self::store::{Note, NoteStore};
+
+use super::base::{
+    error::{ModuleError, ModuleResult},
+    response::RESPONSE_PREFIX,
+    ModuleMessage,
+};
+
+/// The command to save a note, such as `!save hello`.
+const SAVE_CMD: &str = "!save";
+
+/// The command to send a note, such as `!get hello`.
+const GET_CMD: &str = "!get";
+
+/// The command to list the notes.
+const LIST_CMD: &str = "!notes";
+
+/// The command to delete a note, such as `!clear hello`.
+const CLEAR_CMD: &str = "!clear";
+
+/// The flag to save or delete a global note, such as `!save --global hello`.
+const GLOBAL_FLAG: &str = "--global";
+
+/// The prefix to send a note, such as `#hello`.
+const NOTE_PREFIX: char = '#';
+
+/// The maximum length of the note names, in characters.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// The NotesModule actor.
+#[derive(Clone, ModuleActor, ModuleActivator, ModuleMeta)]
+#[name = "NotesModule"]
+pub struct NotesModuleActor {
+    /// The saved notes.
+    pub notes: Arc<NoteStore>,
+}
+
+impl Handler<ModuleMessage> for NotesModuleActor {
+    type Result = ResponseActFuture<Self, ModuleResult>;
+
+    fn handle(&mut self, msg: ModuleMessage, _: &mut Self::Context) -> Self::Result {
+        // Clone the fields of self to move into the following block.
+        let notes = self.notes.clone();
+
+        async move {
+            // Take a snapshot of the message, so we don't need to lock it again.
+            let mut message = msg.snapshot().await;
+
+            if let Some(args) = extract_args(&message, SAVE_CMD) {
+                let (global, name) = parse_scope(&args, SAVE_CMD)?;
+                handle_save(&msg, &mut message, &notes, global, name).await
+            } else if let Some(args) = extract_args(&message, GET_CMD) {
+                let name = match args.as_slice() {
+                    [name] => parse_name(name, GET_CMD)?,
+                    _ => {
+                        return Err(ModuleError::usage_with(
+                            "請指定一個筆記名稱。",
+                            usage(GET_CMD),
+                        ))
+                    }
+                };
+                match notes.find(message.chat().id(), &name).await {
+                    Some(note) => send_note(&msg, &message, note).await,
+                    None => Err(ModuleError::usage(format!("找不到筆記 #{}。", name))),
+                }
+            } else if extract_args(&message, LIST_CMD).is_some() {
+                handle_list(&msg, &message, &notes).await
+            } else if let Some(args) = extract_args(&message, CLEAR_CMD) {
+                let (global, name) = parse_scope(&args, CLEAR_CMD)?;
+                handle_clear(&msg, &message, &notes, global, name).await
+            } else if let Some(name) = extract_hashtag(&message) {
+                // A hashtag not naming any note is just a hashtag.
+                match notes.find(message.chat().id(), &name).await {
+                    Some(note) => send_note(&msg, &message, note).await,
+                    None => Ok(()),
+                }
+            } else {
+                Ok(())
+            }
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Save the message replied to as a note with `!save [--global] <name>`.
+async fn handle_save(
+    msg: &ModuleMessage,
+    message: &mut Message,
+    notes: &NoteStore,
+    global: bool,
+    name: String,
+) -> ModuleResult {
+    let replied = match message.get_reply().await? {
+        Some(replied) => replied,
+        None => {
+            return Err(ModuleError::usage_with(
+                "請回覆要儲存的訊息。",
+                usage(SAVE_CMD),
+            ))
+        }
+    };
+
+    let text = FormattedText::with_entities(
+        replied.text(),
+        replied.fmt_entities().cloned().unwrap_or_default(),
+    );
+    let media = replied.media();
+    if text.is_empty() && media.is_none() {
+        return Err(ModuleError::usage("這則訊息沒有可以儲存的文字或媒體。"));
+    }
+
+    // Stash the media, so the note doesn't depend on
+    // the original message, which may be deleted.
+    let media = match media {
+        Some(media) => {
+            let caption = format!("{} 📝 筆記 #{} 的媒體", RESPONSE_PREFIX, name);
+            stash_media(&msg.handle, &media, caption)
+                .await?
+                .map_err(|e| ModuleError::rpc("failed to stash the media", e))?
+        }
+        None => None,
+    };
+
+    let chat_id = (!global).then(|| message.chat().id());
+    let replaced = notes
+        .insert(Note {
+            name: name.clone(),
+            chat_id,
+            text: text.into(),
+            media,
+        })
+        .await?;
+    if let Some(media) = replaced.as_ref().and_then(|note| note.media.as_ref()) {
+        drop_media(&msg.handle, media).await;
+    }
+    info!("📝 Saved the note #{} ({:?}).", name, chat_id);
+
+    let text = FormattedText::new()
+        .push("✅ 已儲存")
+        .push(scope_name(chat_id))
+        .push("筆記 ")
+        .code(format!("{}{}", NOTE_PREFIX, name));
+    msg.edit_or_reply(match replaced {
+        Some(_) => text.push("，並取代了舊的內容。"),
+        None => text.push("。"),
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Send the note in place of the message invoking it.
+///
+/// The note replies to what the invoking message replies to,
+/// and the invoking message is deleted.
+async fn send_note(msg: &ModuleMessage, message: &Message, note: Note) -> ModuleResult {
+    let chat = message.chat();
+    let mut input =
+        OutgoingMessage::from(note.text.formatted()).reply_to(message.reply_to_message_id());
+
+    if let Some(media) = &note.media {
+        match fetch_media(&msg.handle, media)
+            .await?
+            .map_err(|e| ModuleError::rpc("failed to get the media of the note", e))?
+        {
+            Some(media) => input = input.copy_media(&media),
+            None => {
+                return Err(ModuleError::usage(format!(
+                    "筆記 #{} 的媒體已被刪除，請重新儲存。",
+                    note.name
+                )))
+            }
+        }
+    }
+
+    msg.handle
+        .send(SendMessageCommand(chat.clone(), input))
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to send the note", e))?;
+    msg.handle
+        .send(DeleteMessagesCommand {
+            chat,
+            message_ids: vec![message.id()],
+        })
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to delete the command", e))?;
+
+    Ok(())
+}
+
+/// List the notes available in the chat with `!notes`.
+async fn handle_list(msg: &ModuleMessage, message: &Message, notes: &NoteStore) -> ModuleResult {
+    let chat_id = message.chat().id();
+    let (global, local): (Vec<_>, Vec<_>) = notes
+        .list(chat_id)
+        .await
+        .into_iter()
+        .partition(|note| note.chat_id.is_none());
+
+    if global.is_empty() && local.is_empty() {
+        msg.edit_or_reply("📝 沒有可用的筆記。").await?;
+        return Ok(());
+    }
+
+    let mut text = FormattedText::new().push("📝 可用的筆記（📎 表示附有媒體）：");
+    for (title, notes) in [("\n此聊天：", local), ("\n全域：", global)] {
+        if notes.is_empty() {
+            continue;
+        }
+
+        text = text.push(title);
+        for note in notes {
+            text = text.push(" ").code(format!("{}{}", NOTE_PREFIX, note.name));
+            if note.media.is_some() {
+                text = text.push("📎");
+            }
+        }
+    }
+    msg.edit_or_reply(text).await?;
+
+    Ok(())
+}
+
+/// Delete the note with `!clear [--global] <name>`.
+async fn handle_clear(
+    msg: &ModuleMessage,
+    message: &Message,
+    notes: &NoteStore,
+    global: bool,
+    name: String,
+) -> ModuleResult {
+    let chat_id = (!global).then(|| message.chat().id());
+    let removed = match notes.remove(chat_id, &name).await? {
+        Some(removed) => removed,
+        None => {
+            return Err(ModuleError::usage(format!(
+                "找不到{}筆記 #{}。",
+                scope_name(chat_id),
+                name
+            )))
+        }
+    };
+    if let Some(media) = &removed.media {
+        drop_media(&msg.handle, media).await;
+    }
+    info!("🗑️ Deleted the note #{} ({:?}).", name, chat_id);
+
+    msg.edit_or_reply(
+        FormattedText::new()
+            .push("🗑️ 已刪除")
+            .push(scope_name(chat_id))
+            .push("筆記 ")
+            .code(format!("{}{}", NOTE_PREFIX, name))
+            .push("。"),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Describe the scope of the note, such as `全域`.
+fn scope_name(chat_id: Option<i32>) -> &'static str {
+    match chat_id {
+        Some(_) => "此聊天的",
+        None => "全域",
+    }
+}
+
+/// Get the usage of the command.
+fn usage(command: &str) -> String {
+    match command {
+        GET_CMD => format!("{} <名稱>，或 {}名稱", GET_CMD, NOTE_PREFIX),
+        _ => format!("{} [{}] <名稱>", command, GLOBAL_FLAG),
+    }
+}
+
+/// Parse the arguments in the form of `[--global] <name>`.
+fn parse_scope(args: &[String], command: &str) -> Result<(bool, String), ModuleError> {
+    let (flags, names): (Vec<_>, Vec<_>) = args.iter().partition(|arg| *arg == GLOBAL_FLAG);
+
+    match names.as_slice() {
+        [name] => Ok((!flags.is_empty(), parse_name(name, command)?)),
+        _ => Err(ModuleError::usage_with(
+            "請指定一個筆記名稱。",
+            usage(command),
+        )),
+    }
+}
+
+/// Normalize the note name, and check if it is valid.
+///
+/// The name is case-insensitive, and the leading `#` is optional.
+fn parse_name(name: &str, command: &str) -> Result<String, ModuleError> {
+    let name = name.trim_start_matches(NOTE_PREFIX).to_lowercase();
+
+    if name.is_empty()
+        || name.chars().count() > MAX_NAME_LENGTH
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(ModuleError::usage_with(
+            format!(
+                "筆記名稱只能包含文字、數字、_ 和 -，且不超過 {} 個字。",
+                MAX_NAME_LENGTH
+            ),
+            usage(command),
+        ));
+    }
+
+    Ok(name)
+}
+
+/// Get the note name if the message is `#name` sent by us.
+fn extract_hashtag(message: &Message) -> Option<String> {
+    let text = message.text().trim();
+
+    if text.starts_with(NOTE_PREFIX) && !text.contains(char::is_whitespace) && is_root_user(message)
+    {
+        parse_name(text, GET_CMD).ok()
+    } else {
+        None
+    }
+}
+
+/// Extract the arguments if the message is the command sent by us.
+fn extract_args(message: &Message, command: &str) -> Option<Vec<String>> {
+    let mut words = message.text().split_whitespace();
+
+    if words.next() == Some(command) && is_root_user(message) {
+        Some(words.map(String::from).collect())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,111 @@
+//! PBot: Modules: NotesModule: Note Store
+//!
+//! The saved notes, either of a chat or global.
+//!
+//! The media of the notes are stashed in Saved Messages, so the notes
+//! keep working even if the original messages are gone.
+//! See [`crate::telegram::stash`] for details.
+//!
+//! They are persisted in the storage, so the notes survive restarts.
+
+use crate::storage::{Persisted, StorageActor};
+use crate::telegram::stash::{MediaRef, StashedText};
+use actix::Addr;
+use serde::{Deserialize, Serialize};
+
+/// The storage namespace of the notes.
+const STORAGE_NAMESPACE: &str = "notes";
+
+/// A saved note.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    /// The name of the note, in lowercase and without the leading `#`.
+    pub name: String,
+    /// The ID of the chat which the note belongs to, or `None` if it is global.
+    pub chat_id: Option<i32>,
+    /// The text of the note.
+    #[serde(flatten)]
+    pub text: StashedText,
+    /// The media of the note, if any.
+    pub media: Option<MediaRef>,
+}
+
+/// The persisted notes.
+pub struct NoteStore {
+    /// The notes, from the oldest to the newest.
+    notes: Persisted<Vec<Note>>,
+}
+
+impl NoteStore {
+    /// Load the notes from the storage.
+    pub async fn load(storage: Addr<StorageActor>) -> anyhow::Result<Self> {
+        Ok(Self {
+            notes: Persisted::load(storage, STORAGE_NAMESPACE).await?,
+        })
+    }
+
+    /// Save the note and persist it.
+    ///
+    /// It returns the note replaced, which has the same name in the same scope.
+    pub async fn insert(&self, note: Note) -> anyhow::Result<Option<Note>> {
+        let replaced = self
+            .notes
+            .update(|notes| {
+                let index = notes
+                    .iter()
+                    .position(|n| n.chat_id == note.chat_id && n.name == note.name);
+                let replaced = index.map(|index| notes.remove(index));
+                notes.push(note);
+                Some(replaced)
+            })
+            .await?;
+
+        Ok(replaced.flatten())
+    }
+
+    /// Find the note by its name for the chat.
+    ///
+    /// The note of the chat takes precedence over the global one.
+    pub async fn find(&self, chat_id: i32, name: &str) -> Option<Note> {
+        self.notes
+            .read(|notes| {
+                notes
+                    .iter()
+                    .find(|note| note.chat_id == Some(chat_id) && note.name == name)
+                    .or_else(|| {
+                        notes
+                            .iter()
+                            .find(|note| note.chat_id.is_none() && note.name == name)
+                    })
+                    .cloned()
+            })
+            .await
+    }
+
+    /// List the notes available in the chat, including the global ones.
+    pub async fn list(&self, chat_id: i32) -> Vec<Note> {
+        self.notes
+            .read(|notes| {
+                notes
+                    .iter()
+                    .filter(|note| note.chat_id.is_none() || note.chat_id == Some(chat_id))
+                    .cloned()
+                    .collect()
+            })
+            .await
+    }
+
+    /// Remove the note in the scope and persist the remaining notes.
+    ///
+    /// It returns the removed note, or `None` if there is no such note.
+    pub async fn remove(&self, chat_id: Option<i32>, name: &str) -> anyhow::Result<Option<Note>> {
+        self.notes
+            .update(|notes| {
+                let index = notes
+                    .iter()
+                    .position(|note| note.chat_id == chat_id && note.name == name)?;
+                Some(notes.remove(index))
+            })
+            .await
+    }
+}
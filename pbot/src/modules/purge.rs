@@ -0,0 +1,235 @@
+//! PBot: Modules: PurgeModule
+//!
+//! Delete the messages in bulk.
+//!
+//! * `!purge`: reply to a message to delete every message from it
+//!   up to the command. It requires the right to delete messages
+//!   in the groups and the channels.
+//! * `!purgeme <N>`: delete our own last N messages in any chat.
+//! * `!del`: reply to a message to delete it.
+//!
+//! The command is deleted along with the messages, and a summary of
+//! how many messages were removed deletes itself after a few seconds.
+//! The deletion is batched and rate-limited by [`ClientActor`].
+//!
+//! [`ClientActor`]: crate::telegram::client::ClientActor
+
+use std::time::Duration;
+
+use actix::prelude::*;
+use grammers_client::types::{Chat, Message};
+use log::info;
+use pbot_modules_derive::{ModuleActivator, ModuleActor, ModuleMeta};
+
+use crate::telegram::{
+    cleanup::commands::ScheduleDeletionCommand,
+    client::{
+        commands::{DeleteMessagesCommand, GetAdminsCommand, GetHistoryIdsCommand, GetMeCommand},
+        outgoing::SentMessage,
+    },
+    user::is_root_user,
+};
+
+use super::base::{
+    error::{ModuleError, ModuleResult},
+    ModuleMessage,
+};
+
+/// The command to delete the messages from the replied one, such as `!purge`.
+const PURGE_CMD: &str = "!purge";
+
+/// The command to delete our own last messages, such as `!purgeme 10`.
+const PURGE_ME_CMD: &str = "!purgeme";
+
+/// The command to delete the replied message, such as `!del`.
+const DEL_CMD: &str = "!del";
+
+/// How long the summary stays before deleting itself.
+const SUMMARY_LIFETIME: Duration = Duration::from_secs(5);
+
+/// The PurgeModule actor.
+#[derive(Clone, ModuleActor, ModuleActivator, ModuleMeta)]
+#[name = "PurgeModule"]
+pub struct PurgeModuleActor;
+
+impl Handler<ModuleMessage> for PurgeModuleActor {
+    type Result = ResponseActFuture<Self, ModuleResult>;
+
+    fn handle(&mut self, msg: ModuleMessage, _: &mut Self::Context) -> Self::Result {
+        async move {
+            // Take a snapshot of the message, so we don't need to lock it again.
+            let mut message = msg.snapshot().await;
+
+            if extract_args(&message, PURGE_CMD).is_some() {
+                handle_purge(&msg, &mut message).await
+            } else if let Some(args) = extract_args(&message, PURGE_ME_CMD) {
+                let count = match args.as_slice() {
+                    [count] => count.parse::<usize>().ok().filter(|count| *count > 0),
+                    _ => None,
+                };
+                match count {
+                    Some(count) => handle_purge_me(&msg, &message, count).await,
+                    None => Err(ModuleError::usage_with(
+                        "請指定要刪除的訊息數量。",
+                        format!("{} <數量>", PURGE_ME_CMD),
+                    )),
+                }
+            } else if extract_args(&message, DEL_CMD).is_some() {
+                handle_del(&msg, &mut message).await
+            } else {
+                Ok(())
+            }
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Delete every message from the replied one up to the command with `!purge`.
+async fn handle_purge(msg: &ModuleMessage, message: &mut Message) -> ModuleResult {
+    let reply_id = match message.reply_to_message_id() {
+        Some(reply_id) => reply_id,
+        None => {
+            return Err(ModuleError::usage_with(
+                "請回覆要開始刪除的訊息。",
+                PURGE_CMD,
+            ))
+        }
+    };
+    let chat = message.chat();
+    ensure_delete_rights(msg, &chat).await?;
+
+    let message_ids = msg
+        .handle
+        .send(GetHistoryIdsCommand {
+            chat: chat.clone(),
+            min_id: reply_id,
+            max_id: message.id(),
+            from_self: false,
+            oldest_first: false,
+            limit: usize::MAX,
+        })
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to get the messages to purge", e))?;
+
+    delete_and_summarize(msg, chat, message_ids).await
+}
+
+/// Delete our own last N messages with `!purgeme <N>`.
+async fn handle_purge_me(msg: &ModuleMessage, message: &Message, count: usize) -> ModuleResult {
+    let chat = message.chat();
+
+    let mut message_ids = msg
+        .handle
+        .send(GetHistoryIdsCommand {
+            chat: chat.clone(),
+            min_id: 1,
+            max_id: message.id() - 1,
+            from_self: true,
+            oldest_first: false,
+            limit: count,
+        })
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to get our messages", e))?;
+    message_ids.push(message.id());
+
+    delete_and_summarize(msg, chat, message_ids).await
+}
+
+/// Delete the replied message with `!del`.
+async fn handle_del(msg: &ModuleMessage, message: &mut Message) -> ModuleResult {
+    let replied = match message.get_reply().await? {
+        Some(replied) => replied,
+        None => return Err(ModuleError::usage_with("請回覆要刪除的訊息。", DEL_CMD)),
+    };
+    let chat = message.chat();
+    if !replied.outgoing() {
+        ensure_delete_rights(msg, &chat).await?;
+    }
+
+    let message_ids = vec![replied.id(), message.id()];
+    delete_and_summarize(msg, chat, message_ids).await
+}
+
+/// Delete the messages including the command, and send a self-deleting summary.
+///
+/// The command is not counted in the summary.
+async fn delete_and_summarize(
+    msg: &ModuleMessage,
+    chat: Chat,
+    message_ids: Vec<i32>,
+) -> ModuleResult {
+    let requested = message_ids.len();
+    let deleted = msg
+        .handle
+        .send(DeleteMessagesCommand {
+            chat: chat.clone(),
+            message_ids,
+        })
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to delete the messages", e))?;
+    let removed = deleted.saturating_sub(1);
+    info!(
+        "🧹 Deleted {} of {} messages in {}.",
+        deleted,
+        requested,
+        chat.id()
+    );
+
+    // The command has gone, so send the summary as a new message.
+    let summary = msg
+        .respond(format!("🧹 已刪除 {} 則訊息。", removed))
+        .await?;
+    if let Some(cleanup) = &msg.options.cleanup {
+        cleanup.do_send(ScheduleDeletionCommand {
+            chat,
+            message_ids: summary.iter().filter_map(SentMessage::id).collect(),
+            after: SUMMARY_LIFETIME,
+        });
+    }
+
+    Ok(())
+}
+
+/// Check if we can delete the messages of the others in the chat.
+///
+/// We can always delete the messages in the private chats. In the groups
+/// and the channels, we must be the creator or have the right to delete messages.
+async fn ensure_delete_rights(msg: &ModuleMessage, chat: &Chat) -> ModuleResult {
+    if let Chat::User(_) = chat {
+        return Ok(());
+    }
+
+    let me = msg
+        .handle
+        .send(GetMeCommand)
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to get the logged-in user", e))?;
+    let admins = msg
+        .handle
+        .send(GetAdminsCommand { chat: chat.clone() })
+        .await?
+        .map_err(|e| ModuleError::rpc("failed to get the administrators", e))?;
+
+    let allowed = admins.iter().any(|admin| {
+        admin.user_id == me.id() && (admin.is_creator || admin.rights.delete_messages)
+    });
+    if allowed {
+        Ok(())
+    } else {
+        Err(ModuleError::permission_denied(
+            "你沒有在此聊天刪除訊息的權限。",
+        ))
+    }
+}
+
+/// Extract the arguments if the message is the command sent by us.
+fn extract_args(message: &Message, command: &str) -> Option<Vec<String>> {
+    let mut words = message.text().split_whitespace();
+
+    if words.next() == Some(command) && is_root_user(message) {
+        Some(words.map(String::from).collect())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,372 @@
+//! PBot: Modules: RemindersModule
+//!
+//! Remind us of something later, after a while or at a time.
+//!
+//! The reminder is delivered to Saved Messages with a link back to
+//! the context message, which is the message replied to or the command
+//! itself. With `--here`, it is delivered to the chat where it was set,
+//! replying to the context message instead. The times are in the zone
+//! configured with `TG_TIMEZONE`.
+//!
+//! ```text
+//! !remindme [--here] <期限> [內容]
+//! !remind [--here] <YYYY-MM-DD> <HH:MM> [內容]
+//! !reminders
+//! !unremind <ID>
+//! ```
+
+pub mod scheduler;
+
+use std::time::Duration;
+
+use actix::prelude::*;
+use chrono::{DateTime, LocalResult, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use grammers_client::types::{chat::PackedChat, Message};
+use log::info;
+use pbot_modules_derive::{ModuleActivator, ModuleActor, ModuleMeta};
+
+use crate::telegram::{format::FormattedText, user::is_root_user};
+use crate::utils::{format_duration, parse_duration};
+
+use self::scheduler::{
+    commands::{CancelReminderCommand, ListRemindersCommand, ScheduleReminderCommand},
+    Destination, Reminder, ReminderActor,
+};
+
+use super::base::{
+    error::{ModuleError, ModuleResult},
+    ModuleMessage,
+};
+
+/// The command to remind after a while, such as `!remindme 2h30m call the vendor`.
+const REMIND_ME_CMD: &str = "!remindme";
+
+/// The command to remind at a time, such as `!remind 2026-11-01 09:00 meeting`.
+const REMIND_CMD: &str = "!remind";
+
+/// The command to list the pending reminders.
+const LIST_CMD: &str = "!reminders";
+
+/// The command to cancel a reminder, such as `!unremind 3`.
+const CANCEL_CMD: &str = "!unremind";
+
+/// The flag to deliver the reminder to the chat where it was set.
+const HERE_FLAG: &str = "--here";
+
+/// The usage of the commands.
+const USAGE: &str = "!remindme [--here] <期限> [內容]
+!remind [--here] <YYYY-MM-DD> <HH:MM> [內容]
+!reminders
+!unremind <ID>";
+
+/// The format of the time in `!remind`.
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// The maximum length of the reminder text shown in `!reminders`, in characters.
+const MAX_EXCERPT_LENGTH: usize = 40;
+
+/// The RemindersModule actor.
+#[derive(Clone, ModuleActor, ModuleActivator, ModuleMeta)]
+#[name = "RemindersModule"]
+pub struct RemindersModuleActor {
+    /// The actor delivering the reminders.
+    pub reminders: Addr<ReminderActor>,
+    /// The time zone of the owner.
+    pub timezone: Tz,
+}
+
+impl Handler<ModuleMessage> for RemindersModuleActor {
+    type Result = ResponseActFuture<Self, ModuleResult>;
+
+    fn handle(&mut self, msg: ModuleMessage, _: &mut Self::Context) -> Self::Result {
+        // Clone the fields of self to move into the following block.
+        let reminders = self.reminders.clone();
+        let timezone = self.timezone;
+
+        async move {
+            // Take a snapshot of the message, so we don't need to lock it again.
+            let message = msg.snapshot().await;
+            let now = Utc::now();
+
+            if let Some(args) = extract_args(&message, REMIND_ME_CMD) {
+                let (destination, args) = parse_destination(args);
+                let (due, text) = parse_after(args, now)?;
+                handle_remind(&msg, &message, &reminders, timezone, destination, due, text).await
+            } else if let Some(args) = extract_args(&message, REMIND_CMD) {
+                let (destination, args) = parse_destination(args);
+                let (due, text) = parse_at(args, now, timezone)?;
+                handle_remind(&msg, &message, &reminders, timezone, destination, due, text).await
+            } else if extract_args(&message, LIST_CMD).is_some() {
+                handle_list(&msg, &reminders, timezone).await
+            } else if let Some(args) = extract_args(&message, CANCEL_CMD) {
+                handle_cancel(&msg, &reminders, args).await
+            } else {
+                Ok(())
+            }
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Schedule the reminder with `!remindme` or `!remind`.
+async fn handle_remind(
+    msg: &ModuleMessage,
+    message: &Message,
+    reminders: &Addr<ReminderActor>,
+    timezone: Tz,
+    destination: Destination,
+    due: DateTime<Utc>,
+    text: &str,
+) -> ModuleResult {
+    let text = text.trim();
+    let context_id = message.reply_to_message_id();
+    if text.is_empty() && context_id.is_none() {
+        return Err(ModuleError::usage_with(
+            "請寫下提醒的內容，或回覆要提醒的訊息。",
+            USAGE,
+        ));
+    }
+
+    let chat = message.chat();
+    let id = reminders
+        .send(ScheduleReminderCommand(Reminder {
+            id: 0,
+            text: text.to_string(),
+            chat: chat.pack().to_bytes(),
+            chat_name: chat.name().to_string(),
+            message_id: context_id.unwrap_or_else(|| message.id()),
+            destination,
+            due: due.timestamp(),
+        }))
+        .await?;
+    info!("⏰ Scheduled the reminder #{} at {}.", id, due);
+
+    let remaining = Duration::from_secs((due - Utc::now()).num_seconds().max(0) as u64);
+    msg.edit_or_reply(
+        FormattedText::new()
+            .push("⏰ 已設定提醒 ")
+            .code(format!("#{}", id))
+            .push(format!(
+                "，將於 {}（{}後）送到{}。",
+                format_time(due.timestamp(), timezone),
+                format_duration(remaining),
+                destination_name(destination)
+            )),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// List the pending reminders with `!reminders`.
+async fn handle_list(
+    msg: &ModuleMessage,
+    reminders: &Addr<ReminderActor>,
+    timezone: Tz,
+) -> ModuleResult {
+    let list = reminders.send(ListRemindersCommand).await?;
+    if list.is_empty() {
+        msg.edit_or_reply("⏰ 沒有待送達的提醒。").await?;
+        return Ok(());
+    }
+
+    let mut text = FormattedText::plain(format!("⏰ 有 {} 個待送達的提醒：", list.len()));
+    for reminder in list {
+        text = text
+            .push("\n• ")
+            .code(format!("#{}", reminder.id))
+            .push(format!("｜{}｜", format_time(reminder.due, timezone)));
+        text = match PackedChat::from_bytes(&reminder.chat) {
+            Ok(chat) => text.message_link(&reminder.chat_name, &chat.unpack(), reminder.message_id),
+            Err(_) => text.push(&reminder.chat_name),
+        };
+        text = text.push(format!("｜送到{}", destination_name(reminder.destination)));
+
+        if !reminder.text.is_empty() {
+            text = text.push(format!("\n  {}", excerpt(&reminder.text)));
+        }
+    }
+    msg.edit_or_reply(text).await?;
+
+    Ok(())
+}
+
+/// Cancel the reminder with `!unremind <ID>`.
+async fn handle_cancel(
+    msg: &ModuleMessage,
+    reminders: &Addr<ReminderActor>,
+    args: &str,
+) -> ModuleResult {
+    let id = match args.trim().trim_start_matches('#').parse::<u32>() {
+        Ok(id) => id,
+        Err(_) => return Err(ModuleError::usage_with("請指定提醒的 ID。", USAGE)),
+    };
+
+    match reminders.send(CancelReminderCommand { id }).await? {
+        Some(_) => {
+            info!("🗑️ Cancelled the reminder #{}.", id);
+            msg.edit_or_reply(
+                FormattedText::new()
+                    .push("🗑️ 已取消提醒 ")
+                    .code(format!("#{}", id))
+                    .push("。"),
+            )
+            .await?;
+            Ok(())
+        }
+        None => Err(ModuleError::usage(format!("找不到提醒 #{}。", id))),
+    }
+}
+
+/// Parse the arguments in the form of `<duration> [text]`.
+fn parse_after(args: &str, now: DateTime<Utc>) -> Result<(DateTime<Utc>, &str), ModuleError> {
+    let (duration, text) = match next_word(args) {
+        Some((duration, text)) => (parse_duration(duration), text),
+        None => return Err(ModuleError::usage_with("請指定期限。", USAGE)),
+    };
+    let duration = match duration {
+        Some(duration) if !duration.is_zero() => duration,
+        _ => {
+            return Err(ModuleError::usage_with(
+                "無法解析期限，例如 30m、2h30m 或 1d。",
+                USAGE,
+            ))
+        }
+    };
+
+    match chrono::Duration::from_std(duration)
+        .ok()
+        .and_then(|duration| now.checked_add_signed(duration))
+    {
+        Some(due) => Ok((due, text)),
+        None => Err(ModuleError::usage("期限太長了。")),
+    }
+}
+
+/// Parse the arguments in the form of `<YYYY-MM-DD> <HH:MM> [text]`
+/// in the time zone.
+fn parse_at(
+    args: &str,
+    now: DateTime<Utc>,
+    timezone: Tz,
+) -> Result<(DateTime<Utc>, &str), ModuleError> {
+    let parsed = next_word(args).and_then(|(date, rest)| {
+        let (time, text) = next_word(rest)?;
+        let local = NaiveDateTime::parse_from_str(&format!("{} {}", date, time), TIME_FORMAT);
+
+        Some((local.ok()?, text))
+    });
+    let (local, text) = match parsed {
+        Some(parsed) => parsed,
+        None => {
+            return Err(ModuleError::usage_with(
+                "無法解析時間，例如 2026-11-01 09:00。",
+                USAGE,
+            ))
+        }
+    };
+
+    // Take the earlier one when the clocks go back.
+    let due = match timezone.from_local_datetime(&local) {
+        LocalResult::Single(due) | LocalResult::Ambiguous(due, _) => due.with_timezone(&Utc),
+        LocalResult::None => {
+            return Err(ModuleError::usage(format!(
+                "{} 在 {} 時區不存在。",
+                local.format(TIME_FORMAT),
+                timezone
+            )))
+        }
+    };
+    if due <= now {
+        return Err(ModuleError::usage(format!(
+            "{} 已經過了。",
+            format_time(due.timestamp(), timezone)
+        )));
+    }
+
+    Ok((due, text))
+}
+
+/// Parse the leading `--here` flag, and return the rest of the arguments.
+fn parse_destination(args: &str) -> (Destination, &str) {
+    match next_word(args) {
+        Some((HERE_FLAG, rest)) => (Destination::Chat, rest),
+        _ => (Destination::SavedMessages, args),
+    }
+}
+
+/// Describe where the reminder is delivered, such as `收藏訊息`.
+fn destination_name(destination: Destination) -> &'static str {
+    match destination {
+        Destination::SavedMessages => "收藏訊息",
+        Destination::Chat => "原聊天",
+    }
+}
+
+/// Format the UNIX timestamp in the time zone, such as `2026-11-01 09:00 CST`.
+fn format_time(timestamp: i64, timezone: Tz) -> String {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .map(|time| {
+            time.with_timezone(&timezone)
+                .format("%Y-%m-%d %H:%M %Z")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+/// Shorten the reminder text to a line of [`MAX_EXCERPT_LENGTH`] characters.
+fn excerpt(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default();
+
+    if line.chars().count() > MAX_EXCERPT_LENGTH || line.len() < text.trim_end().len() {
+        let shortened = line.chars().take(MAX_EXCERPT_LENGTH).collect::<String>();
+        format!("{}…", shortened)
+    } else {
+        line.to_string()
+    }
+}
+
+/// Take the first word of the text, and return it with the rest of the text.
+fn next_word(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    if text.is_empty() {
+        return None;
+    }
+
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    Some((&text[..end], &text[end..]))
+}
+
+/// Get the text following the command if the message is the command sent by us.
+fn extract_args<'a>(message: &'a Message, command: &str) -> Option<&'a str> {
+    let rest = message.text().trim_start().strip_prefix(command)?;
+
+    if (rest.is_empty() || rest.starts_with(char::is_whitespace)) && is_root_user(message) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_split_at_whitespace() {
+        assert_eq!(
+            next_word("  10m  take a break"),
+            Some(("10m", "  take a break"))
+        );
+        assert_eq!(next_word("10m"), Some(("10m", "")));
+        assert_eq!(next_word(" \n "), None);
+    }
+
+    #[test]
+    fn quotes_are_not_special() {
+        assert_eq!(next_word("\"a b\" c"), Some(("\"a", " b\" c")));
+    }
+}
@@ -0,0 +1,323 @@
+//! PBot: Modules: RemindersModule: Reminder Actor
+//!
+//! This delivers the reminders when they are due, to Saved Messages
+//! or to the chat where they were set.
+//!
+//! The pending reminders are persisted in the storage, and
+//! rescheduled when PBot starts, so they survive restarts.
+//! The reminders due while PBot was not running are delivered
+//! as soon as it starts, noting how late they are.
+
+pub mod commands;
+
+use std::time::Duration;
+
+use actix::prelude::*;
+use chrono::Utc;
+use grammers_client::types::{chat::PackedChat, Chat};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::modules::base::response::RESPONSE_PREFIX;
+use crate::storage::{self, StorageActor};
+use crate::telegram::{
+    client::{
+        commands::{GetMeCommand, SendMessageCommand},
+        outgoing::OutgoingMessage,
+        ClientActor,
+    },
+    format::FormattedText,
+};
+use crate::utils::format_duration;
+
+use self::commands::{CancelReminderCommand, ListRemindersCommand, ScheduleReminderCommand};
+
+/// The storage namespace of the pending reminders.
+const STORAGE_NAMESPACE: &str = "reminders";
+
+/// How late a reminder is, in seconds, before noting it in the delivery.
+const LATE_NOTICE_AFTER: i64 = 60;
+
+/// How long to wait before retrying the first failed delivery.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// The longest wait between the retries of a failed delivery.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Where to deliver the reminder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Destination {
+    /// Saved Messages, with a link back to the context message.
+    SavedMessages,
+    /// The chat where the reminder was set, replying to the context message.
+    Chat,
+}
+
+/// A reminder which has not been delivered yet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reminder {
+    /// The ID of the reminder, for cancelling it.
+    pub id: u32,
+    /// What to remind.
+    pub text: String,
+    /// The serialized [`PackedChat`] where the reminder was set.
+    pub chat: Vec<u8>,
+    /// The name of the chat where the reminder was set.
+    pub chat_name: String,
+    /// The ID of the context message.
+    pub message_id: i32,
+    /// Where to deliver the reminder.
+    pub destination: Destination,
+    /// When to deliver the reminder, in UNIX timestamp.
+    pub due: i64,
+}
+
+/// The persisted state of the reminders.
+#[derive(Default, Serialize, Deserialize)]
+struct ReminderState {
+    /// The ID of the next reminder.
+    next_id: u32,
+    /// The reminders which have not been delivered yet.
+    pending: Vec<Reminder>,
+}
+
+/// The reminder actor.
+pub struct ReminderActor {
+    /// The client to deliver the reminders with.
+    client: Addr<ClientActor>,
+    /// The storage to persist the pending reminders.
+    storage: Addr<StorageActor>,
+    /// The ID of the next reminder.
+    next_id: u32,
+    /// The reminders which have not been delivered yet.
+    pending: Vec<Reminder>,
+}
+
+impl ReminderActor {
+    /// Create a reminder actor.
+    pub fn new(client: Addr<ClientActor>, storage: Addr<StorageActor>) -> Self {
+        Self {
+            client,
+            storage,
+            next_id: 1,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Persist the pending reminders.
+    fn persist(&self, ctx: &mut Context<Self>) {
+        let storage = self.storage.clone();
+        let state = ReminderState {
+            next_id: self.next_id,
+            pending: self.pending.clone(),
+        };
+
+        ctx.spawn(
+            async move {
+                if let Err(e) = storage::save(&storage, STORAGE_NAMESPACE, &state).await {
+                    error!("Failed to persist the pending reminders: {:?}", e);
+                }
+            }
+            .into_actor(self),
+        );
+    }
+
+    /// Deliver the reminder when it is due.
+    fn schedule(&mut self, reminder: Reminder, ctx: &mut Context<Self>) {
+        let delay = Duration::from_secs((reminder.due - Utc::now().timestamp()).max(0) as u64);
+
+        ctx.run_later(delay, move |act, ctx| act.execute(reminder, 0, ctx));
+    }
+
+    /// Deliver the reminder, and forget it.
+    ///
+    /// If the delivery fails, the reminder stays pending and
+    /// is retried later; `attempt` counts the failed deliveries.
+    /// Only a reminder with a malformed chat is dropped undelivered.
+    fn execute(&mut self, reminder: Reminder, attempt: u32, ctx: &mut Context<Self>) {
+        // The reminder may have been cancelled.
+        if !self.pending.contains(&reminder) {
+            return;
+        }
+
+        let chat = match PackedChat::from_bytes(&reminder.chat) {
+            Ok(chat) => chat.unpack(),
+            Err(_) => {
+                error!("Dropped the reminder #{}: malformed chat", reminder.id);
+                self.forget(reminder.id, ctx);
+                return;
+            }
+        };
+
+        let client = self.client.clone();
+        let task = reminder.clone();
+
+        ctx.spawn(
+            async move { deliver(&client, &task, chat).await }
+                .into_actor(self)
+                .map(move |result, act, ctx| match result {
+                    Ok(()) => {
+                        info!("⏰ Delivered the reminder #{}.", reminder.id);
+                        act.forget(reminder.id, ctx);
+                    }
+                    Err(e) => {
+                        let delay = retry_delay(attempt);
+                        error!(
+                            "Failed to deliver the reminder #{}, retrying in {:?}: {:?}",
+                            reminder.id, delay, e
+                        );
+
+                        ctx.run_later(delay, move |act, ctx| {
+                            act.execute(reminder, attempt + 1, ctx)
+                        });
+                    }
+                }),
+        );
+    }
+
+    /// Forget the reminder with the ID.
+    fn forget(&mut self, id: u32, ctx: &mut Context<Self>) {
+        self.pending.retain(|pending| pending.id != id);
+        self.persist(ctx);
+    }
+}
+
+/// How long to wait before retrying a delivery which failed
+/// `attempt` times before, doubling up to [`MAX_RETRY_DELAY`].
+fn retry_delay(attempt: u32) -> Duration {
+    RETRY_DELAY
+        .checked_mul(2u32.saturating_pow(attempt))
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+}
+
+/// Send the reminder to its destination.
+async fn deliver(
+    client: &Addr<ClientActor>,
+    reminder: &Reminder,
+    chat: Chat,
+) -> anyhow::Result<()> {
+    let mut text = FormattedText::new().push(format!("{} ⏰ 提醒", RESPONSE_PREFIX));
+    if !reminder.text.is_empty() {
+        text = text.push("：").push(&reminder.text);
+    }
+    let late = Utc::now().timestamp() - reminder.due;
+    if late >= LATE_NOTICE_AFTER {
+        text = text.push(format!(
+            "\n（PBot 未在執行，延遲了 {}）",
+            format_duration(Duration::from_secs(late as u64))
+        ));
+    }
+
+    let (target, input) = match reminder.destination {
+        Destination::SavedMessages => {
+            let me = client.send(GetMeCommand).await??;
+            let text =
+                text.push("\n📍 ")
+                    .message_link(&reminder.chat_name, &chat, reminder.message_id);
+
+            (Chat::User(me), OutgoingMessage::from(text))
+        }
+        Destination::Chat => (
+            chat,
+            OutgoingMessage::from(text).reply_to(Some(reminder.message_id)),
+        ),
+    };
+    client.send(SendMessageCommand(target, input)).await??;
+
+    Ok(())
+}
+
+impl Actor for ReminderActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("🌟 Reminder started!");
+
+        // Reschedule the reminders pending before the restart.
+        let storage = self.storage.clone();
+        ctx.wait(
+            async move { storage::load::<ReminderState>(&storage, STORAGE_NAMESPACE).await }
+                .into_actor(self)
+                .map(|result, act, ctx| match result {
+                    Ok(state) => {
+                        let state = state.unwrap_or_default();
+                        act.next_id = act.next_id.max(state.next_id);
+
+                        for reminder in state.pending {
+                            act.pending.push(reminder.clone());
+                            act.schedule(reminder, ctx);
+                        }
+                    }
+                    Err(e) => error!("Failed to load the pending reminders: {:?}", e),
+                }),
+        );
+    }
+
+    fn stopped(&mut self, _: &mut Self::Context) {
+        info!("👋 Reminder stopped!");
+    }
+}
+
+impl Handler<ScheduleReminderCommand> for ReminderActor {
+    type Result = MessageResult<ScheduleReminderCommand>;
+
+    /// Deliver the reminder when it is due, and return its ID.
+    fn handle(&mut self, cmd: ScheduleReminderCommand, ctx: &mut Context<Self>) -> Self::Result {
+        let mut reminder = cmd.0;
+        reminder.id = self.next_id;
+        self.next_id += 1;
+
+        self.pending.push(reminder.clone());
+        self.persist(ctx);
+        self.schedule(reminder.clone(), ctx);
+
+        MessageResult(reminder.id)
+    }
+}
+
+impl Handler<CancelReminderCommand> for ReminderActor {
+    type Result = MessageResult<CancelReminderCommand>;
+
+    /// Cancel the pending reminder with the ID.
+    fn handle(&mut self, cmd: CancelReminderCommand, ctx: &mut Context<Self>) -> Self::Result {
+        let index = self.pending.iter().position(|pending| pending.id == cmd.id);
+        let cancelled = index.map(|index| self.pending.remove(index));
+
+        if cancelled.is_some() {
+            self.persist(ctx);
+        }
+        MessageResult(cancelled)
+    }
+}
+
+impl Handler<ListRemindersCommand> for ReminderActor {
+    type Result = MessageResult<ListRemindersCommand>;
+
+    /// List the pending reminders.
+    fn handle(&mut self, _: ListRemindersCommand, _: &mut Context<Self>) -> Self::Result {
+        let mut reminders = self.pending.clone();
+        reminders.sort_by_key(|reminder| reminder.due);
+
+        MessageResult(reminders)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles() {
+        assert_eq!(retry_delay(0), Duration::from_secs(30));
+        assert_eq!(retry_delay(1), Duration::from_secs(60));
+        assert_eq!(retry_delay(2), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        assert_eq!(retry_delay(7), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(40), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+}
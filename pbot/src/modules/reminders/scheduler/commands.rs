@@ -0,0 +1,29 @@
+//! Commands for the reminder actor.
+
+use actix::prelude::*;
+
+use super::Reminder;
+
+/// Deliver the reminder when it is due.
+///
+/// The ID of the reminder is assigned by the actor and returned,
+/// so the ID in the command is ignored. The reminder is persisted,
+/// so it will still be delivered after restarting PBot.
+#[derive(Message)]
+#[rtype(result = "u32")]
+pub struct ScheduleReminderCommand(pub Reminder);
+
+/// Cancel the pending reminder with the ID.
+///
+/// It returns the cancelled reminder, or `None` if there is no such reminder.
+#[derive(Message)]
+#[rtype(result = "Option<Reminder>")]
+pub struct CancelReminderCommand {
+    /// The ID of the reminder.
+    pub id: u32,
+}
+
+/// List the pending reminders, from the earliest due.
+#[derive(Message)]
+#[rtype(result = "Vec<Reminder>")]
+pub struct ListRemindersCommand;
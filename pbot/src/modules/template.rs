@@ -12,7 +12,7 @@ use actix::prelude::*;
 use log::info;
 use pbot_modules_derive::{ModuleActivator, ModuleActor, ModuleMeta};
 
-use super::base::ModuleMessage;
+use super::base::{error::ModuleResult, ModuleMessage};
 
 /// The TemplateModule actor.
 #[derive(Clone, Default, ModuleActor, ModuleActivator, ModuleMeta)]
@@ -22,7 +22,7 @@ pub struct TemplateModuleActor {
 }
 
 impl Handler<ModuleMessage> for TemplateModuleActor {
-    type Result = ResponseActFuture<Self, anyhow::Result<()>>;
+    type Result = ResponseActFuture<Self, ModuleResult>;
 
     fn handle(&mut self, msg: ModuleMessage, _: &mut Self::Context) -> Self::Result {
         // https://github.com/actix/actix/issues/308
@@ -33,12 +33,19 @@ impl Handler<ModuleMessage> for TemplateModuleActor {
             let ModuleMessage {
                 handle: _,
                 message: _,
+                ..
             } = msg;
 
             // DEVEDIT: Your logic here.
             //
             // You can separate your logic into different functions
             // for better readability.
+            //
+            // To answer the message, use the helpers such as
+            // `msg.edit_or_reply()` in `super::base::response`.
+            //
+            // To tell the user what went wrong, return a `ModuleError`
+            // in `super::base::error`, such as `ModuleError::usage()`.
 
             // It worked with no fault errors! 👌
             Ok(())
@@ -0,0 +1,231 @@
+//! PBot: Storage Actor
+//!
+//! This persists the states of PBot, such as the pending jobs
+//! and the data of modules, into a JSON file.
+//!
+//! The states are grouped by namespaces. Every namespace holds a
+//! single JSON value, which is usually the whole state of a module.
+//! The modules keeping their state outside an actor use [`Persisted`],
+//! which saves the whole state after every change.
+
+pub mod commands;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use actix::prelude::*;
+use log::{error, info};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Mutex;
+
+use self::commands::{LoadStateCommand, SaveStateCommand};
+
+/// The storage actor.
+pub struct StorageActor {
+    /// The path to the storage file.
+    path: PathBuf,
+    /// The states, keyed by their namespaces.
+    states: BTreeMap<String, serde_json::Value>,
+}
+
+impl StorageActor {
+    /// Open the storage file, or create an empty storage
+    /// if the file does not exist yet.
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let states = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self { path, states })
+    }
+
+    /// Write the states to the storage file.
+    ///
+    /// We write to a temporary file first, so a crash while
+    /// writing won't corrupt the storage.
+    fn flush(&self) -> std::io::Result<()> {
+        let content = serde_json::to_vec_pretty(&self.states)?;
+        let temp_path = self.path.with_extension("tmp");
+
+        std::fs::write(&temp_path, content)?;
+        std::fs::rename(&temp_path, &self.path)
+    }
+}
+
+impl Actor for StorageActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, _: &mut Self::Context) {
+        info!("🌟 Storage started!");
+    }
+
+    fn stopped(&mut self, _: &mut Self::Context) {
+        info!("👋 Storage stopped!");
+    }
+}
+
+impl Handler<LoadStateCommand> for StorageActor {
+    type Result = Option<serde_json::Value>;
+
+    /// Load the state of the namespace.
+    fn handle(&mut self, cmd: LoadStateCommand, _: &mut Context<Self>) -> Self::Result {
+        self.states.get(cmd.0).cloned()
+    }
+}
+
+impl Handler<SaveStateCommand> for StorageActor {
+    type Result = std::io::Result<()>;
+
+    /// Save the state of the namespace, and write it to the storage file.
+    fn handle(&mut self, cmd: SaveStateCommand, _: &mut Context<Self>) -> Self::Result {
+        let SaveStateCommand(namespace, state) = cmd;
+        self.states.insert(namespace.to_string(), state);
+
+        self.flush().map_err(|e| {
+            error!("Failed to write the storage: {:?}", e);
+            e
+        })
+    }
+}
+
+/// Load the state of the namespace, and deserialize it.
+pub async fn load<T: DeserializeOwned>(
+    storage: &Addr<StorageActor>,
+    namespace: &'static str,
+) -> anyhow::Result<Option<T>> {
+    match storage.send(LoadStateCommand(namespace)).await? {
+        Some(state) => Ok(Some(serde_json::from_value(state)?)),
+        None => Ok(None),
+    }
+}
+
+/// Serialize the state, and save it to the namespace.
+pub async fn save<T: Serialize>(
+    storage: &Addr<StorageActor>,
+    namespace: &'static str,
+    state: &T,
+) -> anyhow::Result<()> {
+    let state = serde_json::to_value(state)?;
+    storage.send(SaveStateCommand(namespace, state)).await??;
+
+    Ok(())
+}
+
+/// A state persisted in a namespace of the storage.
+///
+/// The state is kept in memory, and the whole state is saved
+/// to the storage after every change.
+pub struct Persisted<T> {
+    /// The storage to persist the state.
+    storage: Addr<StorageActor>,
+    /// The namespace of the state.
+    namespace: &'static str,
+    /// The state.
+    state: Mutex<T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> Persisted<T> {
+    /// Load the state from the namespace, or use the default state
+    /// if the namespace is empty.
+    pub async fn load(
+        storage: Addr<StorageActor>,
+        namespace: &'static str,
+    ) -> anyhow::Result<Self> {
+        let state = load(&storage, namespace).await?.unwrap_or_default();
+
+        Ok(Self {
+            storage,
+            namespace,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Read the state.
+    pub async fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&*self.state.lock().await)
+    }
+
+    /// Change the state, and persist it.
+    ///
+    /// `f` returns `None` if it left the state unchanged,
+    /// and then nothing is persisted.
+    pub async fn update<R>(
+        &self,
+        f: impl FnOnce(&mut T) -> Option<R>,
+    ) -> anyhow::Result<Option<R>> {
+        let mut state = self.state.lock().await;
+        let result = f(&mut state);
+
+        if result.is_some() {
+            save(&self.storage, self.namespace, &*state).await?;
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A storage file which is removed when dropped.
+    struct TempStorage(PathBuf);
+
+    impl TempStorage {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("pbot-{}-{}.json", name, std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+
+        fn start(&self) -> Addr<StorageActor> {
+            StorageActor::open(&self.0)
+                .expect("failed to open the storage")
+                .start()
+        }
+    }
+
+    impl Drop for TempStorage {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[actix::test]
+    async fn persisted_survives_reopening() {
+        let file = TempStorage::new("persisted");
+
+        let persisted = Persisted::<Vec<i32>>::load(file.start(), "numbers")
+            .await
+            .unwrap();
+        assert_eq!(persisted.read(Vec::len).await, 0);
+        persisted
+            .update(|numbers| {
+                numbers.push(42);
+                Some(())
+            })
+            .await
+            .unwrap();
+
+        let reopened = Persisted::<Vec<i32>>::load(file.start(), "numbers")
+            .await
+            .unwrap();
+        assert_eq!(reopened.read(Clone::clone).await, [42]);
+    }
+
+    #[actix::test]
+    async fn unchanged_state_is_not_persisted() {
+        let file = TempStorage::new("unchanged");
+
+        let persisted = Persisted::<Vec<i32>>::load(file.start(), "numbers")
+            .await
+            .unwrap();
+        let result = persisted.update(|numbers| numbers.pop()).await.unwrap();
+
+        assert_eq!(result, None);
+        assert!(!file.0.exists());
+    }
+}
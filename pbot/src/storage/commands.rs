@@ -0,0 +1,17 @@
+//! Commands for the storage actor.
+
+use actix::prelude::*;
+
+/// Load the state of the namespace.
+///
+/// The first element is the namespace.
+#[derive(Message)]
+#[rtype(result = "Option<serde_json::Value>")]
+pub struct LoadStateCommand(pub &'static str);
+
+/// Save the state of the namespace, and write it to the storage file.
+///
+/// The first element is the namespace; the second element is the state.
+#[derive(Message)]
+#[rtype(result = "std::io::Result<()>")]
+pub struct SaveStateCommand(pub &'static str, pub serde_json::Value);
@@ -1,5 +1,11 @@
 //! PBot: The Telegram clients encapsulation
 
+pub mod cleanup;
 pub mod client;
+pub mod format;
+pub mod recorder;
+pub mod replay;
+pub mod stash;
+pub mod tl_json;
 pub mod update;
 pub mod user;
@@ -0,0 +1,170 @@
+//! PBot: Telegram: Cleanup Actor
+//!
+//! This deletes the messages after a while, such as the
+//! self-destructing feedback of modules.
+//!
+//! The pending deletions are persisted in the storage, and
+//! rescheduled when PBot starts, so they survive restarts.
+
+pub mod commands;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix::prelude::*;
+use grammers_client::types::chat::PackedChat;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{self, StorageActor};
+
+use self::commands::ScheduleDeletionCommand;
+
+use super::client::{commands::DeleteMessagesCommand, ClientActor};
+
+/// The storage namespace of the pending deletions.
+const STORAGE_NAMESPACE: &str = "cleanup";
+
+/// A deletion which has not been done yet.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct PendingDeletion {
+    /// The serialized [`PackedChat`] where the messages are.
+    chat: Vec<u8>,
+    /// The IDs of the messages to delete.
+    message_ids: Vec<i32>,
+    /// When to delete the messages, in UNIX timestamp.
+    due: u64,
+}
+
+/// Get the current UNIX timestamp.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is before epoch")
+        .as_secs()
+}
+
+/// The cleanup actor.
+pub struct CleanupActor {
+    /// The client to delete the messages with.
+    client: Addr<ClientActor>,
+    /// The storage to persist the pending deletions.
+    storage: Addr<StorageActor>,
+    /// The deletions which have not been done yet.
+    pending: Vec<PendingDeletion>,
+}
+
+impl CleanupActor {
+    /// Create a cleanup actor.
+    pub fn new(client: Addr<ClientActor>, storage: Addr<StorageActor>) -> Self {
+        Self {
+            client,
+            storage,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Persist the pending deletions.
+    fn persist(&self, ctx: &mut Context<Self>) {
+        let storage = self.storage.clone();
+        let pending = self.pending.clone();
+
+        ctx.spawn(
+            async move {
+                if let Err(e) = storage::save(&storage, STORAGE_NAMESPACE, &pending).await {
+                    error!("Failed to persist the pending deletions: {:?}", e);
+                }
+            }
+            .into_actor(self),
+        );
+    }
+
+    /// Run the deletion when it is due.
+    fn schedule(&mut self, deletion: PendingDeletion, ctx: &mut Context<Self>) {
+        let delay = Duration::from_secs(deletion.due.saturating_sub(now()));
+
+        ctx.run_later(delay, move |act, ctx| act.execute(deletion, ctx));
+    }
+
+    /// Delete the messages, and forget the deletion.
+    fn execute(&mut self, deletion: PendingDeletion, ctx: &mut Context<Self>) {
+        let client = self.client.clone();
+        let chat = match PackedChat::from_bytes(&deletion.chat) {
+            Ok(chat) => chat.unpack(),
+            Err(_) => {
+                warn!("Dropped a deletion with the malformed chat.");
+                self.forget(&deletion, ctx);
+                return;
+            }
+        };
+        let message_ids = deletion.message_ids.clone();
+
+        ctx.spawn(
+            async move {
+                client
+                    .send(DeleteMessagesCommand { chat, message_ids })
+                    .await
+            }
+            .into_actor(self)
+            .map(move |result, act, ctx| {
+                match result {
+                    Ok(Ok(deleted)) => debug!("🧹 Deleted {} messages.", deleted),
+                    Ok(Err(e)) => error!("Failed to delete the messages: {:?}", e),
+                    Err(e) => error!("Failed to send request to Client: {:?}", e),
+                }
+
+                act.forget(&deletion, ctx);
+            }),
+        );
+    }
+
+    /// Remove the deletion from the pending list.
+    fn forget(&mut self, deletion: &PendingDeletion, ctx: &mut Context<Self>) {
+        self.pending.retain(|pending| pending != deletion);
+        self.persist(ctx);
+    }
+}
+
+impl Actor for CleanupActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("🌟 Cleanup started!");
+
+        // Reschedule the deletions pending before the restart.
+        let storage = self.storage.clone();
+        ctx.wait(
+            async move { storage::load::<Vec<PendingDeletion>>(&storage, STORAGE_NAMESPACE).await }
+                .into_actor(self)
+                .map(|result, act, ctx| match result {
+                    Ok(pending) => {
+                        for deletion in pending.unwrap_or_default() {
+                            act.pending.push(deletion.clone());
+                            act.schedule(deletion, ctx);
+                        }
+                    }
+                    Err(e) => error!("Failed to load the pending deletions: {:?}", e),
+                }),
+        );
+    }
+
+    fn stopped(&mut self, _: &mut Self::Context) {
+        info!("👋 Cleanup stopped!");
+    }
+}
+
+impl Handler<ScheduleDeletionCommand> for CleanupActor {
+    type Result = ();
+
+    /// Delete the messages in the specified Chat after a while.
+    fn handle(&mut self, cmd: ScheduleDeletionCommand, ctx: &mut Context<Self>) -> Self::Result {
+        let deletion = PendingDeletion {
+            chat: cmd.chat.pack().to_bytes(),
+            message_ids: cmd.message_ids,
+            due: now() + cmd.after.as_secs(),
+        };
+
+        self.pending.push(deletion.clone());
+        self.persist(ctx);
+        self.schedule(deletion, ctx);
+    }
+}
@@ -0,0 +1,21 @@
+//! Commands for the cleanup actor.
+
+use std::time::Duration;
+
+use actix::prelude::*;
+use grammers_client::types::Chat;
+
+/// Delete the messages in the specified Chat after a while.
+///
+/// The deletion is persisted, so it will still be done
+/// after restarting PBot.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ScheduleDeletionCommand {
+    /// The chat where the messages are.
+    pub chat: Chat,
+    /// The IDs of the messages to delete.
+    pub message_ids: Vec<i32>,
+    /// How long to wait before deleting.
+    pub after: Duration,
+}
@@ -2,14 +2,29 @@
 //!
 //! This encapsulates the Telegram client as a Actor
 //! so we can manage and track the instance well.
+//!
+//! In the dry-run mode, the mutating commands are logged with their
+//! parameters instead of hitting Telegram, and return a synthetic success.
+//! `grammers_client` can't construct messages by itself, so sending,
+//! forwarding and uploading return the dry-run variants of their results;
+//! see [`outgoing`] for details. The read-only commands go through as normal.
+//!
+//! When replaying, the client is connected to the replay server instead
+//! of Telegram, and the chats not in the recording resolve to placeholders.
 
+pub mod admins;
 pub mod commands;
+pub mod forward;
+mod history;
+pub mod outgoing;
+mod peer;
 
 use actix::prelude::*;
-use grammers_client::types::AdminRightsBuilder;
+use grammers_client::types::User;
 
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
 use grammers_client::types::iter_buffer::InvocationError;
@@ -19,22 +34,52 @@ use grammers_client::{
     Client,
 };
 
+use self::admins::{get_admins, resolve_member, AdminInfo};
 use self::commands::{
-    ForwardSingleMessageCommand, GetAdminRightsBuilderCommand, LoginCommand, NextUpdatesCommand,
-    ResolveChatCommand, SaveSessionToFileCommand, SendMessageCommand, UnpackChatCommand,
+    ConnectReplayCommand, DeleteMessagesCommand, EditMessageCommand, ForwardMessagesCommand,
+    GetAdminsCommand, GetHistoryIdsCommand, GetMeCommand, GetMessagesCommand, GetRawMessageCommand,
+    LoginCommand, NextUpdatesCommand, ResolveChatCommand, ResolveMemberCommand,
+    SaveSessionToFileCommand, SendMessageCommand, SetAdminRightsCommand, UnpackChatCommand,
+    UploadFileCommand,
 };
+use self::forward::{forward_messages, ForwardMessagesResult};
+use self::history::get_history_ids;
+use self::outgoing::{SentMessage, UploadedFile};
 
+use super::replay;
 use super::user::login;
 
-use log::{debug, info};
+use log::{debug, info, warn};
+
+/// The maximum amount of messages to delete in a request.
+const DELETE_BATCH_SIZE: usize = 100;
+
+/// The pause between the requests of deleting messages.
+const DELETE_BATCH_INTERVAL: Duration = Duration::from_secs(1);
 
 /// The Telegram client actor.
 #[derive(Default)]
 pub struct ClientActor {
     client: Option<Arc<RwLock<Client>>>,
+    /// Whether the mutating commands are only logged.
+    dry_run: bool,
+    /// Whether the client is connected to the replay server.
+    replaying: bool,
 }
 
 impl ClientActor {
+    /// Create a client actor.
+    ///
+    /// With `dry_run`, the mutating commands are only logged.
+    /// See the module documentation for details.
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            client: None,
+            dry_run,
+            replaying: false,
+        }
+    }
+
     /// Get the `Arc<RwLock<Client>>` instance.
     pub fn get_client(&mut self) -> Arc<RwLock<Client>> {
         self.client
@@ -73,28 +118,56 @@ impl Handler<LoginCommand> for ClientActor {
     }
 }
 
-impl Handler<ForwardSingleMessageCommand> for ClientActor {
-    type Result = ResponseActFuture<
-        Self,
-        Result<Vec<Option<grammers_client::types::Message>>, InvocationError>,
-    >;
+impl Handler<ConnectReplayCommand> for ClientActor {
+    type Result = ResponseActFuture<Self, ()>;
+
+    /// Connecting to the replay server.
+    fn handle(&mut self, msg: ConnectReplayCommand, _: &mut Context<Self>) -> Self::Result {
+        async move {
+            replay::server::connect(msg.0)
+                .await
+                .expect("failed to connect to the replay server")
+        }
+        .into_actor(self)
+        .map(|value, act, _ctx| {
+            act.client = Some(Arc::new(RwLock::new(value)));
+            act.replaying = true;
+        })
+        .boxed_local()
+    }
+}
+
+impl Handler<ForwardMessagesCommand> for ClientActor {
+    type Result = ResponseActFuture<Self, ForwardMessagesResult>;
+
+    /// Forward messages to the specified chat.
+    fn handle(&mut self, msg: ForwardMessagesCommand, _ctx: &mut Context<Self>) -> Self::Result {
+        if self.dry_run {
+            info!(
+                "🧪 [dry run] forward {:?} from {} ({}) to {} ({}) with {:?}",
+                msg.message_ids,
+                msg.message_chat.name(),
+                msg.message_chat.id(),
+                msg.forward_to.name(),
+                msg.forward_to.id(),
+                msg.options
+            );
+            let result = msg
+                .message_ids
+                .into_iter()
+                .map(|id| (id, Ok(SentMessage::DryRun)))
+                .collect();
+            return Box::pin(fut::ready(result));
+        }
 
-    /// Forward a single message to the specified chat.
-    fn handle(
-        &mut self,
-        msg: ForwardSingleMessageCommand,
-        _ctx: &mut Context<Self>,
-    ) -> Self::Result {
         // Get the unwrapped client.
         let client = self.get_client();
 
         async move {
-            // Forward the message.
-            client
-                .write()
-                .await
-                .forward_messages(&msg.forward_to, &[msg.message_id], &msg.message_chat)
-                .await
+            // Forward the messages.
+            //
+            // For details, see the implementation in forward.rs.
+            forward_messages(client, msg).await
         }
         .into_actor(self)
         .boxed_local()
@@ -108,6 +181,7 @@ impl Handler<ResolveChatCommand> for ClientActor {
     fn handle(&mut self, msg: ResolveChatCommand, _: &mut Context<Self>) -> Self::Result {
         // Get the unwrapped client.
         let client = self.get_client();
+        let replaying = self.replaying;
 
         async move {
             // Get the dialogs iterator.
@@ -137,6 +211,17 @@ impl Handler<ResolveChatCommand> for ClientActor {
                 }
             }
 
+            // The dialogs of the replay server are the recorded chats only,
+            // so the other chats, such as the forwarding targets, stand in
+            // for the real ones.
+            if replaying {
+                warn!(
+                    "Chat {} is not in the recording; using a placeholder.",
+                    msg.0
+                );
+                return Ok(replay::server::placeholder_chat(msg.0));
+            }
+
             // If we reach here, it means the chat was not found.
             Err(anyhow::anyhow!("No such a group."))
         }
@@ -179,36 +264,324 @@ impl Handler<NextUpdatesCommand> for ClientActor {
 }
 
 impl Handler<SendMessageCommand> for ClientActor {
-    type Result = ResponseActFuture<Self, Result<grammers_client::types::Message, InvocationError>>;
+    type Result = ResponseActFuture<Self, Result<SentMessage, InvocationError>>;
 
     /// Send message to the specified Chat.
     fn handle(&mut self, cmd: SendMessageCommand, _: &mut Context<Self>) -> Self::Result {
-        let client = self.get_client();
         let SendMessageCommand(chat, message) = cmd;
 
+        if self.dry_run {
+            info!(
+                "🧪 [dry run] send to {} ({}): {:?}",
+                chat.name(),
+                chat.id(),
+                message
+            );
+            return Box::pin(fut::ready(Ok(SentMessage::DryRun)));
+        }
+
+        let client = self.get_client();
+
         async move {
             // Send message.
-            client.write().await.send_message(&chat, message).await
+            client
+                .write()
+                .await
+                .send_message(&chat, message.into())
+                .await
+                .map(SentMessage::from)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+impl Handler<EditMessageCommand> for ClientActor {
+    type Result = ResponseActFuture<Self, Result<(), InvocationError>>;
+
+    /// Edit the specified message in the Chat.
+    fn handle(&mut self, cmd: EditMessageCommand, _: &mut Context<Self>) -> Self::Result {
+        let EditMessageCommand {
+            chat,
+            message_id,
+            message,
+        } = cmd;
+
+        if self.dry_run {
+            info!(
+                "🧪 [dry run] edit message {} in {} ({}): {:?}",
+                message_id,
+                chat.name(),
+                chat.id(),
+                message
+            );
+            return Box::pin(fut::ready(Ok(())));
+        }
+
+        let client = self.get_client();
+
+        async move {
+            // Edit message.
+            client
+                .write()
+                .await
+                .edit_message(&chat, message_id, message.into())
+                .await
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+impl Handler<DeleteMessagesCommand> for ClientActor {
+    type Result = ResponseActFuture<Self, Result<usize, InvocationError>>;
+
+    /// Delete the messages in the specified Chat.
+    fn handle(&mut self, cmd: DeleteMessagesCommand, _: &mut Context<Self>) -> Self::Result {
+        let DeleteMessagesCommand { chat, message_ids } = cmd;
+
+        if self.dry_run {
+            info!(
+                "🧪 [dry run] delete {:?} in {} ({})",
+                message_ids,
+                chat.name(),
+                chat.id()
+            );
+            return Box::pin(fut::ready(Ok(message_ids.len())));
+        }
+
+        let client = self.get_client();
+
+        async move {
+            let mut deleted = 0;
+
+            // Telegram only accepts up to 100 messages in a request.
+            for (index, batch) in message_ids.chunks(DELETE_BATCH_SIZE).enumerate() {
+                // Pause between the batches, so a bulk deletion won't be flood-limited.
+                if index > 0 {
+                    tokio::time::sleep(DELETE_BATCH_INTERVAL).await;
+                }
+
+                deleted += loop {
+                    let result = client.write().await.delete_messages(&chat, batch).await;
+
+                    match result {
+                        // Wait as long as Telegram asks, then retry.
+                        Err(InvocationError::Rpc(e)) if e.code == 420 => {
+                            let secs = e.value.unwrap_or(1);
+                            warn!("Flood-limited when deleting; retrying in {}s.", secs);
+                            tokio::time::sleep(Duration::from_secs(secs.into())).await;
+                        }
+                        result => break result?,
+                    }
+                };
+            }
+
+            Ok(deleted)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+impl Handler<GetHistoryIdsCommand> for ClientActor {
+    type Result = ResponseActFuture<Self, Result<Vec<i32>, InvocationError>>;
+
+    /// Get the IDs of the messages in the specified Chat within a range.
+    fn handle(&mut self, cmd: GetHistoryIdsCommand, _: &mut Context<Self>) -> Self::Result {
+        let client = self.get_client();
+
+        // For details, see the implementation in history.rs.
+        async move { get_history_ids(&client, cmd).await }
+            .into_actor(self)
+            .boxed_local()
+    }
+}
+
+impl Handler<GetMessagesCommand> for ClientActor {
+    type Result = ResponseActFuture<
+        Self,
+        Result<Vec<Option<grammers_client::types::Message>>, InvocationError>,
+    >;
+
+    /// Get the messages in the specified Chat by their IDs.
+    fn handle(&mut self, cmd: GetMessagesCommand, _: &mut Context<Self>) -> Self::Result {
+        let client = self.get_client();
+        let GetMessagesCommand { chat, message_ids } = cmd;
+
+        async move {
+            client
+                .write()
+                .await
+                .get_messages_by_id(&chat, &message_ids)
+                .await
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+impl Handler<GetRawMessageCommand> for ClientActor {
+    type Result =
+        ResponseActFuture<Self, Result<Option<grammers_tl_types::enums::Message>, InvocationError>>;
+
+    /// Get the raw TL object of the message in the specified Chat.
+    fn handle(&mut self, cmd: GetRawMessageCommand, _: &mut Context<Self>) -> Self::Result {
+        use grammers_tl_types as tl;
+
+        let client = self.get_client();
+        let GetRawMessageCommand { chat, message_id } = cmd;
+
+        async move {
+            let id = vec![tl::types::InputMessageId { id: message_id }.into()];
+            let result = match peer::input_channel(&chat) {
+                Some(channel) => {
+                    client
+                        .write()
+                        .await
+                        .invoke(&tl::functions::channels::GetMessages { channel, id })
+                        .await?
+                }
+                None => {
+                    client
+                        .write()
+                        .await
+                        .invoke(&tl::functions::messages::GetMessages { id })
+                        .await?
+                }
+            };
+
+            let messages = match result {
+                tl::enums::messages::Messages::Messages(m) => m.messages,
+                tl::enums::messages::Messages::Slice(m) => m.messages,
+                tl::enums::messages::Messages::ChannelMessages(m) => m.messages,
+                tl::enums::messages::Messages::NotModified(_) => Vec::new(),
+            };
+
+            // Telegram returns `messageEmpty` for the messages which don't exist.
+            Ok(messages
+                .into_iter()
+                .find(|message| !matches!(message, tl::enums::Message::Empty(_))))
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+impl Handler<UploadFileCommand> for ClientActor {
+    type Result = ResponseActFuture<Self, std::io::Result<UploadedFile>>;
+
+    /// Upload the file content.
+    fn handle(&mut self, cmd: UploadFileCommand, _: &mut Context<Self>) -> Self::Result {
+        let UploadFileCommand { name, content } = cmd;
+        let size = content.len();
+
+        if self.dry_run {
+            info!("🧪 [dry run] upload {:?} ({} bytes)", name, size);
+            return Box::pin(fut::ready(Ok(UploadedFile::DryRun)));
+        }
+
+        let client = self.get_client();
+
+        async move {
+            let mut stream = std::io::Cursor::new(content);
+
+            client
+                .write()
+                .await
+                .upload_stream(&mut stream, size, name)
+                .await
+                .map(UploadedFile::Uploaded)
         }
         .into_actor(self)
         .boxed_local()
     }
 }
 
-impl Handler<GetAdminRightsBuilderCommand> for ClientActor {
-    type Result = ResponseActFuture<Self, AdminRightsBuilder>;
+impl Handler<GetMeCommand> for ClientActor {
+    type Result = ResponseActFuture<Self, Result<User, InvocationError>>;
 
-    /// Get the admin rights builder.
-    fn handle(&mut self, cmd: GetAdminRightsBuilderCommand, _: &mut Context<Self>) -> Self::Result {
+    /// Get the user of the logged-in account.
+    fn handle(&mut self, _: GetMeCommand, _: &mut Context<Self>) -> Self::Result {
         let client = self.get_client();
-        let GetAdminRightsBuilderCommand { channel, user } = cmd;
 
-        async move { client.write().await.set_admin_rights(&channel, &user) }
+        async move { client.write().await.get_me().await }
             .into_actor(self)
             .boxed_local()
     }
 }
 
+impl Handler<GetAdminsCommand> for ClientActor {
+    type Result = ResponseActFuture<Self, Result<Vec<AdminInfo>, InvocationError>>;
+
+    /// Get the administrators of the specified Chat.
+    fn handle(&mut self, cmd: GetAdminsCommand, _: &mut Context<Self>) -> Self::Result {
+        let client = self.get_client();
+
+        get_admins(client, cmd).into_actor(self).boxed_local()
+    }
+}
+
+impl Handler<ResolveMemberCommand> for ClientActor {
+    type Result = ResponseActFuture<Self, Result<Option<User>, InvocationError>>;
+
+    /// Resolve the member of the specified Chat.
+    fn handle(&mut self, cmd: ResolveMemberCommand, _: &mut Context<Self>) -> Self::Result {
+        let client = self.get_client();
+
+        resolve_member(client, cmd).into_actor(self).boxed_local()
+    }
+}
+
+impl Handler<SetAdminRightsCommand> for ClientActor {
+    type Result = ResponseActFuture<Self, Result<(), InvocationError>>;
+
+    /// Set the admin rights and the rank of the user in the specified Chat.
+    fn handle(&mut self, cmd: SetAdminRightsCommand, _: &mut Context<Self>) -> Self::Result {
+        let SetAdminRightsCommand {
+            channel,
+            user,
+            rights,
+            rank,
+        } = cmd;
+
+        if self.dry_run {
+            info!(
+                "🧪 [dry run] set the admin rights of {} ({}) in {} ({}) to {:?} with rank {:?}",
+                user.full_name(),
+                user.id(),
+                channel.name(),
+                channel.id(),
+                rights,
+                rank
+            );
+            return Box::pin(fut::ready(Ok(())));
+        }
+
+        let client = self.get_client();
+
+        async move {
+            let mut admin_builder = client.write().await.set_admin_rights(&channel, &user);
+
+            // Without loading the current rights, every right is revoked.
+            match rights {
+                Some(rights) if rights.granted().is_empty() => {}
+                Some(rights) => {
+                    admin_builder.load_current().await?;
+                    rights.apply(&mut admin_builder);
+                }
+                None => {
+                    admin_builder.load_current().await?;
+                }
+            }
+
+            admin_builder.rank(&rank).invoke().await
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
 impl<T> Handler<SaveSessionToFileCommand<T>> for ClientActor
 where
     T: 'static + AsRef<Path>,
@@ -225,3 +598,90 @@ where
             .boxed_local()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::telegram::client::outgoing::OutgoingMessage;
+    use crate::telegram::format::FormattedText;
+
+    use super::*;
+
+    /// A user chat with the ID, without the access hash.
+    fn user_chat(id: i32) -> Chat {
+        let mut bytes = vec![0b0000_0010, 6];
+        bytes.extend_from_slice(&id.to_le_bytes());
+
+        PackedChat::from_bytes(&bytes).unwrap().unpack()
+    }
+
+    // The client is never logged in, so these fail if anything reaches Telegram.
+
+    #[actix::test]
+    async fn dry_run_sends_nothing() {
+        let client = ClientActor::new(true).start();
+
+        let sent = client
+            .send(SendMessageCommand(
+                user_chat(42),
+                OutgoingMessage::from(FormattedText::plain("hi")).reply_to(Some(1)),
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(matches!(sent, SentMessage::DryRun));
+        assert_eq!(sent.id(), None);
+    }
+
+    #[actix::test]
+    async fn dry_run_forwards_nothing() {
+        let client = ClientActor::new(true).start();
+
+        let result = client
+            .send(ForwardMessagesCommand {
+                forward_to: Arc::new(user_chat(42)),
+                message_ids: vec![3, 1, 2],
+                message_chat: Arc::new(user_chat(7)),
+                options: Default::default(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result.keys().copied().collect::<Vec<_>>(), [1, 2, 3]);
+        assert!(result
+            .values()
+            .all(|result| matches!(result, Ok(SentMessage::DryRun))));
+    }
+
+    #[actix::test]
+    async fn dry_run_uploads_nothing() {
+        let client = ClientActor::new(true).start();
+
+        let uploaded = client
+            .send(UploadFileCommand {
+                name: "raw.json".to_string(),
+                content: b"{}".to_vec(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(matches!(uploaded, UploadedFile::DryRun));
+    }
+
+    #[actix::test]
+    async fn dry_run_deletes_nothing() {
+        let client = ClientActor::new(true).start();
+
+        let deleted = client
+            .send(DeleteMessagesCommand {
+                chat: user_chat(42),
+                message_ids: vec![1, 2],
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(deleted, 2);
+    }
+}
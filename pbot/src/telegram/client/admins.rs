@@ -0,0 +1,367 @@
+//! Listing the administrators and resolving the members for the client actor.
+//!
+//! This implements [`GetAdminsCommand`] and [`ResolveMemberCommand`].
+//! `grammers_client` can only iterate over all the participants, so we
+//! request the administrators of channels directly, which is much cheaper
+//! in large groups.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use grammers_client::types::{iter_buffer::InvocationError, AdminRightsBuilder, Chat, Role, User};
+use grammers_client::Client;
+use grammers_tl_types as tl;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use super::commands::{GetAdminsCommand, ResolveMemberCommand};
+use super::peer::input_channel;
+
+/// The maximum amount of participants Telegram returns in a single request.
+const MAX_PARTICIPANTS_BATCH: i32 = 200;
+
+/// The rights of an administrator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdminRights {
+    /// Change the information of the chat.
+    pub change_info: bool,
+    /// Post messages in the channel.
+    pub post_messages: bool,
+    /// Edit the messages of others in the channel.
+    pub edit_messages: bool,
+    /// Delete the messages of others.
+    pub delete_messages: bool,
+    /// Ban the users.
+    pub ban_users: bool,
+    /// Invite the users.
+    pub invite_users: bool,
+    /// Pin the messages.
+    pub pin_messages: bool,
+    /// Add the administrators.
+    pub add_admins: bool,
+    /// Stay anonymous when sending messages.
+    pub anonymous: bool,
+    /// Manage the voice chats.
+    pub manage_call: bool,
+}
+
+impl AdminRights {
+    /// The names of the rights, in the order of [`AdminRights::entries`].
+    pub const NAMES: [&'static str; 10] = [
+        "change_info",
+        "post_messages",
+        "edit_messages",
+        "delete_messages",
+        "ban_users",
+        "invite_users",
+        "pin_messages",
+        "add_admins",
+        "anonymous",
+        "manage_call",
+    ];
+
+    /// Get the rights with their names.
+    pub fn entries(&self) -> [(&'static str, bool); 10] {
+        let values = [
+            self.change_info,
+            self.post_messages,
+            self.edit_messages,
+            self.delete_messages,
+            self.ban_users,
+            self.invite_users,
+            self.pin_messages,
+            self.add_admins,
+            self.anonymous,
+            self.manage_call,
+        ];
+
+        let mut entries = [("", false); 10];
+        for (entry, (name, value)) in entries.iter_mut().zip(Self::NAMES.iter().zip(values)) {
+            *entry = (name, value);
+        }
+        entries
+    }
+
+    /// Get the names of the granted rights.
+    pub fn granted(&self) -> Vec<&'static str> {
+        self.entries()
+            .iter()
+            .filter(|(_, value)| *value)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// Compare the rights with the rights before.
+    ///
+    /// It returns the names of the newly granted rights and the revoked rights.
+    pub fn changes_from(&self, before: &AdminRights) -> (Vec<&'static str>, Vec<&'static str>) {
+        let (granted, revoked): (Vec<_>, Vec<_>) = self
+            .entries()
+            .into_iter()
+            .zip(before.entries())
+            .filter(|((_, now), (_, was))| now != was)
+            .map(|((name, now), _)| (name, now))
+            .partition(|(_, now)| *now);
+
+        (
+            granted.into_iter().map(|(name, _)| name).collect(),
+            revoked.into_iter().map(|(name, _)| name).collect(),
+        )
+    }
+
+    /// Set the right by its name.
+    ///
+    /// It returns `false` if there is no right with this name.
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        let right = match name {
+            "change_info" => &mut self.change_info,
+            "post_messages" => &mut self.post_messages,
+            "edit_messages" => &mut self.edit_messages,
+            "delete_messages" => &mut self.delete_messages,
+            "ban_users" => &mut self.ban_users,
+            "invite_users" => &mut self.invite_users,
+            "pin_messages" => &mut self.pin_messages,
+            "add_admins" => &mut self.add_admins,
+            "anonymous" => &mut self.anonymous,
+            "manage_call" => &mut self.manage_call,
+            _ => return false,
+        };
+
+        *right = value;
+        true
+    }
+
+    /// Apply the rights to the builder.
+    pub fn apply(&self, builder: &mut AdminRightsBuilder) {
+        builder
+            .change_info(self.change_info)
+            .post_messages(self.post_messages)
+            .edit_messages(self.edit_messages)
+            .delete_messages(self.delete_messages)
+            .ban_users(self.ban_users)
+            .invite_users(self.invite_users)
+            .pin_messages(self.pin_messages)
+            .add_admins(self.add_admins)
+            .anonymous(self.anonymous)
+            .manage_call(self.manage_call);
+    }
+}
+
+impl From<tl::enums::ChatAdminRights> for AdminRights {
+    fn from(rights: tl::enums::ChatAdminRights) -> Self {
+        let tl::enums::ChatAdminRights::Rights(rights) = rights;
+
+        Self {
+            change_info: rights.change_info,
+            post_messages: rights.post_messages,
+            edit_messages: rights.edit_messages,
+            delete_messages: rights.delete_messages,
+            ban_users: rights.ban_users,
+            invite_users: rights.invite_users,
+            pin_messages: rights.pin_messages,
+            add_admins: rights.add_admins,
+            anonymous: rights.anonymous,
+            manage_call: rights.manage_call,
+        }
+    }
+}
+
+/// An administrator of a chat.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdminInfo {
+    /// The ID of the user.
+    pub user_id: i32,
+    /// The full name of the user.
+    pub name: String,
+    /// Whether the user created the chat.
+    pub is_creator: bool,
+    /// The custom title of the administrator, if any.
+    pub rank: Option<String>,
+    /// The rights of the administrator.
+    pub rights: AdminRights,
+    /// The ID of the user who promoted this administrator, if known.
+    pub promoted_by: Option<i32>,
+}
+
+/// Get the administrators according to [`GetAdminsCommand`].
+pub async fn get_admins(
+    client: Arc<RwLock<Client>>,
+    cmd: GetAdminsCommand,
+) -> Result<Vec<AdminInfo>, InvocationError> {
+    let GetAdminsCommand { chat } = cmd;
+
+    match input_channel(&chat) {
+        Some(channel) => get_channel_admins(&client, channel).await,
+        None => get_group_admins(&client, &chat).await,
+    }
+}
+
+/// Get the administrators of a channel or a megagroup.
+async fn get_channel_admins(
+    client: &RwLock<Client>,
+    channel: tl::enums::InputChannel,
+) -> Result<Vec<AdminInfo>, InvocationError> {
+    let mut admins = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let request = tl::functions::channels::GetParticipants {
+            channel: channel.clone(),
+            filter: tl::types::ChannelParticipantsAdmins {}.into(),
+            offset,
+            limit: MAX_PARTICIPANTS_BATCH,
+            hash: 0,
+        };
+        let participants = match client.write().await.invoke(&request).await? {
+            tl::enums::channels::ChannelParticipants::Participants(participants) => participants,
+            tl::enums::channels::ChannelParticipants::NotModified => break,
+        };
+        let batch_size = participants.participants.len() as i32;
+
+        // Map the IDs of the users to their names.
+        let names = participants
+            .users
+            .into_iter()
+            .filter_map(|user| match user {
+                tl::enums::User::User(user) => {
+                    let name = [user.first_name, user.last_name]
+                        .iter()
+                        .flatten()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    Some((user.id, name))
+                }
+                tl::enums::User::Empty(_) => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        for participant in participants.participants {
+            let admin = match participant {
+                tl::enums::ChannelParticipant::Creator(creator) => AdminInfo {
+                    user_id: creator.user_id,
+                    name: String::new(),
+                    is_creator: true,
+                    rank: creator.rank,
+                    rights: creator.admin_rights.into(),
+                    promoted_by: None,
+                },
+                tl::enums::ChannelParticipant::Admin(admin) => AdminInfo {
+                    user_id: admin.user_id,
+                    name: String::new(),
+                    is_creator: false,
+                    rank: admin.rank,
+                    rights: admin.admin_rights.into(),
+                    promoted_by: Some(admin.promoted_by),
+                },
+                _ => continue,
+            };
+
+            admins.push(AdminInfo {
+                name: names.get(&admin.user_id).cloned().unwrap_or_default(),
+                ..admin
+            });
+        }
+
+        if batch_size < MAX_PARTICIPANTS_BATCH {
+            break;
+        }
+        offset += batch_size;
+    }
+
+    Ok(admins)
+}
+
+/// Get the administrators of a small group.
+///
+/// Small groups have neither custom titles nor fine-grained rights,
+/// so the rights are what Telegram reports for the participants.
+async fn get_group_admins(
+    client: &RwLock<Client>,
+    chat: &Chat,
+) -> Result<Vec<AdminInfo>, InvocationError> {
+    let mut admins = Vec::new();
+    let mut participants = client.write().await.iter_participants(chat);
+
+    while let Some(participant) = participants.next().await? {
+        let (is_creator, permissions, promoted_by) = match &participant.role {
+            Role::Creator(creator) => (true, creator.permissions(), None),
+            Role::Admin(admin) => (false, admin.permissions(), admin.promoted_by()),
+            _ => continue,
+        };
+
+        admins.push(AdminInfo {
+            user_id: participant.user.id(),
+            name: participant.user.full_name(),
+            is_creator,
+            rank: None,
+            rights: AdminRights {
+                change_info: permissions.change_info(),
+                post_messages: permissions.post_messages(),
+                edit_messages: permissions.edit_messages(),
+                delete_messages: permissions.delete_messages(),
+                ban_users: permissions.ban_users(),
+                invite_users: permissions.invite_users(),
+                pin_messages: permissions.pin_messages(),
+                add_admins: permissions.add_admins(),
+                anonymous: permissions.anonymous(),
+                manage_call: permissions.manage_call(),
+            },
+            promoted_by,
+        });
+    }
+
+    Ok(admins)
+}
+
+/// The reference to a member of a chat.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MemberRef {
+    /// The username, without the leading `@`.
+    Username(String),
+    /// The ID of the user.
+    Id(i32),
+}
+
+impl fmt::Display for MemberRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Username(username) => write!(f, "@{}", username),
+            Self::Id(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// Resolve the member according to [`ResolveMemberCommand`].
+///
+/// The users referred by ID are looked up in the participants of
+/// the chat, since we need their access hashes to act on them.
+pub async fn resolve_member(
+    client: Arc<RwLock<Client>>,
+    cmd: ResolveMemberCommand,
+) -> Result<Option<User>, InvocationError> {
+    let ResolveMemberCommand { chat, member } = cmd;
+
+    match member {
+        MemberRef::Username(username) => {
+            let chat = client.write().await.resolve_username(&username).await?;
+
+            Ok(match chat {
+                Some(Chat::User(user)) => Some(user),
+                _ => None,
+            })
+        }
+        MemberRef::Id(id) => {
+            let mut participants = client.write().await.iter_participants(&chat);
+
+            while let Some(participant) = participants.next().await? {
+                if participant.user.id() == id {
+                    return Ok(Some(participant.user));
+                }
+            }
+
+            Ok(None)
+        }
+    }
+}
@@ -1,30 +1,47 @@
 //! Commands for the client actor.
 
+use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::Arc;
 
+use super::super::format::FormattedText;
 use super::super::user::LoginConfig;
+use super::admins::{AdminInfo, AdminRights, MemberRef};
+use super::forward::{ForwardMessagesResult, ForwardOptions};
+use super::outgoing::{OutgoingMessage, SentMessage, UploadedFile};
 use actix::prelude::*;
 use grammers_client::types::iter_buffer::InvocationError;
 use grammers_client::types::User;
-use grammers_client::types::{chat::PackedChat, AdminRightsBuilder, Chat};
-use grammers_client::{InputMessage, UpdateIter};
+use grammers_client::types::{chat::PackedChat, Chat};
+use grammers_client::UpdateIter;
 
 /// Logging in to Telegram.
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct LoginCommand(pub LoginConfig);
 
-/// Forward a single message to the specified chat.
+/// Connect to the replay server at the address instead of logging in.
+///
+/// See [`crate::telegram::replay::server`] for details.
 #[derive(Message)]
-#[rtype(result = "Result<Vec<Option<grammers_client::types::Message>>, InvocationError>")]
-pub struct ForwardSingleMessageCommand {
+#[rtype(result = "()")]
+pub struct ConnectReplayCommand(pub SocketAddr);
+
+/// Forward messages to the specified chat.
+///
+/// The messages are forwarded in batches; see [`ForwardOptions`]
+/// for the delivery options.
+#[derive(Message)]
+#[rtype(result = "ForwardMessagesResult")]
+pub struct ForwardMessagesCommand {
     /// The chat to forward to.
     pub forward_to: Arc<Chat>,
-    /// The message to forward.
-    pub message_id: i32,
-    /// The chat which the message was sent in.
+    /// The messages to forward.
+    pub message_ids: Vec<i32>,
+    /// The chat which the messages were sent in.
     pub message_chat: Arc<Chat>,
+    /// The options of forwarding.
+    pub options: ForwardOptions,
 }
 
 /// Resolve the chat according to the specified chat_id.
@@ -44,17 +61,134 @@ pub struct NextUpdatesCommand;
 
 /// Send message to the specified Chat.
 #[derive(Message)]
-#[rtype(result = "Result<grammers_client::types::Message, InvocationError>")]
-pub struct SendMessageCommand(pub Chat, pub InputMessage);
+#[rtype(result = "Result<SentMessage, InvocationError>")]
+pub struct SendMessageCommand(pub Chat, pub OutgoingMessage);
+
+/// Edit the specified message in the Chat.
+#[derive(Message)]
+#[rtype(result = "Result<(), InvocationError>")]
+pub struct EditMessageCommand {
+    /// The chat where the message is.
+    pub chat: Chat,
+    /// The ID of the message to edit.
+    pub message_id: i32,
+    /// The new content of the message.
+    pub message: FormattedText,
+}
+
+/// Get the messages in the specified Chat by their IDs.
+///
+/// The result is in the same order as `message_ids`, and
+/// the messages which don't exist are `None`.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<Option<grammers_client::types::Message>>, InvocationError>")]
+pub struct GetMessagesCommand {
+    /// The chat where the messages are.
+    pub chat: Chat,
+    /// The IDs of the messages to get.
+    pub message_ids: Vec<i32>,
+}
+
+/// Get the raw TL object of the message in the specified Chat.
+///
+/// It returns `None` if the message doesn't exist.
+#[derive(Message)]
+#[rtype(result = "Result<Option<grammers_tl_types::enums::Message>, InvocationError>")]
+pub struct GetRawMessageCommand {
+    /// The chat where the message is.
+    pub chat: Chat,
+    /// The ID of the message.
+    pub message_id: i32,
+}
+
+/// Get the IDs of the messages in the specified Chat within a range.
+///
+/// The message IDs of the private chats and the small groups are shared
+/// by every chat of the account, so ask for the IDs before deleting or
+/// forwarding a range.
+///
+/// The IDs are newest first, or oldest first with `oldest_first`.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<i32>, InvocationError>")]
+pub struct GetHistoryIdsCommand {
+    /// The chat where the messages are.
+    pub chat: Chat,
+    /// The smallest ID of the messages, inclusive.
+    pub min_id: i32,
+    /// The largest ID of the messages, inclusive.
+    pub max_id: i32,
+    /// Only get the messages sent by us.
+    pub from_self: bool,
+    /// Take the oldest messages of the range first, instead of the newest.
+    pub oldest_first: bool,
+    /// The maximum amount of the IDs to get.
+    pub limit: usize,
+}
+
+/// Delete the messages in the specified Chat.
+///
+/// The messages are deleted in batches of 100 with a pause between
+/// the batches, and the flood waits of Telegram are respected.
+///
+/// It returns the amount of deleted messages.
+#[derive(Message)]
+#[rtype(result = "Result<usize, InvocationError>")]
+pub struct DeleteMessagesCommand {
+    /// The chat where the messages are.
+    pub chat: Chat,
+    /// The IDs of the messages to delete.
+    pub message_ids: Vec<i32>,
+}
+
+/// Upload the file content, so it can be attached to a message.
+#[derive(Message)]
+#[rtype(result = "std::io::Result<UploadedFile>")]
+pub struct UploadFileCommand {
+    /// The file name.
+    pub name: String,
+    /// The file content.
+    pub content: Vec<u8>,
+}
+
+/// Get the user of the logged-in account.
+#[derive(Message)]
+#[rtype(result = "Result<User, InvocationError>")]
+pub struct GetMeCommand;
+
+/// Get the administrators of the specified Chat.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<AdminInfo>, InvocationError>")]
+pub struct GetAdminsCommand {
+    /// The chat to get the administrators of.
+    pub chat: Chat,
+}
+
+/// Resolve the member of the specified Chat.
+///
+/// It returns `None` if there is no such user.
+#[derive(Message)]
+#[rtype(result = "Result<Option<User>, InvocationError>")]
+pub struct ResolveMemberCommand {
+    /// The chat where the member is.
+    pub chat: Chat,
+    /// The member to resolve.
+    pub member: MemberRef,
+}
 
-/// Get the admin rights builder.
+/// Set the admin rights and the rank of the user in the specified Chat.
 #[derive(Message)]
-#[rtype(result = "AdminRightsBuilder")]
-pub struct GetAdminRightsBuilderCommand {
+#[rtype(result = "Result<(), InvocationError>")]
+pub struct SetAdminRightsCommand {
     /// The channel where to apply the admin rights to this user.
     pub channel: Chat,
     /// The user to apply the admin rights to.
     pub user: User,
+    /// The rights to set, or `None` to keep the current rights.
+    ///
+    /// Setting no right at all revokes the administrator status.
+    pub rights: Option<AdminRights>,
+    /// The rank to set, or an empty string to clear it.
+    pub rank: String,
 }
 
 /// Save the current session to file.
@@ -0,0 +1,619 @@
+//! Forwarding messages for the client actor.
+//!
+//! This implements [`ForwardMessagesCommand`], which generalises
+//! the plain `forward_messages` of `grammers_client` with batching,
+//! album expansion and some delivery options.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use grammers_client::types::{Chat, Message};
+use grammers_client::{Client, InputMessage};
+use grammers_tl_types as tl;
+use log::warn;
+use tokio::sync::RwLock;
+
+use grammers_client::types::iter_buffer::InvocationError;
+
+use super::super::format::{message_link, FormattedText};
+use super::commands::ForwardMessagesCommand;
+use super::outgoing::SentMessage;
+use super::peer::input_peer;
+
+/// The maximum amount of messages Telegram accepts in a single forward request.
+const MAX_FORWARD_BATCH: usize = 100;
+
+/// The maximum amount of messages Telegram returns in a single request by IDs.
+const MAX_GET_BATCH: usize = 100;
+
+/// The maximum amount of items in an album.
+const MAX_ALBUM_SIZE: i32 = 10;
+
+/// The maximum length of the text of a message, in UTF-16 code units.
+const MAX_TEXT_LENGTH: usize = 4096;
+
+/// The maximum length of the caption of media, in UTF-16 code units.
+const MAX_CAPTION_LENGTH: usize = 1024;
+
+/// The options of [`ForwardMessagesCommand`].
+#[derive(Clone, Debug, Default)]
+pub struct ForwardOptions {
+    /// Deliver the messages without a notification.
+    pub silent: bool,
+    /// Re-send the messages as copies instead of forwarding them,
+    /// so they have no "Forwarded from" header.
+    ///
+    /// Note that the items of an album are sent one by one in this mode.
+    pub copy: bool,
+    /// Re-send the messages as copies if the source chat refuses
+    /// to forward them, such as the chats restricting saving content.
+    pub fallback_to_copy: bool,
+    /// The attribution footer appended to the copies.
+    ///
+    /// The placeholders `{chat}`, `{sender}` and `{link}` are replaced
+    /// with the title of the source chat, the name of the original sender,
+    /// and the link to the original message respectively. The footer
+    /// which doesn't fit in the caption is sent as a reply to the copy.
+    pub attribution: Option<String>,
+    /// Hide the name of the original sender.
+    ///
+    /// The Telegram layer we are using has no native flag for this,
+    /// so the messages are re-sent as copies instead of being forwarded.
+    /// Note that the items of an album are sent one by one in this mode.
+    pub drop_author: bool,
+    /// Drop the captions of the media.
+    ///
+    /// It implies [`ForwardOptions::drop_author`] for the same reason.
+    pub drop_media_captions: bool,
+    /// Include the whole album when any message of it is requested.
+    ///
+    /// The albums are detected with the `grouped_id` of the messages.
+    pub with_albums: bool,
+}
+
+impl ForwardOptions {
+    /// Whether the messages should be re-sent as copies.
+    pub fn copies(&self) -> bool {
+        self.copy || self.drop_author || self.drop_media_captions
+    }
+
+    /// Render the attribution footer of the message, if configured.
+    fn attribution_of(&self, message: &Message) -> Option<FormattedText> {
+        let template = self.attribution.as_ref()?;
+        let chat = message.chat();
+        let sender = message
+            .sender()
+            .map(|sender| sender.name().to_string())
+            .or_else(|| message.post_author().map(String::from))
+            .unwrap_or_default();
+        let link = message_link(&chat, message.id()).unwrap_or_default();
+
+        Some(FormattedText::plain(
+            template
+                .replace("{chat}", chat.name())
+                .replace("{sender}", &sender)
+                .replace("{link}", &link)
+                .trim_end(),
+        ))
+    }
+}
+
+/// The reason why a message was not forwarded.
+#[derive(Clone, Debug)]
+pub enum ForwardError {
+    /// The message does not exist, or it is not accessible.
+    NotFound,
+    /// The message is a service message or has no content to copy.
+    Unsupported,
+    /// Telegram accepted the request but did not deliver this message,
+    /// for example, when the source chat restricts forwarding.
+    NotDelivered,
+    /// The request carrying this message failed.
+    Invocation(Arc<InvocationError>),
+}
+
+impl fmt::Display for ForwardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "message not found"),
+            Self::Unsupported => write!(f, "message is not forwardable"),
+            Self::NotDelivered => write!(f, "message was not delivered"),
+            Self::Invocation(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ForwardError {}
+
+impl ForwardError {
+    /// Whether the source chat refused to forward the message.
+    pub fn is_refusal(&self) -> bool {
+        match self {
+            Self::NotDelivered => true,
+            Self::Invocation(e) => {
+                matches!(&**e, InvocationError::Rpc(e) if e.name == "CHAT_FORWARDS_RESTRICTED")
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The result of [`ForwardMessagesCommand`].
+///
+/// The key is the ID of the source message, and the value is
+/// the new message or the reason why it was not forwarded.
+/// The keys are sorted, so iterating over it keeps the original order.
+pub type ForwardMessagesResult = BTreeMap<i32, Result<SentMessage, ForwardError>>;
+
+/// Generate a new random ID for sending requests.
+///
+/// Like `grammers_client`, we only need the IDs to be unique,
+/// so a counter initiated with the current time is enough.
+fn generate_random_id() -> i64 {
+    static LAST_ID: AtomicI64 = AtomicI64::new(0);
+
+    if LAST_ID.load(Ordering::SeqCst) == 0 {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("system time is before epoch")
+            .as_nanos() as i64;
+
+        let _ = LAST_ID.compare_exchange(0, now, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    LAST_ID.fetch_add(1, Ordering::SeqCst)
+}
+
+/// Forward the messages according to [`ForwardMessagesCommand`].
+pub async fn forward_messages(
+    client: Arc<RwLock<Client>>,
+    cmd: ForwardMessagesCommand,
+) -> ForwardMessagesResult {
+    let ForwardMessagesCommand {
+        forward_to,
+        message_ids,
+        message_chat,
+        options,
+    } = cmd;
+    let mut result = ForwardMessagesResult::new();
+
+    // Fetch the source messages, so we can expand albums
+    // and tell which messages can't be forwarded.
+    let sources = match fetch_sources(&client, &message_chat, &message_ids, &options).await {
+        Ok(sources) => sources,
+        Err(e) => {
+            let e = Arc::new(e);
+            for id in message_ids {
+                result.insert(id, Err(ForwardError::Invocation(e.clone())));
+            }
+            return result;
+        }
+    };
+
+    let mut messages = Vec::with_capacity(sources.len());
+    for (id, message) in sources {
+        match message {
+            None => {
+                result.insert(id, Err(ForwardError::NotFound));
+            }
+            Some(message) if message.action().is_some() => {
+                result.insert(id, Err(ForwardError::Unsupported));
+            }
+            Some(message) => messages.push(message),
+        }
+    }
+
+    if options.copies() {
+        copy_messages(&client, &forward_to, messages, &options, &mut result).await;
+        return result;
+    }
+
+    for batch in messages.chunks(MAX_FORWARD_BATCH) {
+        forward_batch(
+            &client,
+            &forward_to,
+            &message_chat,
+            batch,
+            &options,
+            &mut result,
+        )
+        .await;
+    }
+
+    // Copy the messages which the source chat refused to forward.
+    if options.fallback_to_copy {
+        let refused = messages
+            .into_iter()
+            .filter(|message| matches!(result.get(&message.id()), Some(Err(e)) if e.is_refusal()))
+            .collect::<Vec<_>>();
+
+        if !refused.is_empty() {
+            copy_messages(&client, &forward_to, refused, &options, &mut result).await;
+        }
+    }
+
+    result
+}
+
+/// Fetch the messages to forward, sorted by their IDs.
+///
+/// If [`ForwardOptions::with_albums`] is set, the other items of
+/// the albums are included too.
+async fn fetch_sources(
+    client: &RwLock<Client>,
+    chat: &Chat,
+    message_ids: &[i32],
+    options: &ForwardOptions,
+) -> Result<BTreeMap<i32, Option<Message>>, InvocationError> {
+    let fetched = get_messages(client, chat, message_ids).await?;
+    // The IDs of the messages outside channels are shared across
+    // the private chats and small groups, so we may get the messages
+    // of the other chats, which are treated as not found.
+    let mut sources = message_ids
+        .iter()
+        .copied()
+        .zip(fetched)
+        .map(|(id, message)| (id, message.filter(|m| m.chat().id() == chat.id())))
+        .collect::<BTreeMap<_, _>>();
+
+    if options.with_albums {
+        let grouped_messages = sources
+            .values()
+            .flatten()
+            .filter_map(|message| message.grouped_id().map(|group| (group, message.id())))
+            .collect::<Vec<_>>();
+
+        if !grouped_messages.is_empty() {
+            let neighbours = album_neighbours(grouped_messages.iter().map(|(_, id)| *id), |id| {
+                sources.contains_key(&id)
+            });
+            let groups = grouped_messages
+                .iter()
+                .map(|(group, _)| *group)
+                .collect::<Vec<_>>();
+
+            let fetched = get_messages(client, chat, &neighbours).await?;
+            for message in fetched.into_iter().flatten() {
+                if matches!(message.grouped_id(), Some(group) if groups.contains(&group)) {
+                    sources.insert(message.id(), Some(message));
+                }
+            }
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Get the IDs which may be the other items of the albums of the messages,
+/// sorted and without the `known` ones.
+///
+/// The items of an album are always sent together,
+/// so their IDs are next to the IDs we have got.
+fn album_neighbours(ids: impl Iterator<Item = i32>, known: impl Fn(i32) -> bool) -> Vec<i32> {
+    let mut neighbours = ids
+        .flat_map(|id| (id - MAX_ALBUM_SIZE + 1)..(id + MAX_ALBUM_SIZE))
+        .filter(|id| *id > 0 && !known(*id))
+        .collect::<Vec<_>>();
+    neighbours.sort_unstable();
+    neighbours.dedup();
+
+    neighbours
+}
+
+/// Get the messages by their IDs, in the batches Telegram accepts.
+async fn get_messages(
+    client: &RwLock<Client>,
+    chat: &Chat,
+    message_ids: &[i32],
+) -> Result<Vec<Option<Message>>, InvocationError> {
+    let mut messages = Vec::with_capacity(message_ids.len());
+
+    for batch in message_ids.chunks(MAX_GET_BATCH) {
+        messages.extend(client.write().await.get_messages_by_id(chat, batch).await?);
+    }
+
+    Ok(messages)
+}
+
+/// Forward a batch of messages in a single request.
+async fn forward_batch(
+    client: &RwLock<Client>,
+    forward_to: &Chat,
+    message_chat: &Chat,
+    batch: &[Message],
+    options: &ForwardOptions,
+    result: &mut ForwardMessagesResult,
+) {
+    let ids = batch.iter().map(Message::id).collect::<Vec<_>>();
+    let random_ids = ids.iter().map(|_| generate_random_id()).collect::<Vec<_>>();
+
+    let request = tl::functions::messages::ForwardMessages {
+        silent: options.silent,
+        background: false,
+        with_my_score: false,
+        from_peer: input_peer(message_chat),
+        id: ids.clone(),
+        random_id: random_ids.clone(),
+        to_peer: input_peer(forward_to),
+        schedule_date: None,
+    };
+
+    // Send the request, and get the IDs of new messages.
+    let new_ids = match client.write().await.invoke(&request).await {
+        Ok(updates) => new_message_ids(updates),
+        Err(e) => {
+            let e = Arc::new(e);
+            for id in ids {
+                result.insert(id, Err(ForwardError::Invocation(e.clone())));
+            }
+            return;
+        }
+    };
+
+    let delivered = map_delivered(ids, random_ids, &new_ids);
+    let delivered_ids = delivered
+        .iter()
+        .filter_map(|(_, new_id)| *new_id)
+        .collect::<Vec<_>>();
+
+    // Get the new messages, since the raw request only gives us their IDs.
+    let new_messages = if delivered_ids.is_empty() {
+        Ok(HashMap::new())
+    } else {
+        get_messages(client, forward_to, &delivered_ids)
+            .await
+            .map(|messages| {
+                messages
+                    .into_iter()
+                    .flatten()
+                    .map(|message| (message.id(), message))
+                    .collect::<HashMap<_, _>>()
+            })
+            .map_err(Arc::new)
+    };
+
+    for (id, new_id) in delivered {
+        let forwarded = match (&new_messages, new_id) {
+            (_, None) => Err(ForwardError::NotDelivered),
+            (Ok(new_messages), Some(new_id)) => new_messages
+                .get(&new_id)
+                .cloned()
+                .ok_or(ForwardError::NotFound),
+            (Err(e), Some(_)) => Err(ForwardError::Invocation(e.clone())),
+        };
+
+        result.insert(id, forwarded.map(SentMessage::from));
+    }
+}
+
+/// Re-send the messages as copies, one by one.
+async fn copy_messages(
+    client: &RwLock<Client>,
+    forward_to: &Chat,
+    messages: Vec<Message>,
+    options: &ForwardOptions,
+    result: &mut ForwardMessagesResult,
+) {
+    for message in messages {
+        let media = message.media();
+
+        // Keep the text only if it is not a caption we should drop.
+        let keep_text = !(options.drop_media_captions && media.is_some());
+        let has_text = keep_text && !message.text().is_empty();
+        if media.is_none() && !has_text {
+            result.insert(message.id(), Err(ForwardError::Unsupported));
+            continue;
+        }
+
+        let text = if keep_text {
+            FormattedText::with_entities(
+                message.text(),
+                message.fmt_entities().cloned().unwrap_or_default(),
+            )
+        } else {
+            FormattedText::new()
+        };
+        let (text, footer) = attach_footer(text, options.attribution_of(&message), media.is_some());
+
+        let mut input = InputMessage::from(text).silent(options.silent);
+
+        if let Some(media) = media {
+            input = input.copy_media(&media);
+        }
+
+        let copied = client
+            .write()
+            .await
+            .send_message(forward_to, input)
+            .await
+            .map_err(|e| ForwardError::Invocation(Arc::new(e)));
+
+        // The footer which doesn't fit in goes to a reply to the copy.
+        if let (Ok(copied), Some(footer)) = (&copied, footer) {
+            let input = InputMessage::from(footer)
+                .silent(options.silent)
+                .reply_to(Some(copied.id()));
+
+            if let Err(e) = client.write().await.send_message(forward_to, input).await {
+                warn!("Failed to send the attribution of {}: {}", message.id(), e);
+            }
+        }
+
+        result.insert(message.id(), copied.map(SentMessage::from));
+    }
+}
+
+/// Append the attribution footer to the text of the copy.
+///
+/// If the text and the footer together are too long for a message,
+/// or for a caption if the copy has media, the text is kept as is
+/// and the footer is returned to be sent separately.
+fn attach_footer(
+    text: FormattedText,
+    footer: Option<FormattedText>,
+    is_caption: bool,
+) -> (FormattedText, Option<FormattedText>) {
+    let footer = match footer {
+        Some(footer) => footer,
+        None => return (text, None),
+    };
+    let separator = if text.is_empty() { "" } else { "\n\n" };
+    let limit = if is_caption {
+        MAX_CAPTION_LENGTH
+    } else {
+        MAX_TEXT_LENGTH
+    };
+
+    if text.len() + separator.len() + footer.len() > limit {
+        (text, Some(footer))
+    } else {
+        (text.push(separator).append(footer), None)
+    }
+}
+
+/// Map the source IDs to the IDs of new messages, with the random IDs
+/// of the request. The ID is `None` if the message was not delivered.
+fn map_delivered(
+    ids: Vec<i32>,
+    random_ids: Vec<i64>,
+    new_ids: &HashMap<i64, i32>,
+) -> Vec<(i32, Option<i32>)> {
+    ids.into_iter()
+        .zip(random_ids)
+        .map(|(id, random_id)| (id, new_ids.get(&random_id).copied()))
+        .collect()
+}
+
+/// Collect the IDs of new messages from the updates, keyed by their random IDs.
+fn new_message_ids(updates: tl::enums::Updates) -> HashMap<i64, i32> {
+    let updates = match updates {
+        tl::enums::Updates::Updates(updates) => updates.updates,
+        tl::enums::Updates::Combined(updates) => updates.updates,
+        tl::enums::Updates::UpdateShort(update) => vec![update.update],
+        _ => Vec::new(),
+    };
+
+    updates
+        .into_iter()
+        .filter_map(|update| match update {
+            tl::enums::Update::MessageId(update) => Some((update.random_id, update.id)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message_id(random_id: i64, id: i32) -> tl::enums::Update {
+        tl::types::UpdateMessageId { id, random_id }.into()
+    }
+
+    #[test]
+    fn footer_is_appended_when_it_fits() {
+        let footer = FormattedText::plain("via news");
+
+        let (text, separate) =
+            attach_footer(FormattedText::plain("hi"), Some(footer.clone()), true);
+        assert_eq!(text.text(), "hi\n\nvia news");
+        assert!(separate.is_none());
+
+        let (text, separate) = attach_footer(FormattedText::new(), Some(footer), true);
+        assert_eq!(text.text(), "via news");
+        assert!(separate.is_none());
+
+        let (text, separate) = attach_footer(FormattedText::plain("hi"), None, true);
+        assert_eq!(text.text(), "hi");
+        assert!(separate.is_none());
+    }
+
+    #[test]
+    fn footer_is_separated_when_the_caption_is_full() {
+        let footer = FormattedText::plain("via news");
+        // 1016 UTF-16 code units, which fits only without the footer.
+        let caption = "👋".repeat(508);
+
+        let (text, separate) =
+            attach_footer(FormattedText::plain(&caption), Some(footer.clone()), true);
+        assert_eq!(text.text(), caption);
+        assert_eq!(
+            separate.map(|footer| footer.text().to_string()),
+            Some("via news".to_string())
+        );
+
+        // The text without media can be longer.
+        let (text, separate) = attach_footer(FormattedText::plain(&caption), Some(footer), false);
+        assert!(text.text().ends_with("\n\nvia news"));
+        assert!(separate.is_none());
+    }
+
+    #[test]
+    fn album_neighbours_cover_both_sides() {
+        let neighbours = album_neighbours([20].into_iter(), |id| id == 20);
+
+        assert_eq!(neighbours, (11..20).chain(21..30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn album_neighbours_are_positive_sorted_and_unique() {
+        let known = [3, 5];
+        let neighbours = album_neighbours(known.into_iter(), |id| known.contains(&id));
+
+        assert_eq!(neighbours.first(), Some(&1));
+        assert_eq!(neighbours.last(), Some(&14));
+        assert!(!neighbours.contains(&3) && !neighbours.contains(&5));
+        assert!(neighbours.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn new_message_ids_from_updates() {
+        let updates = tl::types::Updates {
+            updates: vec![
+                message_id(111, 1001),
+                tl::types::UpdateReadHistoryInbox {
+                    folder_id: None,
+                    peer: tl::types::PeerUser { user_id: 1 }.into(),
+                    max_id: 1,
+                    still_unread_count: 0,
+                    pts: 1,
+                    pts_count: 1,
+                }
+                .into(),
+                message_id(222, 1002),
+            ],
+            users: Vec::new(),
+            chats: Vec::new(),
+            date: 0,
+            seq: 0,
+        };
+
+        assert_eq!(
+            new_message_ids(updates.into()),
+            HashMap::from([(111, 1001), (222, 1002)])
+        );
+    }
+
+    #[test]
+    fn new_message_ids_from_a_short_update() {
+        let update = tl::types::UpdateShort {
+            update: message_id(111, 1001),
+            date: 0,
+        };
+        assert_eq!(new_message_ids(update.into()), HashMap::from([(111, 1001)]));
+
+        let too_long = tl::enums::Updates::TooLong;
+        assert!(new_message_ids(too_long).is_empty());
+    }
+
+    #[test]
+    fn map_delivered_follows_the_random_ids() {
+        let new_ids = HashMap::from([(111, 1001), (333, 1003)]);
+        let delivered = map_delivered(vec![1, 2, 3], vec![111, 222, 333], &new_ids);
+
+        assert_eq!(delivered, [(1, Some(1001)), (2, None), (3, Some(1003))]);
+    }
+}
@@ -0,0 +1,143 @@
+//! PBot: Telegram: Client Actor: Message History
+//!
+//! List the IDs of the messages in a chat within a range.
+//!
+//! The message IDs of the private chats and the small groups are shared
+//! by every chat of the account, so a range of IDs alone may cover the
+//! messages of the other chats. We ask Telegram for the actual messages
+//! instead, with the raw requests since the iterators of `grammers_client`
+//! can't start from an offset.
+
+use grammers_client::types::iter_buffer::InvocationError;
+use grammers_client::types::Chat;
+use grammers_client::Client;
+use grammers_tl_types as tl;
+use tokio::sync::RwLock;
+
+use super::commands::GetHistoryIdsCommand;
+use super::peer;
+
+/// The maximum amount of messages Telegram returns in a request.
+const PAGE_SIZE: i32 = 100;
+
+/// Get the IDs of the messages in the chat within the range.
+///
+/// See [`GetHistoryIdsCommand`] for the details.
+pub async fn get_history_ids(
+    client: &RwLock<Client>,
+    query: GetHistoryIdsCommand,
+) -> Result<Vec<i32>, InvocationError> {
+    let GetHistoryIdsCommand {
+        chat,
+        min_id,
+        max_id,
+        from_self,
+        oldest_first,
+        limit,
+    } = query;
+    let range = min_id..=max_id;
+    let mut ids = Vec::new();
+
+    if oldest_first {
+        // With a negative `add_offset`, Telegram returns the messages newer than the offset.
+        let mut offset_id = min_id.saturating_sub(1);
+
+        while ids.len() < limit && offset_id < max_id {
+            let page = get_page(client, &chat, offset_id, -PAGE_SIZE, min_id, from_self).await?;
+            let mut page = page
+                .into_iter()
+                .filter(|id| *id > offset_id && range.contains(id))
+                .collect::<Vec<_>>();
+            page.sort_unstable();
+
+            offset_id = match page.last() {
+                Some(newest) => *newest,
+                None => break,
+            };
+            ids.extend(page);
+        }
+    } else {
+        // Telegram returns the messages older than the offset.
+        let mut offset_id = max_id.saturating_add(1);
+
+        while ids.len() < limit && offset_id > min_id {
+            let page = get_page(client, &chat, offset_id, 0, min_id, from_self).await?;
+            let oldest = match page.iter().min() {
+                Some(oldest) => *oldest,
+                None => break,
+            };
+
+            ids.extend(page.into_iter().filter(|id| range.contains(id)));
+            offset_id = oldest;
+        }
+    }
+
+    ids.truncate(limit);
+    Ok(ids)
+}
+
+/// Get the IDs of a page of messages around the offset and newer than `min_id - 1`.
+async fn get_page(
+    client: &RwLock<Client>,
+    chat: &Chat,
+    offset_id: i32,
+    add_offset: i32,
+    min_id: i32,
+    from_self: bool,
+) -> Result<Vec<i32>, InvocationError> {
+    let peer = peer::input_peer(chat);
+    let min_id = min_id.saturating_sub(1);
+
+    let result = if from_self {
+        client
+            .write()
+            .await
+            .invoke(&tl::functions::messages::Search {
+                peer,
+                q: String::new(),
+                from_id: Some(tl::enums::InputPeer::PeerSelf),
+                top_msg_id: None,
+                filter: tl::enums::MessagesFilter::InputMessagesFilterEmpty,
+                min_date: 0,
+                max_date: 0,
+                offset_id,
+                add_offset,
+                limit: PAGE_SIZE,
+                max_id: 0,
+                min_id,
+                hash: 0,
+            })
+            .await?
+    } else {
+        client
+            .write()
+            .await
+            .invoke(&tl::functions::messages::GetHistory {
+                peer,
+                offset_id,
+                offset_date: 0,
+                add_offset,
+                limit: PAGE_SIZE,
+                max_id: 0,
+                min_id,
+                hash: 0,
+            })
+            .await?
+    };
+
+    let messages = match result {
+        tl::enums::messages::Messages::Messages(m) => m.messages,
+        tl::enums::messages::Messages::Slice(m) => m.messages,
+        tl::enums::messages::Messages::ChannelMessages(m) => m.messages,
+        tl::enums::messages::Messages::NotModified(_) => Vec::new(),
+    };
+
+    Ok(messages
+        .into_iter()
+        .filter_map(|message| match message {
+            tl::enums::Message::Empty(_) => None,
+            tl::enums::Message::Message(m) => Some(m.id),
+            tl::enums::Message::Service(m) => Some(m.id),
+        })
+        .collect())
+}
@@ -0,0 +1,125 @@
+//! PBot: Telegram: Client: Outgoing Messages
+//!
+//! The messages to send through [`super::ClientActor`], and what sending
+//! them returns.
+//!
+//! [`InputMessage`] can't be inspected, so the modules build an
+//! [`OutgoingMessage`] instead, which the dry-run mode can log in full.
+//! In the dry-run mode nothing is sent or uploaded, so the results have
+//! a dedicated variant standing in for the message or the file.
+
+use grammers_client::types::media::Uploaded;
+use grammers_client::types::{Media, Message};
+use grammers_client::InputMessage;
+
+use super::super::format::FormattedText;
+
+/// The media attached to an [`OutgoingMessage`].
+#[derive(Clone, Debug)]
+pub enum OutgoingMedia {
+    /// The media of an existing message, sent again without downloading it.
+    Copy(Box<Media>),
+    /// The file uploaded with [`super::commands::UploadFileCommand`].
+    File(Uploaded),
+}
+
+/// A message to send with [`super::commands::SendMessageCommand`].
+#[derive(Clone, Debug, Default)]
+pub struct OutgoingMessage {
+    /// The text with its formatting.
+    pub text: FormattedText,
+    /// The ID of the message to reply to, if any.
+    pub reply_to: Option<i32>,
+    /// Send the message without notifying the members.
+    pub silent: bool,
+    /// The media to attach, if any.
+    pub media: Option<OutgoingMedia>,
+}
+
+impl OutgoingMessage {
+    /// Reply to the message with this ID, or to nothing with `None`.
+    pub fn reply_to(mut self, reply_to: Option<i32>) -> Self {
+        self.reply_to = reply_to;
+        self
+    }
+
+    /// Send the message without notifying the members.
+    pub fn silent(mut self, silent: bool) -> Self {
+        self.silent = silent;
+        self
+    }
+
+    /// Attach the media of an existing message.
+    pub fn copy_media(mut self, media: &Media) -> Self {
+        self.media = Some(OutgoingMedia::Copy(Box::new(media.clone())));
+        self
+    }
+
+    /// Attach the uploaded file.
+    pub fn file(mut self, file: Uploaded) -> Self {
+        self.media = Some(OutgoingMedia::File(file));
+        self
+    }
+}
+
+impl From<FormattedText> for OutgoingMessage {
+    fn from(text: FormattedText) -> Self {
+        Self {
+            text,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<OutgoingMessage> for InputMessage {
+    fn from(message: OutgoingMessage) -> Self {
+        let input = InputMessage::from(message.text)
+            .reply_to(message.reply_to)
+            .silent(message.silent);
+
+        match message.media {
+            Some(OutgoingMedia::Copy(media)) => input.copy_media(&media),
+            Some(OutgoingMedia::File(file)) => input.file(file),
+            None => input,
+        }
+    }
+}
+
+/// The message sent with [`super::commands::SendMessageCommand`],
+/// or forwarded with [`super::commands::ForwardMessagesCommand`].
+#[derive(Clone)]
+pub enum SentMessage {
+    /// The message delivered by Telegram.
+    Message(Box<Message>),
+    /// Nothing was sent, since the client is in the dry-run mode.
+    DryRun,
+}
+
+impl SentMessage {
+    /// Get the delivered message, or `None` in the dry-run mode.
+    pub fn message(&self) -> Option<&Message> {
+        match self {
+            Self::Message(message) => Some(message),
+            Self::DryRun => None,
+        }
+    }
+
+    /// Get the ID of the delivered message, or `None` in the dry-run mode.
+    pub fn id(&self) -> Option<i32> {
+        self.message().map(Message::id)
+    }
+}
+
+impl From<Message> for SentMessage {
+    fn from(message: Message) -> Self {
+        Self::Message(Box::new(message))
+    }
+}
+
+/// The file uploaded with [`super::commands::UploadFileCommand`].
+pub enum UploadedFile {
+    /// The file uploaded to Telegram.
+    Uploaded(Uploaded),
+    /// Nothing was uploaded, since the client is in the dry-run mode.
+    DryRun,
+}
@@ -0,0 +1,83 @@
+//! Raw peer conversions for the client actor.
+//!
+//! `grammers_client` keeps the conversion from [`Chat`] to the raw
+//! input peers private, so we rebuild them from the public [`PackedChat`]
+//! serialization whenever we need to invoke a raw TL function.
+
+use grammers_client::types::{chat::PackedChat, Chat};
+use grammers_tl_types as tl;
+
+/// The packed type byte of a user.
+const PACKED_USER: u8 = 0b0000_0010;
+/// The packed type byte of a bot.
+const PACKED_BOT: u8 = 0b0000_0011;
+/// The packed type byte of a small group chat.
+const PACKED_CHAT: u8 = 0b0000_0100;
+
+/// The fields decoded from [`PackedChat::to_bytes`].
+struct PackedParts {
+    /// The packed type byte.
+    ty: u8,
+    /// The ID of the chat.
+    id: i32,
+    /// The access hash of the chat, if any.
+    access_hash: i64,
+}
+
+/// Decode the serialized [`PackedChat`].
+///
+/// The layout is `[type, length, id (4 bytes), access_hash (8 bytes, optional)]`.
+fn unpack_parts(packed: PackedChat) -> PackedParts {
+    let bytes = packed.to_bytes();
+    let id = i32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]);
+    let access_hash = if bytes.len() == 14 {
+        let mut hash = [0; 8];
+        hash.copy_from_slice(&bytes[6..14]);
+        i64::from_le_bytes(hash)
+    } else {
+        0
+    };
+
+    PackedParts {
+        ty: bytes[0],
+        id,
+        access_hash,
+    }
+}
+
+/// Get the raw input peer of the chat.
+pub fn input_peer(chat: &Chat) -> tl::enums::InputPeer {
+    let PackedParts {
+        ty,
+        id,
+        access_hash,
+    } = unpack_parts(chat.pack());
+
+    match ty {
+        PACKED_USER | PACKED_BOT => tl::types::InputPeerUser {
+            user_id: id,
+            access_hash,
+        }
+        .into(),
+        PACKED_CHAT => tl::types::InputPeerChat { chat_id: id }.into(),
+        _ => tl::types::InputPeerChannel {
+            channel_id: id,
+            access_hash,
+        }
+        .into(),
+    }
+}
+
+/// Get the raw input channel of the chat, if it is a channel or a megagroup.
+pub fn input_channel(chat: &Chat) -> Option<tl::enums::InputChannel> {
+    match input_peer(chat) {
+        tl::enums::InputPeer::Channel(channel) => Some(
+            tl::types::InputChannel {
+                channel_id: channel.channel_id,
+                access_hash: channel.access_hash,
+            }
+            .into(),
+        ),
+        _ => None,
+    }
+}
@@ -0,0 +1,886 @@
+//! PBot: Telegram: Rich Text Formatting
+//!
+//! This converts a subset of Markdown or HTML into the text and
+//! the message entities Telegram accepts, and provides a builder
+//! to compose the formatted text piece by piece.
+//!
+//! Telegram measures the offsets of entities in UTF-16 code units,
+//! so every offset in this module is in UTF-16 code units too.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use pbot::telegram::format::FormattedText;
+//!
+//! let text = FormattedText::new()
+//!     .push("✅ Set the rank of ")
+//!     .mention("pan93412", 123456)
+//!     .push(" to ")
+//!     .bold("Senior Dev");
+//! ```
+
+use grammers_client::types::Chat;
+use grammers_client::InputMessage;
+use grammers_tl_types as tl;
+
+/// A text with its formatting entities.
+#[derive(Clone, Debug, Default)]
+pub struct FormattedText {
+    /// The plain text.
+    text: String,
+    /// The formatting entities of the text.
+    entities: Vec<tl::enums::MessageEntity>,
+}
+
+/// The kind of a formatting entity.
+#[derive(Clone, Debug, PartialEq)]
+enum EntityKind {
+    Bold,
+    Italic,
+    Underline,
+    Strike,
+    Code,
+    Pre(String),
+    TextUrl(String),
+}
+
+impl EntityKind {
+    /// Build the entity of this kind at the range.
+    fn build(self, offset: i32, length: i32) -> tl::enums::MessageEntity {
+        match self {
+            Self::Bold => tl::types::MessageEntityBold { offset, length }.into(),
+            Self::Italic => tl::types::MessageEntityItalic { offset, length }.into(),
+            Self::Underline => tl::types::MessageEntityUnderline { offset, length }.into(),
+            Self::Strike => tl::types::MessageEntityStrike { offset, length }.into(),
+            Self::Code => tl::types::MessageEntityCode { offset, length }.into(),
+            Self::Pre(language) => tl::types::MessageEntityPre {
+                offset,
+                length,
+                language,
+            }
+            .into(),
+            Self::TextUrl(url) => tl::types::MessageEntityTextUrl {
+                offset,
+                length,
+                url,
+            }
+            .into(),
+        }
+    }
+}
+
+/// Get the length of the text in UTF-16 code units.
+fn utf16_len(text: &str) -> i32 {
+    text.encode_utf16().count() as i32
+}
+
+/// Get the mutable range `(offset, length)` of the entity.
+fn entity_range(entity: &mut tl::enums::MessageEntity) -> (&mut i32, &mut i32) {
+    use tl::enums::MessageEntity as E;
+
+    match entity {
+        E::Unknown(e) => (&mut e.offset, &mut e.length),
+        E::Mention(e) => (&mut e.offset, &mut e.length),
+        E::Hashtag(e) => (&mut e.offset, &mut e.length),
+        E::BotCommand(e) => (&mut e.offset, &mut e.length),
+        E::Url(e) => (&mut e.offset, &mut e.length),
+        E::Email(e) => (&mut e.offset, &mut e.length),
+        E::Bold(e) => (&mut e.offset, &mut e.length),
+        E::Italic(e) => (&mut e.offset, &mut e.length),
+        E::Code(e) => (&mut e.offset, &mut e.length),
+        E::Pre(e) => (&mut e.offset, &mut e.length),
+        E::TextUrl(e) => (&mut e.offset, &mut e.length),
+        E::MentionName(e) => (&mut e.offset, &mut e.length),
+        E::InputMessageEntityMentionName(e) => (&mut e.offset, &mut e.length),
+        E::Phone(e) => (&mut e.offset, &mut e.length),
+        E::Cashtag(e) => (&mut e.offset, &mut e.length),
+        E::Underline(e) => (&mut e.offset, &mut e.length),
+        E::Strike(e) => (&mut e.offset, &mut e.length),
+        E::Blockquote(e) => (&mut e.offset, &mut e.length),
+        E::BankCard(e) => (&mut e.offset, &mut e.length),
+    }
+}
+
+/// Get the range `(offset, length)` of the entity, in UTF-16 code units.
+pub fn entity_bounds(entity: &tl::enums::MessageEntity) -> (i32, i32) {
+    let mut entity = entity.clone();
+    let (offset, length) = entity_range(&mut entity);
+    (*offset, *length)
+}
+
+/// Get the offset of the entity.
+fn entity_offset(entity: &tl::enums::MessageEntity) -> i32 {
+    entity_bounds(entity).0
+}
+
+impl FormattedText {
+    /// Create an empty formatted text.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a formatted text without any formatting.
+    pub fn plain(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            entities: Vec::new(),
+        }
+    }
+
+    /// Create a formatted text with the existing entities,
+    /// such as the entities of a received message.
+    pub fn with_entities(text: impl Into<String>, entities: Vec<tl::enums::MessageEntity>) -> Self {
+        Self {
+            text: text.into(),
+            entities,
+        }
+    }
+
+    /// Get the plain text.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the formatting entities.
+    pub fn entities(&self) -> &[tl::enums::MessageEntity] {
+        &self.entities
+    }
+
+    /// Check if the text is empty.
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Get the length of the text in UTF-16 code units.
+    pub fn len(&self) -> usize {
+        utf16_len(&self.text) as usize
+    }
+
+    /// Append the plain text.
+    pub fn push(mut self, text: impl AsRef<str>) -> Self {
+        self.text.push_str(text.as_ref());
+        self
+    }
+
+    /// Append another formatted text.
+    pub fn append(mut self, other: impl Into<FormattedText>) -> Self {
+        let other = other.into();
+        let shift = utf16_len(&self.text);
+
+        self.text.push_str(&other.text);
+        self.entities
+            .extend(other.entities.into_iter().map(|mut entity| {
+                *entity_range(&mut entity).0 += shift;
+                entity
+            }));
+        self
+    }
+
+    /// Append the text with an entity covering it.
+    fn push_entity(mut self, text: &str, kind: EntityKind) -> Self {
+        let offset = utf16_len(&self.text);
+        let length = utf16_len(text);
+
+        self.text.push_str(text);
+        if length > 0 {
+            self.entities.push(kind.build(offset, length));
+        }
+        self
+    }
+
+    /// Append the text in bold.
+    pub fn bold(self, text: impl AsRef<str>) -> Self {
+        self.push_entity(text.as_ref(), EntityKind::Bold)
+    }
+
+    /// Append the text in italic.
+    pub fn italic(self, text: impl AsRef<str>) -> Self {
+        self.push_entity(text.as_ref(), EntityKind::Italic)
+    }
+
+    /// Append the text with an underline.
+    pub fn underline(self, text: impl AsRef<str>) -> Self {
+        self.push_entity(text.as_ref(), EntityKind::Underline)
+    }
+
+    /// Append the text with a strikethrough.
+    pub fn strike(self, text: impl AsRef<str>) -> Self {
+        self.push_entity(text.as_ref(), EntityKind::Strike)
+    }
+
+    /// Append the text as inline code.
+    pub fn code(self, text: impl AsRef<str>) -> Self {
+        self.push_entity(text.as_ref(), EntityKind::Code)
+    }
+
+    /// Append the text as a code block in the language.
+    ///
+    /// Pass an empty string if the language is unknown.
+    pub fn pre(self, text: impl AsRef<str>, language: impl Into<String>) -> Self {
+        self.push_entity(text.as_ref(), EntityKind::Pre(language.into()))
+    }
+
+    /// Append the text as a link to the URL.
+    pub fn link(self, text: impl AsRef<str>, url: impl Into<String>) -> Self {
+        self.push_entity(text.as_ref(), EntityKind::TextUrl(url.into()))
+    }
+
+    /// Append the text as a mention of the user with this ID.
+    pub fn mention(self, text: impl AsRef<str>, user_id: i32) -> Self {
+        self.link(text, user_link(user_id))
+    }
+
+    /// Append the text as a link to the message.
+    ///
+    /// The text is appended without a link if the chat
+    /// has no message links; see [`message_link`].
+    pub fn message_link(self, text: impl AsRef<str>, chat: &Chat, message_id: i32) -> Self {
+        match message_link(chat, message_id) {
+            Some(url) => self.link(text, url),
+            None => self.push(text),
+        }
+    }
+
+    /// Parse the Markdown subset.
+    ///
+    /// The supported syntax is `**bold**`, `*italic*` or `_italic_`,
+    /// `__underline__`, `~~strike~~`, `` `code` ``, the fenced
+    /// code blocks and `[text](url)`. Use `\` to escape a character;
+    /// see [`escape_markdown`]. Unclosed markers are kept as is.
+    pub fn markdown(source: &str) -> Self {
+        MarkdownParser::default().parse(source)
+    }
+
+    /// Parse the HTML subset.
+    ///
+    /// The supported tags are `<b>`, `<strong>`, `<i>`, `<em>`, `<u>`,
+    /// `<s>`, `<del>`, `<code>`, `<pre>` (optionally with
+    /// `<code class="language-...">` inside) and `<a href="...">`.
+    /// Other tags are dropped, and `&lt;`, `&gt;`, `&amp;`, `&quot;` and
+    /// `&#39;` are unescaped; see [`escape_html`].
+    pub fn html(source: &str) -> Self {
+        parse_html(source)
+    }
+
+    /// Split into chunks not longer than `limit` UTF-16 code units.
+    ///
+    /// It prefers splitting at line breaks, and falls back to splitting
+    /// at any character if a single line is too long. The entities
+    /// crossing the boundaries are split into the chunks too.
+    pub fn split(&self, limit: usize) -> Vec<FormattedText> {
+        let limit = limit as i32;
+        let mut bounds = Vec::new();
+        let (mut start, mut current_len) = (0, 0);
+
+        for line in self.text.split_inclusive('\n') {
+            let line_len = utf16_len(line);
+
+            // Close the current chunk if this line does not fit.
+            if current_len + line_len > limit && current_len > 0 {
+                bounds.push((start, start + current_len));
+                start += current_len;
+                current_len = 0;
+            }
+
+            if line_len <= limit {
+                current_len += line_len;
+                continue;
+            }
+
+            // The line itself is too long – split it by characters.
+            for c in line.chars() {
+                let c_len = c.len_utf16() as i32;
+
+                if current_len + c_len > limit {
+                    bounds.push((start, start + current_len));
+                    start += current_len;
+                    current_len = 0;
+                }
+
+                current_len += c_len;
+            }
+        }
+
+        if current_len > 0 || bounds.is_empty() {
+            bounds.push((start, start + current_len));
+        }
+
+        bounds
+            .into_iter()
+            .map(|(start, end)| self.slice(start, end))
+            .collect()
+    }
+
+    /// Get the part of `[start, end)` in UTF-16 code units.
+    fn slice(&self, start: i32, end: i32) -> FormattedText {
+        let text = String::from_utf16_lossy(
+            &self
+                .text
+                .encode_utf16()
+                .skip(start as usize)
+                .take((end - start) as usize)
+                .collect::<Vec<_>>(),
+        );
+
+        let entities = self
+            .entities
+            .iter()
+            .filter_map(|entity| {
+                let mut entity = entity.clone();
+                let (offset, length) = entity_range(&mut entity);
+                let entity_start = (*offset).max(start);
+                let entity_end = (*offset + *length).min(end);
+
+                if entity_start >= entity_end {
+                    return None;
+                }
+
+                *offset = entity_start - start;
+                *length = entity_end - entity_start;
+                Some(entity)
+            })
+            .collect();
+
+        FormattedText { text, entities }
+    }
+}
+
+impl From<&str> for FormattedText {
+    fn from(text: &str) -> Self {
+        Self::plain(text)
+    }
+}
+
+impl From<String> for FormattedText {
+    fn from(text: String) -> Self {
+        Self::plain(text)
+    }
+}
+
+impl From<&String> for FormattedText {
+    fn from(text: &String) -> Self {
+        Self::plain(text.as_str())
+    }
+}
+
+impl From<FormattedText> for InputMessage {
+    fn from(text: FormattedText) -> Self {
+        InputMessage::text(text.text).fmt_entities(text.entities)
+    }
+}
+
+/// Get the link to mention the user with this ID.
+pub fn user_link(user_id: i32) -> String {
+    format!("tg://user?id={}", user_id)
+}
+
+/// Get the link to the message in the chat.
+///
+/// Only channels and supergroups have message links, so it
+/// returns `None` for private chats and small groups.
+pub fn message_link(chat: &Chat, message_id: i32) -> Option<String> {
+    match chat {
+        Chat::Channel(_) => {}
+        Chat::Group(group) if group.is_megagroup() => {}
+        _ => return None,
+    }
+
+    Some(format!("https://t.me/c/{}/{}", chat.id(), message_id))
+}
+
+/// Escape the text so [`FormattedText::markdown`] keeps it as is.
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '~' | '`' | '[' | ']' | '(' | ')') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Escape the text so [`FormattedText::html`] keeps it as is.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// An opened but not yet closed Markdown marker.
+struct OpenMarker {
+    /// The marker itself, such as `**`.
+    marker: &'static str,
+    /// Where the marker is in the output, in bytes.
+    byte_pos: usize,
+    /// Where the marker is in the output, in UTF-16 code units.
+    offset: i32,
+}
+
+/// The state of parsing Markdown.
+#[derive(Default)]
+struct MarkdownParser {
+    /// The formatted text parsed so far.
+    output: FormattedText,
+    /// The markers which have been opened.
+    open: Vec<OpenMarker>,
+}
+
+impl MarkdownParser {
+    /// The markers toggling an entity, longest first.
+    const TOGGLES: [&'static str; 6] = ["**", "__", "~~", "*", "_", "["];
+
+    /// Get the kind of the entity the marker toggles.
+    fn toggle_kind(marker: &str) -> EntityKind {
+        match marker {
+            "**" => EntityKind::Bold,
+            "__" => EntityKind::Underline,
+            "~~" => EntityKind::Strike,
+            "[" => EntityKind::TextUrl(String::new()),
+            _ => EntityKind::Italic,
+        }
+    }
+
+    fn parse(mut self, source: &str) -> FormattedText {
+        let mut rest = source;
+
+        while let Some(c) = rest.chars().next() {
+            // Escaped character.
+            if c == '\\' {
+                let mut chars = rest[1..].chars();
+                match chars.next() {
+                    Some(escaped) => {
+                        self.output.text.push(escaped);
+                        rest = chars.as_str();
+                    }
+                    None => {
+                        self.output.text.push('\\');
+                        rest = "";
+                    }
+                }
+                continue;
+            }
+
+            // Fenced code block.
+            if let Some(block) = rest.strip_prefix("```") {
+                match block.find("```") {
+                    Some(end) => {
+                        let (language, code) = match block[..end].split_once('\n') {
+                            Some((language, code)) => (language.trim(), code),
+                            None => ("", &block[..end]),
+                        };
+                        self.push_raw(code, EntityKind::Pre(language.to_string()));
+                        rest = &block[end + 3..];
+                    }
+                    None => {
+                        self.output.text.push_str("```");
+                        rest = block;
+                    }
+                }
+                continue;
+            }
+
+            // Inline code.
+            if let Some(code) = rest.strip_prefix('`') {
+                if let Some(end) = code.find('`') {
+                    self.push_raw(&code[..end], EntityKind::Code);
+                    rest = &code[end + 1..];
+                    continue;
+                }
+            }
+
+            // The end of a link.
+            if let Some(link) = rest.strip_prefix("](") {
+                if let Some(end) = link.find(')') {
+                    if self.close("[", EntityKind::TextUrl(link[..end].to_string())) {
+                        rest = &link[end + 1..];
+                        continue;
+                    }
+                }
+            }
+
+            // Toggle markers.
+            //
+            // A single `_` between two words, such as `snake_case`, is not a marker.
+            let in_word = rest.starts_with('_')
+                && !rest.starts_with("__")
+                && matches!(self.output.text.chars().last(), Some(c) if c.is_alphanumeric())
+                && matches!(rest[1..].chars().next(), Some(c) if c.is_alphanumeric());
+            if let Some(marker) = Self::TOGGLES
+                .into_iter()
+                .filter(|_| !in_word)
+                .find(|marker| rest.starts_with(marker))
+            {
+                // A link is only closed by `](url)`.
+                if marker == "[" || !self.close(marker, Self::toggle_kind(marker)) {
+                    self.open.push(OpenMarker {
+                        marker,
+                        byte_pos: self.output.text.len(),
+                        offset: utf16_len(&self.output.text),
+                    });
+                }
+                rest = &rest[marker.len()..];
+                continue;
+            }
+
+            self.output.text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+
+        // Put the unclosed markers back into the text, from the last one,
+        // shifting the entities after them.
+        while let Some(OpenMarker {
+            marker,
+            byte_pos,
+            offset,
+        }) = self.open.pop()
+        {
+            let shift = utf16_len(marker);
+            self.output.text.insert_str(byte_pos, marker);
+
+            for entity in self.output.entities.iter_mut() {
+                let (entity_offset, length) = entity_range(entity);
+                if *entity_offset >= offset {
+                    *entity_offset += shift;
+                } else if *entity_offset + *length > offset {
+                    *length += shift;
+                }
+            }
+        }
+
+        self.output.entities.sort_by_key(entity_offset);
+        self.output
+    }
+
+    /// Push the text without parsing it.
+    fn push_raw(&mut self, text: &str, kind: EntityKind) {
+        self.output = std::mem::take(&mut self.output).push_entity(text, kind);
+    }
+
+    /// Close the latest opened marker, and add its entity.
+    ///
+    /// It returns `false` if the marker has not been opened.
+    fn close(&mut self, marker: &str, kind: EntityKind) -> bool {
+        let index = match self.open.iter().rposition(|open| open.marker == marker) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        let OpenMarker { offset, .. } = self.open.remove(index);
+        let length = utf16_len(&self.output.text) - offset;
+        if length > 0 {
+            self.output.entities.push(kind.build(offset, length));
+        }
+
+        true
+    }
+}
+
+/// Parse the HTML subset; see [`FormattedText::html`].
+fn parse_html(source: &str) -> FormattedText {
+    let mut output = FormattedText::new();
+    // The opened tags with their offsets in UTF-16 code units.
+    let mut open: Vec<(String, i32, EntityKind)> = Vec::new();
+    let mut rest = source;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '&' {
+            if let Some((unescaped, len)) = ["&lt;", "&gt;", "&amp;", "&quot;", "&#39;"]
+                .iter()
+                .zip(['<', '>', '&', '"', '\''])
+                .find(|(entity, _)| rest.starts_with(*entity))
+                .map(|(entity, c)| (c, entity.len()))
+            {
+                output.text.push(unescaped);
+                rest = &rest[len..];
+                continue;
+            }
+        }
+
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                let tag = &rest[1..end];
+                rest = &rest[end + 1..];
+
+                if let Some(name) = tag.strip_prefix('/') {
+                    // Close the latest tag with the same name.
+                    let name = name.trim().to_ascii_lowercase();
+                    if let Some(index) = open.iter().rposition(|(open, ..)| *open == name) {
+                        let (_, offset, kind) = open.remove(index);
+                        let length = utf16_len(&output.text) - offset;
+                        if length > 0 {
+                            output.entities.push(kind.build(offset, length));
+                        }
+                    }
+                } else {
+                    let (name, attrs) = match tag.split_once(char::is_whitespace) {
+                        Some((name, attrs)) => (name.to_ascii_lowercase(), attrs),
+                        None => (tag.trim_end_matches('/').to_ascii_lowercase(), ""),
+                    };
+                    let offset = utf16_len(&output.text);
+
+                    let kind = match name.as_str() {
+                        "b" | "strong" => Some(EntityKind::Bold),
+                        "i" | "em" => Some(EntityKind::Italic),
+                        "u" | "ins" => Some(EntityKind::Underline),
+                        "s" | "del" | "strike" => Some(EntityKind::Strike),
+                        "pre" => Some(EntityKind::Pre(String::new())),
+                        "code" => {
+                            // `<pre><code class="language-rust">` sets the language of `<pre>`.
+                            let language = html_attr(attrs, "class").and_then(|class| {
+                                class.strip_prefix("language-").map(str::to_string)
+                            });
+                            match (open.last_mut(), language) {
+                                (Some((_, pre_offset, EntityKind::Pre(pre))), Some(language))
+                                    if *pre_offset == offset =>
+                                {
+                                    *pre = language;
+                                    None
+                                }
+                                (Some((_, pre_offset, EntityKind::Pre(_))), None)
+                                    if *pre_offset == offset =>
+                                {
+                                    None
+                                }
+                                _ => Some(EntityKind::Code),
+                            }
+                        }
+                        "a" => html_attr(attrs, "href").map(EntityKind::TextUrl),
+                        "br" => {
+                            output.text.push('\n');
+                            None
+                        }
+                        _ => None,
+                    };
+
+                    if let Some(kind) = kind {
+                        open.push((name, offset, kind));
+                    }
+                }
+                continue;
+            }
+        }
+
+        output.text.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    // Close the unclosed tags at the end of the text.
+    let end = utf16_len(&output.text);
+    for (_, offset, kind) in open.into_iter().rev() {
+        if end > offset {
+            output.entities.push(kind.build(offset, end - offset));
+        }
+    }
+
+    output.entities.sort_by_key(entity_offset);
+    output
+}
+
+/// Get the value of the attribute in the HTML tag.
+///
+/// The attributes are read one by one, so `href` doesn't match
+/// `data-href`, nor the text in the values of the other attributes.
+fn html_attr(attrs: &str, name: &str) -> Option<String> {
+    let mut rest = attrs;
+
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let attr = &rest[..name_end];
+        rest = rest[name_end..].trim_start();
+
+        // An attribute without a value, such as `disabled`.
+        let value = match rest.strip_prefix('=') {
+            Some(value) => value.trim_start(),
+            None => continue,
+        };
+        let (value, remaining) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => match value[1..].find(quote) {
+                Some(end) => (&value[1..end + 1], &value[end + 2..]),
+                None => (&value[1..], ""),
+            },
+            _ => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+        rest = remaining;
+
+        if attr.eq_ignore_ascii_case(name) {
+            return Some(
+                value
+                    .replace("&quot;", "\"")
+                    .replace("&#39;", "'")
+                    .replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&amp;", "&"),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bold(offset: i32, length: i32) -> tl::enums::MessageEntity {
+        EntityKind::Bold.build(offset, length)
+    }
+
+    fn italic(offset: i32, length: i32) -> tl::enums::MessageEntity {
+        EntityKind::Italic.build(offset, length)
+    }
+
+    fn link(offset: i32, length: i32, url: &str) -> tl::enums::MessageEntity {
+        EntityKind::TextUrl(url.to_string()).build(offset, length)
+    }
+
+    #[test]
+    fn markdown_nested_markers() {
+        let text = FormattedText::markdown("**bold _both_** plain");
+
+        assert_eq!(text.text(), "bold both plain");
+        assert_eq!(text.entities(), [bold(0, 9), italic(5, 4)]);
+    }
+
+    #[test]
+    fn markdown_unclosed_markers_are_kept() {
+        let text = FormattedText::markdown("**bold");
+        assert_eq!(text.text(), "**bold");
+        assert!(text.entities().is_empty());
+
+        // The unclosed `*` is put back inside the bold text, which grows to cover it.
+        let text = FormattedText::markdown("**a *b** c");
+        assert_eq!(text.text(), "a *b c");
+        assert_eq!(text.entities(), [bold(0, 4)]);
+    }
+
+    #[test]
+    fn markdown_escapes_and_words() {
+        let text = FormattedText::markdown(r"\*not italic\* snake_case_name \\");
+
+        assert_eq!(text.text(), r"*not italic* snake_case_name \");
+        assert!(text.entities().is_empty());
+        assert_eq!(
+            FormattedText::markdown(&escape_markdown("**[x](y)**")).text(),
+            "**[x](y)**"
+        );
+    }
+
+    #[test]
+    fn markdown_links_and_code() {
+        let text = FormattedText::markdown("see [PBot](https://example.com) and `*code*`");
+
+        assert_eq!(text.text(), "see PBot and *code*");
+        assert_eq!(
+            text.entities(),
+            [
+                link(4, 4, "https://example.com"),
+                EntityKind::Code.build(13, 6),
+            ]
+        );
+
+        let text = FormattedText::markdown("```rust\nfn main() {}\n```");
+        assert_eq!(text.text(), "fn main() {}\n");
+        assert_eq!(
+            text.entities(),
+            [EntityKind::Pre("rust".to_string()).build(0, 13)]
+        );
+    }
+
+    #[test]
+    fn markdown_offsets_count_utf16_units() {
+        let text = FormattedText::markdown("😀 **粗體** 𝒳");
+
+        assert_eq!(text.text(), "😀 粗體 𝒳");
+        assert_eq!(text.entities(), [bold(3, 2)]);
+        assert_eq!(text.len(), 8);
+    }
+
+    #[test]
+    fn html_tags_and_escapes() {
+        let text = FormattedText::html("<b>bold <i>both</i></b> &lt;tag&gt; &amp;<br>😀<u>u");
+
+        assert_eq!(text.text(), "bold both <tag> &\n😀u");
+        assert_eq!(
+            text.entities(),
+            [bold(0, 9), italic(5, 4), EntityKind::Underline.build(20, 1),]
+        );
+    }
+
+    #[test]
+    fn html_pre_with_language() {
+        let text =
+            FormattedText::html(r#"<pre><code class="language-rust">let x = 1;</code></pre>"#);
+
+        assert_eq!(text.text(), "let x = 1;");
+        assert_eq!(
+            text.entities(),
+            [EntityKind::Pre("rust".to_string()).build(0, 10)]
+        );
+    }
+
+    #[test]
+    fn html_attr_matches_whole_names() {
+        let text = FormattedText::html(
+            r#"<a data-href="https://wrong.example" title='href=x' href="https://example.com/?a=1&amp;b=2">x</a>"#,
+        );
+        assert_eq!(
+            text.entities(),
+            [link(0, 1, "https://example.com/?a=1&b=2")]
+        );
+
+        let text = FormattedText::html(r#"<a data-href="https://wrong.example">x</a>"#);
+        assert!(text.entities().is_empty());
+
+        assert_eq!(html_attr("download href=x", "href"), Some("x".to_string()));
+        assert_eq!(html_attr("HREF = 'y'", "href"), Some("y".to_string()));
+    }
+
+    #[test]
+    fn split_prefers_line_breaks_and_splits_entities() {
+        let text = FormattedText::new().bold("aaaa\nbbbb\n").push("cc");
+        let chunks = text.split(5);
+
+        assert_eq!(
+            chunks.iter().map(FormattedText::text).collect::<Vec<_>>(),
+            ["aaaa\n", "bbbb\n", "cc"]
+        );
+        assert_eq!(chunks[0].entities(), [bold(0, 5)]);
+        assert_eq!(chunks[1].entities(), [bold(0, 5)]);
+        assert!(chunks[2].entities().is_empty());
+    }
+
+    #[test]
+    fn split_long_lines_keeps_surrogate_pairs() {
+        let text = FormattedText::new().push("x").italic("😀😀😀");
+        let chunks = text.split(4);
+
+        assert_eq!(
+            chunks.iter().map(FormattedText::text).collect::<Vec<_>>(),
+            ["x😀", "😀😀"]
+        );
+        assert_eq!(chunks[0].entities(), [italic(1, 2)]);
+        assert_eq!(chunks[1].entities(), [italic(0, 4)]);
+        assert_eq!(FormattedText::new().split(10).len(), 1);
+    }
+
+    #[test]
+    fn append_shifts_entities() {
+        let text = FormattedText::plain("😀 ").append(FormattedText::new().bold("b"));
+
+        assert_eq!(text.text(), "😀 b");
+        assert_eq!(text.entities(), [bold(3, 1)]);
+    }
+}
@@ -0,0 +1,213 @@
+//! PBot: Telegram: Update Recorder
+//!
+//! This records the incoming updates into a JSONL file, so the updates
+//! which made a module misbehave can be replayed with `pbot replay <file>`.
+//!
+//! `grammers_client` can't construct the messages outside itself,
+//! so a recording holds the metadata of the messages instead of the
+//! messages themselves. See [`super::replay`] for how they are replayed.
+
+pub mod commands;
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use actix::prelude::*;
+use chrono::Utc;
+use grammers_client::types::{chat::PackedChat, Chat, Media, Message};
+use grammers_client::Update;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use self::commands::RecordUpdateCommand;
+
+/// What to redact from the recorded updates.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Redaction {
+    /// Replace the text of the messages with its length.
+    pub text: bool,
+    /// Drop the names of the chats and the senders.
+    pub names: bool,
+}
+
+impl Redaction {
+    /// Read the redaction from the environment variable `TG_RECORD_REDACT`,
+    /// such as `text,names`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any item is unknown.
+    pub fn from_env() -> Self {
+        let mut redaction = Self::default();
+
+        for item in crate::getenv_opt!("TG_RECORD_REDACT")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            match item {
+                "text" => redaction.text = true,
+                "names" => redaction.names = true,
+                _ => panic!("TG_RECORD_REDACT should only have text and names"),
+            }
+        }
+
+        redaction
+    }
+}
+
+/// A recorded update.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedUpdate {
+    /// When the update was received, in UNIX timestamp.
+    pub recorded_at: i64,
+    /// The serialized [`PackedChat`] where the message is.
+    pub chat: Vec<u8>,
+    /// The ID of the chat where the message is.
+    pub chat_id: i32,
+    /// The name of the chat, unless redacted.
+    #[serde(default)]
+    pub chat_name: Option<String>,
+    /// The ID of the message.
+    pub message_id: i32,
+    /// The ID of the sender, if any.
+    #[serde(default)]
+    pub sender_id: Option<i32>,
+    /// The name of the sender, unless redacted.
+    #[serde(default)]
+    pub sender_name: Option<String>,
+    /// Whether the message was sent by us.
+    #[serde(default)]
+    pub outgoing: bool,
+    /// The ID of the message replied to, if any.
+    #[serde(default)]
+    pub reply_to_message_id: Option<i32>,
+    /// The text of the message, or its length if redacted.
+    #[serde(default)]
+    pub text: String,
+    /// The kind of the media, such as `photo`, if any.
+    #[serde(default)]
+    pub media: Option<String>,
+    /// When the message was sent, in UNIX timestamp.
+    #[serde(default)]
+    pub date: i64,
+}
+
+impl RecordedUpdate {
+    /// Record the update, with the specified fields redacted.
+    ///
+    /// It returns `None` if the update is not supported.
+    pub fn new(update: &Update, redaction: Redaction) -> Option<Self> {
+        match update {
+            Update::NewMessage(message) => Some(Self::from_message(message, redaction)),
+            _ => None,
+        }
+    }
+
+    /// Record the message, with the specified fields redacted.
+    fn from_message(message: &Message, redaction: Redaction) -> Self {
+        let chat = message.chat();
+        let sender = message.sender();
+        let name_of = |chat: &Chat| (!redaction.names).then(|| chat.name().to_string());
+
+        Self {
+            recorded_at: Utc::now().timestamp(),
+            chat: chat.pack().to_bytes(),
+            chat_id: chat.id(),
+            chat_name: name_of(&chat),
+            message_id: message.id(),
+            sender_id: sender.as_ref().map(Chat::id),
+            sender_name: sender.as_ref().and_then(name_of),
+            outgoing: message.outgoing(),
+            reply_to_message_id: message.reply_to_message_id(),
+            text: if redaction.text {
+                redact_text(message.text())
+            } else {
+                message.text().to_string()
+            },
+            media: message.media().map(|media| {
+                match media {
+                    Media::Photo(_) => "photo",
+                    Media::Document(_) => "document",
+                    Media::Sticker(_) => "sticker",
+                    _ => "unknown",
+                }
+                .to_string()
+            }),
+            date: message.date().timestamp(),
+        }
+    }
+
+    /// Get the chat where the message is.
+    pub fn chat(&self) -> Option<Chat> {
+        PackedChat::from_bytes(&self.chat)
+            .ok()
+            .map(|chat| chat.unpack())
+    }
+}
+
+/// Redact the text of a message, keeping only its length.
+pub fn redact_text(text: &str) -> String {
+    format!("<redacted: {} chars>", text.chars().count())
+}
+
+/// Read the recorded updates from the JSONL file.
+pub fn read_recording(path: impl AsRef<Path>) -> anyhow::Result<Vec<RecordedUpdate>> {
+    let mut updates = Vec::new();
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            updates.push(serde_json::from_str(&line)?);
+        }
+    }
+
+    Ok(updates)
+}
+
+/// The recorder actor.
+pub struct RecorderActor {
+    /// The JSONL file to append the updates to.
+    file: File,
+}
+
+impl RecorderActor {
+    /// Open the JSONL file to append the updates to.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self { file })
+    }
+}
+
+impl Actor for RecorderActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, _: &mut Self::Context) {
+        info!("🌟 Recorder started!");
+    }
+
+    fn stopped(&mut self, _: &mut Self::Context) {
+        info!("👋 Recorder stopped!");
+    }
+}
+
+impl Handler<RecordUpdateCommand> for RecorderActor {
+    type Result = ();
+
+    /// Append the update to the recording.
+    fn handle(&mut self, cmd: RecordUpdateCommand, _: &mut Context<Self>) -> Self::Result {
+        let result = serde_json::to_vec(&cmd.0)
+            .map_err(std::io::Error::from)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                self.file.write_all(&line)
+            });
+
+        if let Err(e) = result {
+            error!("Failed to record the update: {:?}", e);
+        }
+    }
+}
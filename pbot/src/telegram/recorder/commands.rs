@@ -0,0 +1,10 @@
+//! Commands for the recorder actor.
+
+use actix::prelude::*;
+
+use super::RecordedUpdate;
+
+/// Append the update to the recording.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RecordUpdateCommand(pub RecordedUpdate);
@@ -0,0 +1,232 @@
+//! PBot: Telegram: Update Replay
+//!
+//! This replays a recording of [`super::recorder`] through the modules.
+//!
+//! `grammers_client` can't construct the messages outside itself, so
+//! `pbot replay <file>` connects the client to the [`server`] answering
+//! from the recording instead of Telegram, and the recorded messages are
+//! fetched from it by their IDs. Each fetched message is checked against
+//! its recording before being dispatched, so a message rebuilt wrongly
+//! is skipped instead of replaying something the modules never saw.
+//!
+//! The client always runs in the dry-run mode when replaying, and each
+//! update is handled by the modules before the next one is dispatched.
+
+pub mod server;
+
+use std::future::Future;
+
+use grammers_client::types::{Chat, Message};
+use log::{info, warn};
+
+use super::recorder::{redact_text, RecordedUpdate};
+
+/// The fields of a message compared with its recording.
+pub trait ReplayedMessage {
+    /// The text of the message.
+    fn text(&self) -> &str;
+    /// Whether the message was sent by us.
+    fn outgoing(&self) -> bool;
+    /// The ID of the sender, if any.
+    fn sender_id(&self) -> Option<i32>;
+}
+
+impl ReplayedMessage for Message {
+    fn text(&self) -> &str {
+        Message::text(self)
+    }
+
+    fn outgoing(&self) -> bool {
+        Message::outgoing(self)
+    }
+
+    fn sender_id(&self) -> Option<i32> {
+        self.sender().as_ref().map(Chat::id)
+    }
+}
+
+/// Why a recorded update was not replayed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The recorded chat is malformed.
+    MalformedChat,
+    /// Failed to fetch the message again.
+    FetchFailed(String),
+    /// The message no longer exists.
+    Deleted,
+    /// The text of the message differs from the recording.
+    Edited,
+    /// The sender of the message differs from the recording.
+    SenderChanged,
+}
+
+/// The summary of a replay.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    /// The number of the replayed updates.
+    pub replayed: usize,
+    /// The indexes of the skipped updates, with the reasons.
+    pub skipped: Vec<(usize, SkipReason)>,
+}
+
+/// Check if the fetched message is still the recorded one.
+pub fn check(record: &RecordedUpdate, message: &impl ReplayedMessage) -> Result<(), SkipReason> {
+    if message.outgoing() != record.outgoing || message.sender_id() != record.sender_id {
+        return Err(SkipReason::SenderChanged);
+    }
+
+    // The redacted text only keeps the length of the text.
+    let text = message.text();
+    if record.text != text && record.text != redact_text(text) {
+        return Err(SkipReason::Edited);
+    }
+
+    Ok(())
+}
+
+/// Replay the recorded updates in order.
+///
+/// `fetch` gets the message of the update again with the chat and
+/// the message ID, and `dispatch` hands the message to the modules.
+/// `dispatch` is awaited before the next update, so the updates are
+/// dispatched in order.
+pub async fn replay<M, F, FFut, D, DFut>(
+    recording: Vec<RecordedUpdate>,
+    mut fetch: F,
+    mut dispatch: D,
+) -> ReplaySummary
+where
+    M: ReplayedMessage,
+    F: FnMut(Chat, i32) -> FFut,
+    FFut: Future<Output = anyhow::Result<Option<M>>>,
+    D: FnMut(M) -> DFut,
+    DFut: Future<Output = anyhow::Result<()>>,
+{
+    let mut summary = ReplaySummary::default();
+
+    for (index, record) in recording.into_iter().enumerate() {
+        let result = match record.chat() {
+            Some(chat) => match fetch(chat, record.message_id).await {
+                Ok(Some(message)) => check(&record, &message).map(|_| message),
+                Ok(None) => Err(SkipReason::Deleted),
+                Err(e) => Err(SkipReason::FetchFailed(format!("{:?}", e))),
+            },
+            None => Err(SkipReason::MalformedChat),
+        };
+
+        match result {
+            Ok(message) => {
+                info!(
+                    "Replaying update #{}: message {} in {}.",
+                    index, record.message_id, record.chat_id
+                );
+                if let Err(e) = dispatch(message).await {
+                    warn!("Update #{} was not handled: {:?}", index, e);
+                }
+                summary.replayed += 1;
+            }
+            Err(reason) => {
+                warn!(
+                    "Skipped update #{}: message {} in {}: {:?}",
+                    index, record.message_id, record.chat_id, reason
+                );
+                summary.skipped.push((index, reason));
+            }
+        }
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use crate::telegram::recorder::read_recording;
+
+    use super::*;
+
+    /// The recording of the fixture.
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/recording.jsonl"
+    );
+
+    /// A message as fetched again when replaying.
+    #[derive(Clone, Debug)]
+    struct FakeMessage {
+        text: &'static str,
+        outgoing: bool,
+        sender_id: Option<i32>,
+    }
+
+    impl ReplayedMessage for FakeMessage {
+        fn text(&self) -> &str {
+            self.text
+        }
+
+        fn outgoing(&self) -> bool {
+            self.outgoing
+        }
+
+        fn sender_id(&self) -> Option<i32> {
+            self.sender_id
+        }
+    }
+
+    /// The messages of the chats in the fixture as they are now, keyed by
+    /// the chat IDs and the message IDs.
+    fn current_messages() -> HashMap<(i32, i32), FakeMessage> {
+        let message = |text, outgoing, sender_id| FakeMessage {
+            text,
+            outgoing,
+            sender_id: Some(sender_id),
+        };
+
+        HashMap::from([
+            ((1001, 10), message("!ping", true, 42)),
+            ((1234, 20), message("hello, edited", false, 1001)),
+            ((1234, 22), message("hi 👋!", false, 1001)),
+            ((1001, 11), message("!del", true, 42)),
+        ])
+    }
+
+    #[tokio::test]
+    async fn replays_the_fixture_in_order() {
+        let recording = read_recording(FIXTURE).expect("failed to read the fixture");
+        let messages = current_messages();
+        let dispatched = RefCell::new(Vec::new());
+
+        let summary = replay(
+            recording,
+            |chat, message_id| {
+                let result = match message_id {
+                    99 => Err(anyhow::anyhow!("FLOOD_WAIT")),
+                    _ => Ok(messages.get(&(chat.id(), message_id)).cloned()),
+                };
+                async move { result }
+            },
+            |message| {
+                dispatched.borrow_mut().push(message.text);
+                async { Ok(()) }
+            },
+        )
+        .await;
+
+        assert_eq!(dispatched.into_inner(), ["!ping", "hi 👋!"]);
+        assert_eq!(
+            summary,
+            ReplaySummary {
+                replayed: 2,
+                skipped: vec![
+                    (1, SkipReason::Edited),
+                    (2, SkipReason::MalformedChat),
+                    (3, SkipReason::Deleted),
+                    (5, SkipReason::SenderChanged),
+                    (6, SkipReason::FetchFailed("FLOOD_WAIT".to_string())),
+                ],
+            }
+        );
+    }
+}
@@ -0,0 +1,875 @@
+//! PBot: Telegram: Update Replay: Recording Server
+//!
+//! A loopback MTProto server answering the client from a recording,
+//! so `pbot replay <file>` never logs in or talks to Telegram.
+//!
+//! The client connects with an authorization key fixed in both sides
+//! instead of generating one. The server rebuilds the messages, the
+//! users and the chats from the recorded fields, and answers the requests
+//! fetching them; any other request fails with `REPLAY_UNSUPPORTED`.
+//! The media and the formatting are not recorded, so the rebuilt messages
+//! have only their text, and a redacted text stays redacted.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use bytes::{Buf, BytesMut};
+use flate2::read::GzDecoder;
+use grammers_client::client::auth::AuthorizationError;
+use grammers_client::types::chat::PackedChat;
+use grammers_client::{Client, Config, InitParams};
+use grammers_crypto::{decrypt_data_v2, encrypt_data_v2, AuthKey};
+use grammers_mtproto::transport::{self, Full, Transport};
+use grammers_session::Session;
+use grammers_tl_types::{self as tl, Cursor, Deserializable, Identifiable, Serializable};
+use log::{error, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::super::recorder::RecordedUpdate;
+
+/// The data center which the client connects to with an empty session.
+const DEFAULT_DC: i32 = 2;
+
+/// The error of the requests the recording can't answer.
+const UNSUPPORTED: &str = "REPLAY_UNSUPPORTED";
+
+/// The constructor of `rpc_result`, which wraps the answer of a request.
+const RPC_RESULT: u32 = 0xf35c_6d01;
+
+/// The constructor of `msg_container`, which bundles several messages.
+const MSG_CONTAINER: u32 = 0x73f1_f8dc;
+
+/// The constructor of `gzip_packed`, which wraps a compressed message.
+const GZIP_PACKED: u32 = 0x3072_cfa1;
+
+// The types in the layout of `PackedChat::to_bytes`.
+const PACKED_USER: u8 = 0b0000_0010;
+const PACKED_BOT: u8 = 0b0000_0011;
+const PACKED_CHAT: u8 = 0b0000_0100;
+const PACKED_BROADCAST: u8 = 0b0011_0000;
+const PACKED_GIGAGROUP: u8 = 0b0011_1000;
+
+/// The authorization key shared by the server and the client.
+///
+/// MTProto derives the keys of the two directions from the authorization
+/// key 8 bytes apart, and `grammers_crypto` only implements the client
+/// side of them. This key repeats every 8 bytes, so both directions derive
+/// the same keys, and the server can use `grammers_crypto` as well.
+fn auth_key() -> AuthKey {
+    let mut data = [0; 256];
+    for (index, byte) in data.iter_mut().enumerate() {
+        *byte = b"pbot-rpl"[index % 8];
+    }
+
+    AuthKey::from_bytes(data)
+}
+
+/// Start serving the recording on a loopback port, and return its address.
+pub async fn serve(recording: &[RecordedUpdate]) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let addr = listener.local_addr()?;
+    let recording = Arc::new(Recording::new(recording));
+
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    error!("The replay server stopped accepting: {:?}", e);
+                    break;
+                }
+            };
+
+            let recording = recording.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve_connection(stream, &recording).await {
+                    warn!("The replay connection was closed: {:?}", e);
+                }
+            });
+        }
+    });
+
+    Ok(addr)
+}
+
+/// Connect a client to the replay server at `addr`.
+pub async fn connect(addr: SocketAddr) -> Result<Client, AuthorizationError> {
+    let session = Session::new();
+    session.insert_dc(DEFAULT_DC, addr, &auth_key());
+
+    Client::connect(Config {
+        session,
+        api_id: 0,
+        api_hash: String::new(),
+        params: InitParams {
+            server_addr: Some(addr),
+            ..Default::default()
+        },
+    })
+    .await
+}
+
+/// A basic group standing in for the chat which is not in the recording.
+pub fn placeholder_chat(id: i32) -> PackedChat {
+    let mut bytes = vec![PACKED_CHAT, 6];
+    bytes.extend(id.to_le_bytes());
+
+    PackedChat::from_bytes(&bytes).expect("the placeholder should be a valid packed chat")
+}
+
+/// Answer the requests of a client until it disconnects.
+async fn serve_connection(mut stream: TcpStream, recording: &Recording) -> io::Result<()> {
+    let key = auth_key();
+    let mut transport = Full::new();
+    let mut input = BytesMut::new();
+    let mut payload = BytesMut::new();
+    let mut output = BytesMut::new();
+    let mut sent: i32 = 0;
+
+    loop {
+        let len = match transport.unpack(&input, &mut payload) {
+            Ok(len) => len,
+            Err(transport::Error::MissingBytes) => {
+                if stream.read_buf(&mut input).await? == 0 {
+                    return Ok(());
+                }
+                continue;
+            }
+            Err(e) => return Err(invalid_data(e)),
+        };
+        input.advance(len);
+
+        let plaintext = decrypt_data_v2(&payload, &key).map_err(invalid_data)?;
+        payload.clear();
+
+        // salt:long session_id:long msg_id:long seq_no:int bytes:int body
+        let mut cursor = Cursor::from_slice(&plaintext);
+        let _salt = i64::deserialize(&mut cursor).map_err(invalid_data)?;
+        let session_id = i64::deserialize(&mut cursor).map_err(invalid_data)?;
+        let (msg_id, body) = read_message(&mut cursor)?;
+
+        let mut requests = Vec::new();
+        unwrap_requests(msg_id, body, &mut requests)?;
+
+        for (msg_id, request) in requests {
+            let answer = recording.answer(msg_id, &request);
+
+            // The client checks neither the salt nor the IDs of our messages.
+            sent += 1;
+            let mut message = Vec::with_capacity(32 + answer.len());
+            message.extend(0i64.to_le_bytes());
+            message.extend(session_id.to_le_bytes());
+            message.extend((i64::from(sent) * 4 + 1).to_le_bytes());
+            message.extend((sent * 2 + 1).to_le_bytes());
+            message.extend((answer.len() as i32).to_le_bytes());
+            message.extend(answer);
+
+            transport.pack(&encrypt_data_v2(&message, &key), &mut output);
+        }
+
+        stream.write_all(&output).await?;
+        output.clear();
+    }
+}
+
+/// Read a message: `msg_id:long seq_no:int bytes:int body`.
+fn read_message(cursor: &mut Cursor) -> io::Result<(i64, Vec<u8>)> {
+    let msg_id = i64::deserialize(cursor).map_err(invalid_data)?;
+    let _seq_no = i32::deserialize(cursor).map_err(invalid_data)?;
+    let len = i32::deserialize(cursor).map_err(invalid_data)?;
+
+    let mut body = vec![0; usize::try_from(len).map_err(invalid_data)?];
+    cursor.read_exact(&mut body).map_err(invalid_data)?;
+
+    Ok((msg_id, body))
+}
+
+/// Collect the requests in the message, unwrapping the containers and
+/// the compressed messages, and dropping the acknowledgements.
+fn unwrap_requests(
+    msg_id: i64,
+    body: Vec<u8>,
+    requests: &mut Vec<(i64, Vec<u8>)>,
+) -> io::Result<()> {
+    let mut cursor = Cursor::from_slice(&body);
+
+    match u32::deserialize(&mut cursor).map_err(invalid_data)? {
+        MSG_CONTAINER => {
+            for _ in 0..i32::deserialize(&mut cursor).map_err(invalid_data)? {
+                let (msg_id, body) = read_message(&mut cursor)?;
+                unwrap_requests(msg_id, body, requests)?;
+            }
+        }
+        GZIP_PACKED => {
+            let packed = Vec::<u8>::deserialize(&mut cursor).map_err(invalid_data)?;
+            let mut body = Vec::new();
+            GzDecoder::new(&packed[..]).read_to_end(&mut body)?;
+            unwrap_requests(msg_id, body, requests)?;
+        }
+        tl::types::MsgsAck::CONSTRUCTOR_ID => {}
+        _ => requests.push((msg_id, body)),
+    }
+
+    Ok(())
+}
+
+/// Wrap the error in an [`io::Error`] of the malformed data.
+fn invalid_data(e: impl std::fmt::Debug) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+/// A peer of the recording.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum PeerId {
+    User(i32),
+    Chat(i32),
+    Channel(i32),
+}
+
+impl PeerId {
+    fn to_peer(self) -> tl::enums::Peer {
+        match self {
+            Self::User(user_id) => tl::types::PeerUser { user_id }.into(),
+            Self::Chat(chat_id) => tl::types::PeerChat { chat_id }.into(),
+            Self::Channel(channel_id) => tl::types::PeerChannel { channel_id }.into(),
+        }
+    }
+
+    fn of(peer: &tl::enums::Peer) -> Self {
+        match peer {
+            tl::enums::Peer::User(peer) => Self::User(peer.user_id),
+            tl::enums::Peer::Chat(peer) => Self::Chat(peer.chat_id),
+            tl::enums::Peer::Channel(peer) => Self::Channel(peer.channel_id),
+        }
+    }
+}
+
+/// The chat of a record, decoded from the layout of `PackedChat::to_bytes`:
+/// the type, the length, the ID and the access hash if any, in little endian.
+struct RecordedChat {
+    ty: u8,
+    id: i32,
+    access_hash: Option<i64>,
+}
+
+impl RecordedChat {
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        // Let grammers validate the layout first.
+        PackedChat::from_bytes(bytes).ok()?;
+
+        Some(Self {
+            ty: bytes[0],
+            id: i32::from_le_bytes(bytes[2..6].try_into().ok()?),
+            access_hash: bytes
+                .get(6..14)
+                .map(|hash| i64::from_le_bytes(hash.try_into().unwrap())),
+        })
+    }
+
+    fn peer(&self) -> PeerId {
+        match self.ty {
+            PACKED_USER | PACKED_BOT => PeerId::User(self.id),
+            PACKED_CHAT => PeerId::Chat(self.id),
+            _ => PeerId::Channel(self.id),
+        }
+    }
+}
+
+/// The users, the chats and the messages rebuilt from a recording.
+struct Recording {
+    /// The ID of our account, which sent the outgoing messages.
+    self_id: Option<i32>,
+    users: BTreeMap<i32, tl::types::User>,
+    chats: BTreeMap<PeerId, tl::enums::Chat>,
+    /// The recorded chats, with the ID of their last messages.
+    dialogs: BTreeMap<PeerId, i32>,
+    messages: Vec<tl::types::Message>,
+}
+
+impl Recording {
+    fn new(records: &[RecordedUpdate]) -> Self {
+        let self_id = records
+            .iter()
+            .find(|record| record.outgoing)
+            .and_then(|record| record.sender_id);
+
+        // The names may be recorded in some of the records only.
+        let mut names = HashMap::new();
+        for record in records {
+            if let Some(name) = &record.chat_name {
+                names.insert(record.chat_id, name.clone());
+            }
+            if let (Some(id), Some(name)) = (record.sender_id, &record.sender_name) {
+                names.insert(id, name.clone());
+            }
+        }
+        let name_of = |id: i32| names.get(&id).cloned().unwrap_or_else(|| id.to_string());
+
+        let mut recording = Self {
+            self_id,
+            users: BTreeMap::new(),
+            chats: BTreeMap::new(),
+            dialogs: BTreeMap::new(),
+            messages: Vec::new(),
+        };
+
+        // The chats go first, since only they have the access hashes.
+        let recorded = records
+            .iter()
+            .filter_map(|record| Some((record, RecordedChat::decode(&record.chat)?)))
+            .collect::<Vec<_>>();
+        for (_, chat) in &recorded {
+            let name = name_of(chat.id);
+            match chat.peer() {
+                PeerId::User(id) => {
+                    let mut user = user(id, name);
+                    user.access_hash = chat.access_hash;
+                    user.bot = chat.ty == PACKED_BOT;
+                    recording.users.insert(id, user);
+                }
+                PeerId::Chat(id) => {
+                    recording.chats.insert(chat.peer(), group(id, name));
+                }
+                PeerId::Channel(id) => {
+                    let mut channel = channel(id, chat.access_hash, name);
+                    channel.broadcast = chat.ty == PACKED_BROADCAST;
+                    channel.gigagroup = chat.ty == PACKED_GIGAGROUP;
+                    channel.megagroup = !channel.broadcast;
+                    recording.chats.insert(chat.peer(), channel.into());
+                }
+            }
+        }
+
+        for (record, chat) in recorded {
+            let peer = chat.peer();
+            // The posts of channels and the anonymous admins are sent as the chat.
+            let from = record.sender_id.map(|id| match peer {
+                PeerId::Channel(channel_id) if channel_id == id => peer,
+                _ => PeerId::User(id),
+            });
+            if let Some(PeerId::User(id)) = from {
+                recording
+                    .users
+                    .entry(id)
+                    .or_insert_with(|| user(id, name_of(id)));
+            }
+
+            let top = recording.dialogs.entry(peer).or_default();
+            *top = record.message_id.max(*top);
+            recording.messages.push(message(record, peer, from));
+        }
+
+        if let Some(user) = self_id.and_then(|id| recording.users.get_mut(&id)) {
+            user.is_self = true;
+        }
+
+        recording
+    }
+
+    /// Answer the request, and return the body of the message to reply.
+    ///
+    /// `grammers_tl_types` can't parse the requests, so their arguments
+    /// are parsed after the constructors in the order of their schema.
+    fn answer(&self, msg_id: i64, request: &[u8]) -> Vec<u8> {
+        use tl::functions::{channels, help, messages, users};
+
+        let constructor = request
+            .get(..4)
+            .map(|constructor| u32::from_le_bytes(constructor.try_into().unwrap()));
+        let mut args = Cursor::from_slice(request.get(4..).unwrap_or_default());
+
+        let result = match constructor.unwrap_or_default() {
+            // `grammers_client` only wraps `help.getConfig` when connecting.
+            tl::functions::InvokeWithLayer::<help::GetConfig>::CONSTRUCTOR_ID
+            | help::GetConfig::CONSTRUCTOR_ID => Some(tl::enums::Config::from(config()).to_bytes()),
+            // ping#7abe77ec ping_id:long
+            // ping_delay_disconnect#f3427b8c ping_id:long disconnect_delay:int
+            //
+            // The pongs are not wrapped in `rpc_result`.
+            tl::functions::Ping::CONSTRUCTOR_ID
+            | tl::functions::PingDelayDisconnect::CONSTRUCTOR_ID => {
+                return tl::enums::Pong::from(tl::types::Pong {
+                    msg_id,
+                    ping_id: arg(&mut args).unwrap_or_default(),
+                })
+                .to_bytes();
+            }
+            // messages.getMessages#63c66506 id:Vector<InputMessage>
+            messages::GetMessages::CONSTRUCTOR_ID => {
+                arg(&mut args).map(|ids: Vec<_>| self.get_messages(None, &ids).to_bytes())
+            }
+            // channels.getMessages#ad8c9a23 channel:InputChannel id:Vector<InputMessage>
+            channels::GetMessages::CONSTRUCTOR_ID => match arg(&mut args) {
+                Some(tl::enums::InputChannel::Channel(channel)) => {
+                    arg(&mut args).map(|ids: Vec<_>| {
+                        self.get_messages(Some(channel.channel_id), &ids).to_bytes()
+                    })
+                }
+                _ => None,
+            },
+            messages::GetDialogs::CONSTRUCTOR_ID => Some(self.get_dialogs().to_bytes()),
+            // messages.getChats#3c6aa187 id:Vector<int>
+            messages::GetChats::CONSTRUCTOR_ID => {
+                arg(&mut args).map(|ids: Vec<_>| self.get_chats(ids).to_bytes())
+            }
+            // channels.getChannels#a7f6bbb id:Vector<InputChannel>
+            channels::GetChannels::CONSTRUCTOR_ID => {
+                arg(&mut args).map(|ids: Vec<_>| self.get_channels(ids).to_bytes())
+            }
+            // users.getUsers#d91a548 id:Vector<InputUser>
+            users::GetUsers::CONSTRUCTOR_ID => arg(&mut args)
+                .and_then(|ids: Vec<_>| {
+                    ids.into_iter()
+                        .map(|id| self.get_user(id).map(tl::enums::User::from))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .map(|users| users.to_bytes()),
+            _ => None,
+        };
+
+        let result = result.unwrap_or_else(|| {
+            warn!(
+                "The recording can't answer the request {:#010x}.",
+                constructor.unwrap_or_default()
+            );
+            tl::enums::RpcError::from(tl::types::RpcError {
+                error_code: 400,
+                error_message: UNSUPPORTED.to_string(),
+            })
+            .to_bytes()
+        });
+
+        // rpc_result#f35c6d01 req_msg_id:long result:Object
+        let mut body = RPC_RESULT.to_le_bytes().to_vec();
+        body.extend(msg_id.to_le_bytes());
+        body.extend(result);
+        body
+    }
+
+    /// Get the basic groups, or the placeholders of the ones not in the recording.
+    fn get_chats(&self, ids: Vec<i32>) -> tl::enums::messages::Chats {
+        let chats = ids
+            .into_iter()
+            .map(|id| {
+                self.chats
+                    .get(&PeerId::Chat(id))
+                    .cloned()
+                    .unwrap_or_else(|| group(id, id.to_string()))
+            })
+            .collect();
+
+        tl::types::messages::Chats { chats }.into()
+    }
+
+    /// Get the channels, or the placeholders of the ones not in the recording.
+    fn get_channels(&self, ids: Vec<tl::enums::InputChannel>) -> tl::enums::messages::Chats {
+        let chats = ids
+            .into_iter()
+            .filter_map(|input| match input {
+                tl::enums::InputChannel::Channel(input) => Some(input),
+                _ => None,
+            })
+            .map(|input| {
+                self.chats
+                    .get(&PeerId::Channel(input.channel_id))
+                    .cloned()
+                    .unwrap_or_else(|| {
+                        let id = input.channel_id;
+                        let mut placeholder = channel(id, Some(input.access_hash), id.to_string());
+                        placeholder.megagroup = true;
+                        placeholder.broadcast = false;
+                        placeholder.into()
+                    })
+            })
+            .collect();
+
+        tl::types::messages::Chats { chats }.into()
+    }
+
+    /// Get the messages by their IDs, in the channel or out of the channels.
+    fn get_messages(
+        &self,
+        channel: Option<i32>,
+        ids: &[tl::enums::InputMessage],
+    ) -> tl::enums::messages::Messages {
+        let in_scope = |message: &&tl::types::Message| match PeerId::of(&message.peer_id) {
+            PeerId::Channel(id) => channel == Some(id),
+            _ => channel.is_none(),
+        };
+        let find = |id: i32| self.messages.iter().filter(in_scope).find(|m| m.id == id);
+
+        let messages = ids
+            .iter()
+            .filter_map(|id| match id {
+                tl::enums::InputMessage::Id(id) => find(id.id),
+                tl::enums::InputMessage::ReplyTo(id) => find(id.id)
+                    .and_then(|message| message.reply_to.as_ref())
+                    .and_then(|tl::enums::MessageReplyHeader::Header(header)| {
+                        find(header.reply_to_msg_id)
+                    }),
+                _ => None,
+            })
+            .map(|message| message.clone().into())
+            .collect();
+
+        tl::types::messages::Messages {
+            messages,
+            chats: self.chats.values().cloned().collect(),
+            users: self.users.values().cloned().map(Into::into).collect(),
+        }
+        .into()
+    }
+
+    /// Get the recorded chats as the dialogs.
+    fn get_dialogs(&self) -> tl::enums::messages::Dialogs {
+        let dialogs = self
+            .dialogs
+            .iter()
+            .map(|(peer, &top_message)| {
+                tl::types::Dialog {
+                    pinned: false,
+                    unread_mark: false,
+                    peer: peer.to_peer(),
+                    top_message,
+                    read_inbox_max_id: top_message,
+                    read_outbox_max_id: top_message,
+                    unread_count: 0,
+                    unread_mentions_count: 0,
+                    notify_settings: tl::types::PeerNotifySettings {
+                        show_previews: None,
+                        silent: None,
+                        mute_until: None,
+                        sound: None,
+                    }
+                    .into(),
+                    pts: None,
+                    draft: None,
+                    folder_id: None,
+                }
+                .into()
+            })
+            .collect();
+
+        tl::types::messages::Dialogs {
+            dialogs,
+            messages: Vec::new(),
+            chats: self.chats.values().cloned().collect(),
+            users: self.users.values().cloned().map(Into::into).collect(),
+        }
+        .into()
+    }
+
+    /// Get the user, or a placeholder if the user is not in the recording.
+    ///
+    /// It returns `None` for ourselves if no outgoing message is recorded.
+    fn get_user(&self, input: tl::enums::InputUser) -> Option<tl::types::User> {
+        let id = match input {
+            tl::enums::InputUser::UserSelf => self.self_id?,
+            tl::enums::InputUser::User(input) => input.user_id,
+            tl::enums::InputUser::FromMessage(input) => input.user_id,
+            tl::enums::InputUser::Empty => return None,
+        };
+
+        Some(
+            self.users
+                .get(&id)
+                .cloned()
+                .unwrap_or_else(|| user(id, id.to_string())),
+        )
+    }
+}
+
+/// Parse the next argument of a request, or `None` if it is malformed.
+fn arg<T: Deserializable>(args: &mut Cursor) -> Option<T> {
+    T::deserialize(args).ok()
+}
+
+/// Rebuild the message of the record.
+fn message(record: &RecordedUpdate, peer: PeerId, from: Option<PeerId>) -> tl::types::Message {
+    tl::types::Message {
+        out: record.outgoing,
+        mentioned: false,
+        media_unread: false,
+        silent: false,
+        post: false,
+        from_scheduled: false,
+        legacy: false,
+        edit_hide: false,
+        pinned: false,
+        id: record.message_id,
+        from_id: from.map(PeerId::to_peer),
+        peer_id: peer.to_peer(),
+        fwd_from: None,
+        via_bot_id: None,
+        reply_to: record.reply_to_message_id.map(|reply_to_msg_id| {
+            tl::types::MessageReplyHeader {
+                reply_to_msg_id,
+                reply_to_peer_id: None,
+                reply_to_top_id: None,
+            }
+            .into()
+        }),
+        date: i32::try_from(record.date).unwrap_or_default(),
+        message: record.text.clone(),
+        media: None,
+        reply_markup: None,
+        entities: None,
+        views: None,
+        forwards: None,
+        replies: None,
+        edit_date: None,
+        post_author: None,
+        grouped_id: None,
+        restriction_reason: None,
+        ttl_period: None,
+    }
+}
+
+/// A user with the name.
+fn user(id: i32, name: String) -> tl::types::User {
+    tl::types::User {
+        is_self: false,
+        contact: false,
+        mutual_contact: false,
+        deleted: false,
+        bot: false,
+        bot_chat_history: false,
+        bot_nochats: false,
+        verified: false,
+        restricted: false,
+        min: false,
+        bot_inline_geo: false,
+        support: false,
+        scam: false,
+        apply_min_photo: false,
+        fake: false,
+        id,
+        access_hash: None,
+        first_name: Some(name),
+        last_name: None,
+        username: None,
+        phone: None,
+        photo: None,
+        status: None,
+        bot_info_version: None,
+        restriction_reason: None,
+        bot_inline_placeholder: None,
+        lang_code: None,
+    }
+}
+
+/// A basic group with the title.
+fn group(id: i32, title: String) -> tl::enums::Chat {
+    tl::types::Chat {
+        creator: false,
+        kicked: false,
+        left: false,
+        deactivated: false,
+        call_active: false,
+        call_not_empty: false,
+        id,
+        title,
+        photo: tl::enums::ChatPhoto::Empty,
+        participants_count: 0,
+        date: 0,
+        version: 0,
+        migrated_to: None,
+        admin_rights: None,
+        default_banned_rights: None,
+    }
+    .into()
+}
+
+/// A channel with the title, which is a broadcast channel by default.
+fn channel(id: i32, access_hash: Option<i64>, title: String) -> tl::types::Channel {
+    tl::types::Channel {
+        creator: false,
+        left: false,
+        broadcast: true,
+        verified: false,
+        megagroup: false,
+        restricted: false,
+        signatures: false,
+        min: false,
+        scam: false,
+        has_link: false,
+        has_geo: false,
+        slowmode_enabled: false,
+        call_active: false,
+        call_not_empty: false,
+        fake: false,
+        gigagroup: false,
+        id,
+        access_hash,
+        title,
+        username: None,
+        photo: tl::enums::ChatPhoto::Empty,
+        date: 0,
+        version: 0,
+        restriction_reason: None,
+        admin_rights: None,
+        banned_rights: None,
+        default_banned_rights: None,
+        participants_count: None,
+    }
+}
+
+/// The configuration answering `help.getConfig`.
+///
+/// `grammers_client` ignores it, so only its layout matters.
+fn config() -> tl::types::Config {
+    tl::types::Config {
+        phonecalls_enabled: false,
+        default_p2p_contacts: false,
+        preload_featured_stickers: false,
+        ignore_phone_entities: false,
+        revoke_pm_inbox: false,
+        blocked_mode: false,
+        pfs_enabled: false,
+        date: 0,
+        expires: 0,
+        test_mode: false,
+        this_dc: DEFAULT_DC,
+        dc_options: Vec::new(),
+        dc_txt_domain_name: String::new(),
+        chat_size_max: 0,
+        megagroup_size_max: 0,
+        forwarded_count_max: 0,
+        online_update_period_ms: 0,
+        offline_blur_timeout_ms: 0,
+        offline_idle_timeout_ms: 0,
+        online_cloud_timeout_ms: 0,
+        notify_cloud_delay_ms: 0,
+        notify_default_delay_ms: 0,
+        push_chat_period_ms: 0,
+        push_chat_limit: 0,
+        saved_gifs_limit: 0,
+        edit_time_limit: 0,
+        revoke_time_limit: 0,
+        revoke_pm_time_limit: 0,
+        rating_e_decay: 0,
+        stickers_recent_limit: 0,
+        stickers_faved_limit: 0,
+        channels_read_media_period: 0,
+        tmp_sessions: None,
+        pinned_dialogs_count_max: 0,
+        pinned_infolder_count_max: 0,
+        call_receive_timeout_ms: 0,
+        call_ring_timeout_ms: 0,
+        call_connect_timeout_ms: 0,
+        call_packet_timeout_ms: 0,
+        me_url_prefix: String::new(),
+        autoupdate_url_prefix: None,
+        gif_search_username: None,
+        venue_search_username: None,
+        img_search_username: None,
+        static_maps_provider: None,
+        caption_length_max: 0,
+        message_length_max: 0,
+        webfile_dc_id: 0,
+        suggested_lang_code: None,
+        lang_pack_version: None,
+        base_lang_pack_version: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grammers_client::client::chats::InvocationError;
+
+    use crate::telegram::recorder::read_recording;
+
+    use super::*;
+
+    /// The recording of the fixture.
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/recording.jsonl"
+    );
+
+    /// Connect a client to the server of the fixture.
+    async fn connect_fixture() -> (Client, Vec<RecordedUpdate>) {
+        let recording = read_recording(FIXTURE).expect("failed to read the fixture");
+        let addr = serve(&recording)
+            .await
+            .expect("failed to serve the fixture");
+        let client = connect(addr).await.expect("failed to connect");
+
+        (client, recording)
+    }
+
+    #[tokio::test]
+    async fn serves_the_recorded_messages() {
+        let (mut client, recording) = connect_fixture().await;
+
+        let group = recording[1].chat().unwrap();
+        let messages = client
+            .get_messages_by_id(&group, &[20, 22, 23])
+            .await
+            .expect("failed to get the messages");
+        let texts = messages
+            .iter()
+            .map(|message| message.as_ref().map(|message| message.text().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            [
+                Some("hello".to_string()),
+                Some("<redacted: 5 chars>".to_string()),
+                None
+            ]
+        );
+
+        let hello = messages[0].as_ref().unwrap();
+        let sender = hello.sender().unwrap();
+        assert_eq!((sender.id(), sender.name()), (1001, "Alice"));
+        assert_eq!(hello.chat().name(), "Test Group");
+        assert!(!hello.outgoing());
+
+        // The private chat with Alice, where we sent `!ping` and Alice replied.
+        let private = recording[0].chat().unwrap();
+        let messages = client
+            .get_messages_by_id(&private, &[10, 11])
+            .await
+            .expect("failed to get the messages");
+        let (ping, del) = (messages[0].as_ref().unwrap(), messages[1].as_ref().unwrap());
+        assert!(ping.outgoing());
+        assert!(crate::telegram::user::is_root_user(ping));
+        assert_eq!(del.reply_to_message_id(), Some(10));
+        assert_eq!(del.sender().unwrap().id(), 1001);
+    }
+
+    #[tokio::test]
+    async fn serves_the_recorded_chats() {
+        let (mut client, _) = connect_fixture().await;
+
+        let me = client.get_me().await.expect("failed to get ourselves");
+        assert_eq!(me.id(), 42);
+
+        let mut dialogs = client.iter_dialogs();
+        let mut ids = Vec::new();
+        while let Some(dialog) = dialogs.next().await.expect("failed to get the dialogs") {
+            ids.push(dialog.chat().id());
+        }
+        assert_eq!(ids, [1001, 1234]);
+
+        let placeholder = client
+            .unpack_chat(&placeholder_chat(777))
+            .await
+            .expect("failed to unpack the placeholder");
+        assert_eq!(placeholder.id(), 777);
+    }
+
+    #[tokio::test]
+    async fn rejects_the_other_requests() {
+        let (client, _) = connect_fixture().await;
+
+        match client.invoke(&tl::functions::updates::GetState {}).await {
+            Err(InvocationError::Rpc(e)) => assert_eq!(e.name, UNSUPPORTED),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
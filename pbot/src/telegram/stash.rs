@@ -0,0 +1,146 @@
+//! PBot: Telegram: Stash
+//!
+//! Keep the content of the messages, so the modules can send it again later.
+//!
+//! The text is stored along with its formatting entities in [`StashedText`].
+//! The media can't be stored by itself, so it is copied to Saved Messages,
+//! and only the reference to the copy is stored in [`MediaRef`]. The content
+//! keeps working even if the original message is deleted.
+
+use actix::Addr;
+use grammers_client::types::chat::PackedChat;
+use grammers_client::types::iter_buffer::InvocationError;
+use grammers_client::types::{Chat, Media};
+use grammers_tl_types::{self as tl, Deserializable, Serializable};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::client::{
+    commands::{DeleteMessagesCommand, GetMeCommand, GetMessagesCommand, SendMessageCommand},
+    outgoing::OutgoingMessage,
+    ClientActor,
+};
+use super::format::FormattedText;
+
+/// The text with its formatting, which can be serialized.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StashedText {
+    /// The plain text.
+    pub text: String,
+    /// The TL-serialized formatting entities of the text.
+    #[serde(default)]
+    pub entities: Vec<u8>,
+}
+
+impl StashedText {
+    /// Get the text with its formatting.
+    ///
+    /// The formatting is dropped if the entities are malformed.
+    pub fn formatted(&self) -> FormattedText {
+        let entities =
+            Vec::<tl::enums::MessageEntity>::from_bytes(&self.entities).unwrap_or_default();
+
+        FormattedText::with_entities(self.text.clone(), entities)
+    }
+}
+
+impl From<FormattedText> for StashedText {
+    fn from(text: FormattedText) -> Self {
+        Self {
+            text: text.text().to_string(),
+            entities: text.entities().to_vec().to_bytes(),
+        }
+    }
+}
+
+/// The reference to the copy of the media in Saved Messages.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaRef {
+    /// The serialized [`PackedChat`] where the copy is.
+    pub chat: Vec<u8>,
+    /// The ID of the message carrying the copy.
+    pub message_id: i32,
+}
+
+impl MediaRef {
+    /// Get the chat where the copy is.
+    pub fn chat(&self) -> Option<Chat> {
+        PackedChat::from_bytes(&self.chat)
+            .ok()
+            .map(|chat| chat.unpack())
+    }
+}
+
+/// Copy the media to Saved Messages with the caption, and get the reference to it.
+///
+/// It returns `None` if the client is in the dry-run mode, where nothing is copied.
+pub async fn stash_media(
+    client: &Addr<ClientActor>,
+    media: &Media,
+    caption: impl Into<FormattedText>,
+) -> anyhow::Result<Result<Option<MediaRef>, InvocationError>> {
+    let saved_messages = match client.send(GetMeCommand).await? {
+        Ok(me) => Chat::User(me),
+        Err(e) => return Ok(Err(e)),
+    };
+    let copy = client
+        .send(SendMessageCommand(
+            saved_messages.clone(),
+            OutgoingMessage::from(caption.into()).copy_media(media),
+        ))
+        .await?;
+
+    Ok(copy.map(|copy| {
+        copy.id().map(|message_id| MediaRef {
+            chat: saved_messages.pack().to_bytes(),
+            message_id,
+        })
+    }))
+}
+
+/// Get the media from the copy.
+///
+/// It returns `None` if the copy has been deleted.
+pub async fn fetch_media(
+    client: &Addr<ClientActor>,
+    media: &MediaRef,
+) -> anyhow::Result<Result<Option<Media>, InvocationError>> {
+    let chat = match media.chat() {
+        Some(chat) => chat,
+        None => return Ok(Ok(None)),
+    };
+    let messages = client
+        .send(GetMessagesCommand {
+            chat,
+            message_ids: vec![media.message_id],
+        })
+        .await?;
+
+    Ok(messages.map(|messages| {
+        messages
+            .into_iter()
+            .flatten()
+            .next()
+            .and_then(|copy| copy.media())
+    }))
+}
+
+/// Delete the copy of the media, which is no longer used.
+///
+/// It only logs the failure, since nothing depends on the copy anymore.
+pub async fn drop_media(client: &Addr<ClientActor>, media: &MediaRef) {
+    let chat = match media.chat() {
+        Some(chat) => chat,
+        None => return,
+    };
+
+    let result = client
+        .send(DeleteMessagesCommand {
+            chat,
+            message_ids: vec![media.message_id],
+        })
+        .await;
+    if !matches!(result, Ok(Ok(_))) {
+        warn!("Failed to delete the stashed media: {:?}", result);
+    }
+}
@@ -0,0 +1,357 @@
+//! PBot: Telegram: TL objects in JSON
+//!
+//! `grammers_tl_types` only derives [`Debug`] for the TL objects, so we
+//! convert their `Debug` representation into JSON, such as
+//! `Message(Message { id: 1, media: None })` into
+//! `{"_": "Message", "id": 1, "media": null}`.
+//!
+//! The structs become objects with their names in `"_"`, the variants
+//! wrapping a single value become the value itself, the unit variants
+//! become objects with only `"_"`, and `Some(x)` becomes `x`.
+
+use std::fmt::Debug;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use serde_json::{Map, Number, Value};
+
+/// Convert the `Debug` representation of the value into JSON.
+///
+/// It falls back to a JSON string of the `Debug` representation
+/// if it can't be parsed.
+pub fn to_json(value: &impl Debug) -> Value {
+    let debug = format!("{:?}", value);
+    let mut parser = Parser {
+        chars: debug.chars().peekable(),
+    };
+
+    match parser.value() {
+        Some(value) if parser.chars.next().is_none() => value,
+        _ => Value::String(debug),
+    }
+}
+
+/// The parser of the `Debug` representation.
+struct Parser<'a> {
+    /// The remaining characters.
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    /// Skip the whitespaces.
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some(c) if c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    /// Consume the character if it is the expected one.
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&expected).is_some()
+    }
+
+    /// Parse a value.
+    fn value(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+
+        match *self.chars.peek()? {
+            '"' => self.string().map(Value::String),
+            '[' => {
+                self.chars.next();
+                self.list(']').map(Value::Array)
+            }
+            c if c == '-' || c.is_ascii_digit() => self.number(),
+            c if c.is_alphabetic() || c == '_' => self.named(),
+            _ => None,
+        }
+    }
+
+    /// Parse a comma-separated list of values until the closing character.
+    fn list(&mut self, closing: char) -> Option<Vec<Value>> {
+        let mut values = Vec::new();
+
+        while !self.eat(closing) {
+            values.push(self.value()?);
+            if !self.eat(',') && !matches!(self.chars.peek(), Some(&c) if c == closing) {
+                return None;
+            }
+        }
+
+        Some(values)
+    }
+
+    /// Parse a string literal with the escapes of `Debug`.
+    fn string(&mut self) -> Option<String> {
+        self.chars.next();
+        let mut string = String::new();
+
+        loop {
+            match self.chars.next()? {
+                '"' => return Some(string),
+                '\\' => string.push(match self.chars.next()? {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    '0' => '\0',
+                    'u' => {
+                        if self.chars.next()? != '{' {
+                            return None;
+                        }
+                        let code = self
+                            .chars
+                            .by_ref()
+                            .take_while(|&c| c != '}')
+                            .collect::<String>();
+                        char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+                    }
+                    c => c,
+                }),
+                c => string.push(c),
+            }
+        }
+    }
+
+    /// Parse a number.
+    fn number(&mut self) -> Option<Value> {
+        let mut number = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.'))
+        {
+            number.push(c);
+        }
+
+        if let Ok(int) = number.parse::<i64>() {
+            return Some(Value::Number(int.into()));
+        }
+        if let Ok(uint) = number.parse::<u64>() {
+            return Some(Value::Number(uint.into()));
+        }
+        // The non-finite floats have no JSON representation.
+        Some(
+            Number::from_f64(number.parse().ok()?)
+                .map(Value::Number)
+                .unwrap_or(Value::Null),
+        )
+    }
+
+    /// Parse an identifier, and the struct or the tuple following it.
+    fn named(&mut self) -> Option<Value> {
+        let mut name = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_alphanumeric() || *c == '_' || *c == ':')
+        {
+            name.push(c);
+        }
+
+        if self.eat('{') {
+            let mut object = Map::new();
+            object.insert("_".to_string(), Value::String(name));
+
+            while !self.eat('}') {
+                self.skip_whitespace();
+                let mut field = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    field.push(c);
+                }
+                if field.is_empty() || !self.eat(':') {
+                    return None;
+                }
+
+                object.insert(field, self.value()?);
+                if !self.eat(',') && !matches!(self.chars.peek(), Some('}')) {
+                    return None;
+                }
+            }
+
+            return Some(Value::Object(object));
+        }
+
+        if self.eat('(') {
+            let mut values = self.list(')')?;
+
+            // The variants wrapping a single value, such as `Some(x)`
+            // and `Message(Message { .. })`, become the value itself.
+            return Some(if values.len() == 1 {
+                values.remove(0)
+            } else {
+                Value::Array(values)
+            });
+        }
+
+        Some(match name.as_str() {
+            "None" => Value::Null,
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "inf" | "NaN" => Value::Null,
+            _ => {
+                let mut object = Map::new();
+                object.insert("_".to_string(), Value::String(name));
+                Value::Object(object)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grammers_tl_types as tl;
+    use serde_json::{json, Value};
+
+    use super::to_json;
+
+    /// A message with a photo, entities and a reply header.
+    fn message() -> tl::enums::Message {
+        tl::types::Message {
+            out: true,
+            mentioned: false,
+            media_unread: false,
+            silent: false,
+            post: false,
+            from_scheduled: false,
+            legacy: false,
+            edit_hide: false,
+            pinned: false,
+            id: 42,
+            from_id: Some(tl::types::PeerUser { user_id: 1 }.into()),
+            peer_id: tl::types::PeerChannel { channel_id: 1001 }.into(),
+            fwd_from: None,
+            via_bot_id: None,
+            reply_to: Some(
+                tl::types::MessageReplyHeader {
+                    reply_to_msg_id: 41,
+                    reply_to_peer_id: None,
+                    reply_to_top_id: None,
+                }
+                .into(),
+            ),
+            date: 1_600_000_000,
+            message: "say \"hi\"\n👋".to_string(),
+            media: Some(
+                tl::types::MessageMediaPhoto {
+                    photo: Some(
+                        tl::types::Photo {
+                            has_stickers: false,
+                            id: -5_000_000_000,
+                            access_hash: 7,
+                            file_reference: vec![1, 2, 255],
+                            date: 1_600_000_000,
+                            sizes: vec![tl::types::PhotoSize {
+                                r#type: "m".to_string(),
+                                w: 320,
+                                h: 240,
+                                size: 1024,
+                            }
+                            .into()],
+                            video_sizes: None,
+                            dc_id: 5,
+                        }
+                        .into(),
+                    ),
+                    ttl_seconds: None,
+                }
+                .into(),
+            ),
+            reply_markup: None,
+            entities: Some(vec![
+                tl::types::MessageEntityBold {
+                    offset: 0,
+                    length: 3,
+                }
+                .into(),
+                tl::types::MessageEntityTextUrl {
+                    offset: 4,
+                    length: 5,
+                    url: "https://example.com".to_string(),
+                }
+                .into(),
+            ]),
+            views: None,
+            forwards: None,
+            replies: None,
+            edit_date: None,
+            post_author: None,
+            grouped_id: None,
+            restriction_reason: None,
+            ttl_period: None,
+        }
+        .into()
+    }
+
+    #[test]
+    fn message_with_media_entities_and_reply() {
+        let json = to_json(&message());
+
+        assert_eq!(json["_"], "Message");
+        assert_eq!(json["id"], 42);
+        assert_eq!(json["out"], true);
+        assert_eq!(json["message"], "say \"hi\"\n👋");
+        assert_eq!(json["views"], Value::Null);
+        assert_eq!(json["from_id"], json!({"_": "PeerUser", "user_id": 1}));
+        assert_eq!(
+            json["reply_to"],
+            json!({
+                "_": "MessageReplyHeader",
+                "reply_to_msg_id": 41,
+                "reply_to_peer_id": null,
+                "reply_to_top_id": null,
+            })
+        );
+        assert_eq!(
+            json["entities"],
+            json!([
+                {"_": "MessageEntityBold", "offset": 0, "length": 3},
+                {
+                    "_": "MessageEntityTextUrl",
+                    "offset": 4,
+                    "length": 5,
+                    "url": "https://example.com",
+                },
+            ])
+        );
+
+        let photo = &json["media"]["photo"];
+        assert_eq!(json["media"]["_"], "MessageMediaPhoto");
+        assert_eq!(photo["_"], "Photo");
+        assert_eq!(photo["id"], -5_000_000_000i64);
+        assert_eq!(photo["file_reference"], json!([1, 2, 255]));
+        assert_eq!(
+            photo["sizes"],
+            json!([{"_": "PhotoSize", "type": "m", "w": 320, "h": 240, "size": 1024}])
+        );
+    }
+
+    #[test]
+    fn floats_and_unit_variants() {
+        let geo: tl::enums::MessageMedia = tl::types::MessageMediaGeo {
+            geo: tl::types::GeoPoint {
+                long: 121.5,
+                lat: 25.03,
+                access_hash: 0,
+                accuracy_radius: None,
+            }
+            .into(),
+        }
+        .into();
+        let json = to_json(&geo);
+        assert_eq!(json["geo"]["long"], 121.5);
+        assert_eq!(json["geo"]["lat"], 25.03);
+
+        assert_eq!(to_json(&tl::enums::GeoPoint::Empty), json!({"_": "Empty"}));
+    }
+
+    #[test]
+    fn falls_back_to_the_debug_string() {
+        struct Odd;
+
+        impl std::fmt::Debug for Odd {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("<odd>")
+            }
+        }
+
+        assert_eq!(to_json(&Odd), Value::String("<odd>".to_string()));
+    }
+}
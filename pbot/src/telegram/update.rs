@@ -7,13 +7,15 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 
 use super::client::ClientActor;
+use super::user::is_root_user;
 
 use grammers_client::Update::NewMessage;
 
-use crate::modules::base::{ActivatedModuleInfo, ModuleMessage};
+use crate::modules::base::{response::ResponseOptions, ActivatedModuleInfo, ModuleMessage};
 
 /// The message for a ClientModule.
 ///
+/// It resolves once every module has handled the update.
 /// See main.rs > Phase V: Polling updates
 #[derive(Message)]
 #[rtype(result = "anyhow::Result<()>")]
@@ -31,6 +33,8 @@ pub struct ClientModuleExecutor {
     /// The first element is the module name;
     /// the second element is the recipient of [`ModuleMessage`].
     pub modules: Arc<Vec<ActivatedModuleInfo>>,
+    /// The options of the responses of modules.
+    pub response_options: Arc<ResponseOptions>,
 }
 
 impl Actor for ClientModuleExecutor {
@@ -53,6 +57,7 @@ impl Handler<ClientModuleMessage> for ClientModuleExecutor {
         // We clone the variables from self to workaround this error.
         let modules = self.modules.clone();
         let handle = self.client.clone();
+        let options = self.response_options.clone();
         let message = match msg.update {
             NewMessage(message) => Ok(message),
             _ => Err(anyhow::anyhow!("got a unhandled message")),
@@ -62,30 +67,64 @@ impl Handler<ClientModuleMessage> for ClientModuleExecutor {
             // Make a Arc smart pointer to the message
             // so we can share it with those modules.
             let message = Arc::new(RwLock::new(message?));
+            let mut handlers = Vec::with_capacity(modules.len());
 
             for module in modules.iter() {
                 // Clone some context that the following code will use.
                 let module = module.clone();
                 let message = message.clone();
                 let handle = handle.clone();
+                let options = options.clone();
 
-                tokio::spawn(async move {
+                handlers.push(tokio::spawn(async move {
                     // Forward our handle and message to the module.
                     //
                     // Note that we clone() twice - first to workaround the lifetime issue,
                     // this to let the every modules consume.
-                    let error = module
+                    let result = module
                         .recipient
-                        .send(ModuleMessage { handle, message })
+                        .send(ModuleMessage {
+                            handle: handle.clone(),
+                            message: message.clone(),
+                            module: module.name,
+                            options: options.clone(),
+                        })
                         .await
                         .unwrap();
 
                     // module.name is the module name;
-                    // e is the error from module.recipient.send().
-                    if let Err(e) = error {
+                    // e is the error returned by the module.
+                    if let Err(e) = result {
                         error!("error in {}: {:?}", module.name, e);
+
+                        // Only the owner invokes the commands, so the errors
+                        // of the passive modules on the messages of the others
+                        // are not posted into their chats.
+                        if !is_root_user(&*message.read().await) {
+                            return;
+                        }
+
+                        // Let the user who invoked the module know what happened.
+                        let text = e.render(options.error_details);
+                        let msg = ModuleMessage {
+                            handle,
+                            message,
+                            module: module.name,
+                            options,
+                        };
+
+                        if let Err(e) = msg.report_error(text).await {
+                            error!("failed to report the error of {}: {:?}", module.name, e);
+                        }
                     }
-                });
+                }));
+            }
+
+            // Wait for the modules, so replaying can handle the updates one by one.
+            for result in futures::future::join_all(handlers).await {
+                if let Err(e) = result {
+                    error!("a module panicked: {:?}", e);
+                }
             }
 
             Ok(())
@@ -82,7 +82,7 @@ pub async fn login(conf: LoginConfig) -> anyhow::Result<Client> {
         info!("user::login(): ✅ Authorized successfully!");
 
         /* Phase 3: Store this loggin session. */
-        client.session().save_to_file(&conf.session_path)?;
+        client.session().save_to_file(conf.session_path)?;
     } else {
         debug!("user::login(): ✅ Already authorized.");
     }
@@ -1,6 +1,7 @@
 //! PBot: Utilities
 //!
-//! This module contains some useful utilities, such as [`getenv`].
+//! This module contains some useful utilities, such as [`getenv`], [`getenv_opt`],
+//! [`getenv_pairs`], [`parse_duration`] and [`format_duration`].
 
 /// Get the environment value.
 #[macro_export]
@@ -14,3 +15,213 @@ macro_rules! getenv {
             .expect(concat!($envvar, " should be ", stringify!($type)))
     };
 }
+
+/// Get the environment value if it has been specified.
+///
+/// It returns `None` when the variable is not specified.
+#[macro_export]
+macro_rules! getenv_opt {
+    ($envvar:expr) => {
+        std::env::var($envvar).ok()
+    };
+    ($envvar:expr, $type:ty) => {
+        $crate::getenv_opt!($envvar).map(|v| {
+            v.parse::<$type>()
+                .expect(concat!($envvar, " should be ", stringify!($type)))
+        })
+    };
+}
+
+/// Get the environment value in the form of `key:value,key:value`.
+///
+/// It returns an empty list when the variable is not specified.
+/// The value is split at the last `:`, so the keys can contain `:`.
+///
+/// # Panics
+///
+/// Panics if any entry has no `:`.
+pub fn getenv_pairs(envvar: &str) -> Vec<(String, String)> {
+    parse_pairs(envvar, &std::env::var(envvar).unwrap_or_default())
+}
+
+/// Parse the value of the environment variable `envvar`
+/// in the form of `key:value,key:value`.
+///
+/// See [`getenv_pairs`] for the details.
+fn parse_pairs(envvar: &str, value: &str) -> Vec<(String, String)> {
+    value
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (key, value) = entry
+                .rsplit_once(':')
+                .unwrap_or_else(|| panic!("{} should be `key:value,key:value`", envvar));
+
+            (key.trim().to_string(), value.trim().to_string())
+        })
+        .collect()
+}
+
+/// Parse the duration in the form of `1d12h`, `30m` or `90s`.
+///
+/// The units are `w` (weeks), `d` (days), `h` (hours), `m` (minutes)
+/// and `s` (seconds). It returns `None` if the duration is malformed.
+pub fn parse_duration(text: &str) -> Option<std::time::Duration> {
+    let mut secs: u64 = 0;
+    let mut number = String::new();
+
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            'w' => 7 * 24 * 60 * 60,
+            'd' => 24 * 60 * 60,
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        let value: u64 = number.parse().ok()?;
+        secs = secs.checked_add(value.checked_mul(unit)?)?;
+        number.clear();
+    }
+
+    // Every number should have a unit.
+    if !number.is_empty() || text.is_empty() {
+        return None;
+    }
+
+    Some(std::time::Duration::from_secs(secs))
+}
+
+/// Format the duration in the largest units, such as `1 天 12 小時`.
+///
+/// It is for the user-facing text, so the units are in Chinese.
+pub fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    let parts = [
+        (secs / 86400, "天"),
+        (secs % 86400 / 3600, "小時"),
+        (secs % 3600 / 60, "分鐘"),
+        (secs % 60, "秒"),
+    ];
+
+    let formatted = parts
+        .iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{} {}", value, unit))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if formatted.is_empty() {
+        "0 秒".to_string()
+    } else {
+        formatted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn pairs(value: &str) -> Vec<(String, String)> {
+        parse_pairs("TG_TEST", value)
+    }
+
+    #[test]
+    fn pairs_are_trimmed_and_split_at_the_last_colon() {
+        assert_eq!(
+            pairs(" news : -100123 , a:b:c ,"),
+            [
+                ("news".to_string(), "-100123".to_string()),
+                ("a:b".to_string(), "c".to_string()),
+            ]
+        );
+        assert!(pairs("").is_empty());
+        assert!(pairs(" , ").is_empty());
+    }
+
+    #[test]
+    fn pairs_keep_empty_keys_and_values() {
+        assert_eq!(
+            pairs(":1,key:"),
+            [
+                (String::new(), "1".to_string()),
+                ("key".to_string(), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "TG_TEST should be `key:value,key:value`")]
+    fn pairs_panic_on_entries_without_colon() {
+        pairs("news:1,archive");
+    }
+
+    #[test]
+    fn durations_with_units() {
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(
+            parse_duration("1d12h"),
+            Some(Duration::from_secs(36 * 60 * 60))
+        );
+        assert_eq!(
+            parse_duration("1W1H"),
+            Some(Duration::from_secs((7 * 24 + 1) * 60 * 60))
+        );
+        // The same unit can be repeated, and the values add up.
+        assert_eq!(parse_duration("1m1m"), Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn durations_of_zero() {
+        assert_eq!(parse_duration("0s"), Some(Duration::ZERO));
+        assert_eq!(parse_duration("0d0h"), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn malformed_durations() {
+        for text in ["", "5", "1h30", "h", "1x", "-1h", "1.5h", "1h 30m", "１h"] {
+            assert_eq!(parse_duration(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn overflowing_durations() {
+        // The number itself overflows.
+        assert_eq!(parse_duration("18446744073709551616s"), None);
+        // The number overflows after multiplied by the unit.
+        assert_eq!(parse_duration("18446744073709551615m"), None);
+        // The sum overflows.
+        assert_eq!(parse_duration("18446744073709551615s1s"), None);
+        assert_eq!(
+            parse_duration("18446744073709551615s"),
+            Some(Duration::from_secs(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn format_durations() {
+        assert_eq!(format_duration(Duration::ZERO), "0 秒");
+        assert_eq!(format_duration(Duration::from_millis(999)), "0 秒");
+        assert_eq!(format_duration(Duration::from_secs(60)), "1 分鐘");
+        assert_eq!(
+            format_duration(Duration::from_secs(90061)),
+            "1 天 1 小時 1 分鐘 1 秒"
+        );
+        assert_eq!(
+            format_duration(Duration::from_secs(8 * 86400 + 30)),
+            "8 天 30 秒"
+        );
+        assert_eq!(
+            format_duration(Duration::from_secs(u64::MAX)),
+            "213503982334601 天 7 小時 15 秒"
+        );
+    }
+}
@@ -3,7 +3,10 @@
 import itertools, subprocess
 from typing import Iterable
 
-available_modules = ["fwdmod", "getinfomod", "addrankmod"]
+available_modules = [
+    "fwdmod", "getinfomod", "addrankmod", "autofwdmod", "afkmod", "notesmod",
+    "filtersmod", "purgemod", "remindersmod"
+]
 
 
 def cargo_check(modules: Iterable[str]) -> bool: